@@ -0,0 +1,8 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.12;
+
+interface ITrap {
+    function collect() external view returns (bytes memory);
+
+    function shouldRespond(bytes[] calldata data) external pure returns (bool, bytes memory);
+}
@@ -0,0 +1,541 @@
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::{json, Map, Value};
+
+use crate::address_book;
+use crate::protocol::AbiParam;
+use crate::signature::to_hex;
+
+// Standard Solidity ABI decoding and encoding against typed parameters.
+// Decoding is strict (dirty padding and out-of-range values are errors) so a
+// wrong candidate signature fails instead of producing garbage.
+//
+// JSON representation: integers are decimal strings, addresses are
+// checksummed, bytes are 0x-hex, tuples are objects keyed by member name
+// (arrays when a member is unnamed). Encoding accepts the same, plus JSON
+// numbers and 0x-hex for integers and tuples given as arrays.
+
+pub fn parse_hex(input: &str) -> Result<Vec<u8>> {
+    let hex = input.trim();
+    let hex = hex.strip_prefix("0x").or_else(|| hex.strip_prefix("0X")).unwrap_or(hex);
+    if !hex.is_ascii() {
+        bail!("'{}' is not hex", input);
+    }
+    if !hex.len().is_multiple_of(2) {
+        bail!("'{}' has an odd number of hex digits", input);
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| anyhow!("'{}' is not hex", input)))
+        .collect()
+}
+
+// Decodes `data` as the tuple of `params`
+pub fn decode(params: &[AbiParam], data: &[u8]) -> Result<Vec<Value>> {
+    let types: Vec<(&str, &[AbiParam])> = params.iter().map(|p| (p.ty.as_str(), p.components.as_slice())).collect();
+    decode_tuple(&types, data, 0)
+}
+
+// Encodes `values` as the tuple of `params`
+pub fn encode(params: &[AbiParam], values: &[Value]) -> Result<Vec<u8>> {
+    if params.len() != values.len() {
+        bail!("expected {} values, got {}", params.len(), values.len());
+    }
+    let types: Vec<(&str, &[AbiParam])> = params.iter().map(|p| (p.ty.as_str(), p.components.as_slice())).collect();
+    let values: Vec<&Value> = values.iter().collect();
+    encode_tuple(&types, &values)
+}
+
+// Decodes a single 32-byte word holding a value type (an indexed event topic)
+pub fn decode_word(param: &AbiParam, word: &[u8]) -> Result<Value> {
+    if word.len() != 32 {
+        bail!("expected 32 bytes, got {}", word.len());
+    }
+    if is_hashed_when_indexed(param) {
+        bail!("{} is not a value type", param.ty);
+    }
+    decode_elementary(&param.ty, word)
+}
+
+// Indexed strings, bytes, arrays and structs are stored as the keccak256 of their encoding
+pub fn is_hashed_when_indexed(param: &AbiParam) -> bool {
+    param.ty == "string" || param.ty == "bytes" || param.ty.ends_with(']') || param.ty.starts_with("tuple")
+}
+
+fn is_dynamic(ty: &str, components: &[AbiParam]) -> bool {
+    if let Some((inner, len)) = split_array(ty) {
+        return len.is_none() || is_dynamic(inner, components);
+    }
+    match ty {
+        "string" | "bytes" => true,
+        "tuple" => components.iter().any(|c| is_dynamic(&c.ty, &c.components)),
+        _ => false,
+    }
+}
+
+// Size of a static type's encoding in the head
+fn head_size(ty: &str, components: &[AbiParam]) -> usize {
+    if let Some((inner, Some(len))) = split_array(ty) {
+        return len * head_size(inner, components);
+    }
+    if ty == "tuple" {
+        return components.iter().map(|c| head_size(&c.ty, &c.components)).sum();
+    }
+    32
+}
+
+// `uint256[2][]` -> (`uint256[2]`, None); `uint256[2]` -> (`uint256`, Some(2))
+fn split_array(ty: &str) -> Option<(&str, Option<usize>)> {
+    let inner_end = ty.strip_suffix(']')?.rfind('[')?;
+    let len = &ty[inner_end + 1..ty.len() - 1];
+    Some((&ty[..inner_end], if len.is_empty() { None } else { len.parse().ok() }))
+}
+
+fn decode_tuple(types: &[(&str, &[AbiParam])], data: &[u8], start: usize) -> Result<Vec<Value>> {
+    let mut values = Vec::with_capacity(types.len());
+    let mut head = start;
+    for (ty, components) in types {
+        if is_dynamic(ty, components) {
+            let offset = read_usize(data, head)?;
+            let position = start.checked_add(offset).context("offset overflows")?;
+            values.push(decode_value(ty, components, data, position)?);
+            head += 32;
+        } else {
+            values.push(decode_value(ty, components, data, head)?);
+            head += head_size(ty, components);
+        }
+    }
+    Ok(values)
+}
+
+fn decode_value(ty: &str, components: &[AbiParam], data: &[u8], position: usize) -> Result<Value> {
+    if let Some((inner, len)) = split_array(ty) {
+        let (len, start) = match len {
+            Some(len) => (len, position),
+            None => (read_usize(data, position)?, position.saturating_add(32)),
+        };
+        // Every element takes at least one word, which bounds hostile lengths
+        if len > data.len().saturating_sub(start) / 32 + 1 {
+            bail!("array length {} exceeds the data", len);
+        }
+        let types = vec![(inner, components); len];
+        return Ok(Value::Array(decode_tuple(&types, data, start)?));
+    }
+
+    match ty {
+        "tuple" => {
+            let types: Vec<(&str, &[AbiParam])> =
+                components.iter().map(|c| (c.ty.as_str(), c.components.as_slice())).collect();
+            let values = decode_tuple(&types, data, position)?;
+            Ok(tuple_value(components, values))
+        }
+        "bytes" | "string" => {
+            let len = read_usize(data, position)?;
+            let bytes = data
+                .get(position.saturating_add(32)..)
+                .and_then(|rest| rest.get(..len))
+                .with_context(|| format!("{} of length {} exceeds the data", ty, len))?;
+            if ty == "string" {
+                Ok(Value::String(String::from_utf8_lossy(bytes).into_owned()))
+            } else {
+                Ok(Value::String(to_hex(bytes)))
+            }
+        }
+        _ => decode_elementary(ty, word_at(data, position)?),
+    }
+}
+
+fn encode_tuple(types: &[(&str, &[AbiParam])], values: &[&Value]) -> Result<Vec<u8>> {
+    let head_len: usize = types.iter().map(|(ty, components)| if is_dynamic(ty, components) { 32 } else { head_size(ty, components) }).sum();
+    let mut head = Vec::with_capacity(head_len);
+    let mut tail = Vec::new();
+    for ((ty, components), value) in types.iter().zip(values) {
+        let encoded = encode_value(ty, components, value)?;
+        if is_dynamic(ty, components) {
+            head.extend_from_slice(&usize_word(head_len + tail.len()));
+            tail.extend(encoded);
+        } else {
+            head.extend(encoded);
+        }
+    }
+    head.extend(tail);
+    Ok(head)
+}
+
+fn encode_value(ty: &str, components: &[AbiParam], value: &Value) -> Result<Vec<u8>> {
+    if let Some((inner, len)) = split_array(ty) {
+        let elements = value.as_array().with_context(|| format!("{} value must be an array", ty))?;
+        if len.is_some_and(|len| len != elements.len()) {
+            bail!("{} value has {} elements", ty, elements.len());
+        }
+        let types = vec![(inner, components); elements.len()];
+        let elements: Vec<&Value> = elements.iter().collect();
+        let encoded = encode_tuple(&types, &elements)?;
+        return Ok(match len {
+            Some(_) => encoded,
+            None => [usize_word(elements.len()).to_vec(), encoded].concat(),
+        });
+    }
+
+    match ty {
+        "tuple" => {
+            let members: Vec<&Value> = match value {
+                Value::Array(members) if members.len() == components.len() => members.iter().collect(),
+                Value::Object(object) if components.iter().all(|c| !c.name.is_empty()) => components
+                    .iter()
+                    .map(|c| object.get(&c.name).with_context(|| format!("tuple value is missing member '{}'", c.name)))
+                    .collect::<Result<_>>()?,
+                _ => bail!("tuple value must be an array of {} members or an object keyed by member name", components.len()),
+            };
+            let types: Vec<(&str, &[AbiParam])> = components.iter().map(|c| (c.ty.as_str(), c.components.as_slice())).collect();
+            encode_tuple(&types, &members)
+        }
+        "bytes" | "string" => {
+            let text = value.as_str().with_context(|| format!("{} value must be a string", ty))?;
+            let bytes = if ty == "string" { text.as_bytes().to_vec() } else { parse_hex(text)? };
+            let mut encoded = usize_word(bytes.len()).to_vec();
+            encoded.extend_from_slice(&bytes);
+            encoded.resize(32 + bytes.len().div_ceil(32) * 32, 0);
+            Ok(encoded)
+        }
+        _ => {
+            let word = encode_elementary(ty, value)?;
+            // Decoding the word back applies the same range and padding checks
+            decode_elementary(ty, &word).with_context(|| format!("{} is out of range for {}", value, ty))?;
+            Ok(word.to_vec())
+        }
+    }
+}
+
+fn encode_elementary(ty: &str, value: &Value) -> Result<[u8; 32]> {
+    let mut word = [0u8; 32];
+    let text = match value {
+        Value::String(text) => text.trim().to_string(),
+        Value::Number(number) if number.is_u64() || number.is_i64() => number.to_string(),
+        Value::Bool(flag) if ty == "bool" => flag.to_string(),
+        _ => bail!("{} value must be a string{}", ty, if ty.contains("int") { " or an integer" } else { "" }),
+    };
+    match ty {
+        "address" | "function" => {
+            let bytes = parse_hex(&text)?;
+            let size = if ty == "address" { 20 } else { 24 };
+            if bytes.len() != size {
+                bail!("'{}' is not a {}-byte {}", text, size, ty);
+            }
+            let start = if ty == "address" { 12 } else { 0 };
+            word[start..start + size].copy_from_slice(&bytes);
+        }
+        "bool" => match text.as_str() {
+            "true" => word[31] = 1,
+            "false" => {}
+            _ => bail!("'{}' is not a bool", text),
+        },
+        _ if ty.starts_with("uint") || ty.starts_with("int") => {
+            let (negative, digits) = match text.strip_prefix('-') {
+                Some(digits) if ty.starts_with("int") => (true, digits),
+                _ => (false, text.as_str()),
+            };
+            word = parse_uint(digits).with_context(|| format!("'{}' is not a {}", text, ty))?;
+            if ty.starts_with("int") {
+                ensure_int_magnitude(ty, &word, negative).with_context(|| format!("{} is out of range for {}", text, ty))?;
+            }
+            if negative {
+                word = negate(&word).try_into().expect("32 bytes");
+            }
+        }
+        _ if ty.starts_with("bytes") => {
+            let bytes = parse_hex(&text)?;
+            let size: usize = ty["bytes".len()..].parse().with_context(|| format!("unsupported type {}", ty))?;
+            if bytes.len() != size {
+                bail!("'{}' is not {} bytes", text, size);
+            }
+            word[..size].copy_from_slice(&bytes);
+        }
+        _ => bail!("unsupported type {}", ty),
+    }
+    Ok(word)
+}
+
+// Decimal or 0x-hex unsigned integer as a 256-bit big-endian word
+fn parse_uint(text: &str) -> Result<[u8; 32]> {
+    let mut word = [0u8; 32];
+    if text.starts_with("0x") || text.starts_with("0X") {
+        let bytes = parse_hex(text)?;
+        let significant = &bytes[bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len())..];
+        if significant.len() > 32 {
+            bail!("more than 256 bits");
+        }
+        word[32 - significant.len()..].copy_from_slice(significant);
+        return Ok(word);
+    }
+    if text.is_empty() || !text.bytes().all(|b| b.is_ascii_digit()) {
+        bail!("not a decimal integer");
+    }
+    for digit in text.bytes().map(|b| (b - b'0') as u32) {
+        let mut carry = digit;
+        for byte in word.iter_mut().rev() {
+            let value = *byte as u32 * 10 + carry;
+            *byte = value as u8;
+            carry = value >> 8;
+        }
+        if carry != 0 {
+            bail!("more than 256 bits");
+        }
+    }
+    Ok(word)
+}
+
+fn usize_word(value: usize) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    word[24..].copy_from_slice(&(value as u64).to_be_bytes());
+    word
+}
+
+fn tuple_value(components: &[AbiParam], values: Vec<Value>) -> Value {
+    if components.iter().all(|c| !c.name.is_empty()) {
+        let object: Map<String, Value> = components.iter().map(|c| c.name.clone()).zip(values).collect();
+        Value::Object(object)
+    } else {
+        Value::Array(values)
+    }
+}
+
+fn decode_elementary(ty: &str, word: &[u8]) -> Result<Value> {
+    match ty {
+        "address" => {
+            ensure_zero(&word[..12], ty)?;
+            let address = address_book::to_checksum_address(&to_hex(&word[12..])).expect("20 bytes");
+            Ok(Value::String(address))
+        }
+        "bool" => match word {
+            [zeros @ .., last] if zeros.iter().all(|b| *b == 0) && *last <= 1 => Ok(json!(*last == 1)),
+            _ => bail!("invalid bool"),
+        },
+        "function" => {
+            ensure_zero(&word[24..], ty)?;
+            Ok(Value::String(to_hex(&word[..24])))
+        }
+        _ => {
+            if let Some(bits) = ty.strip_prefix("uint") {
+                let bits = int_bits(bits, ty)?;
+                ensure_zero(&word[..32 - bits / 8], ty)?;
+                Ok(Value::String(to_decimal(word)))
+            } else if let Some(bits) = ty.strip_prefix("int") {
+                let bits = int_bits(bits, ty)?;
+                let negative = word[32 - bits / 8] & 0x80 != 0;
+                let extension = if negative { 0xff } else { 0 };
+                if word[..32 - bits / 8].iter().any(|b| *b != extension) {
+                    bail!("{} value out of range", ty);
+                }
+                if negative {
+                    Ok(Value::String(format!("-{}", to_decimal(&negate(word)))))
+                } else {
+                    Ok(Value::String(to_decimal(word)))
+                }
+            } else if let Some(size) = ty.strip_prefix("bytes") {
+                let size: usize = size.parse().ok().filter(|n| (1..=32).contains(n)).with_context(|| format!("unsupported type {}", ty))?;
+                ensure_zero(&word[size..], ty)?;
+                Ok(Value::String(to_hex(&word[..size])))
+            } else {
+                bail!("unsupported type {}", ty)
+            }
+        }
+    }
+}
+
+fn int_bits(bits: &str, ty: &str) -> Result<usize> {
+    if bits.is_empty() {
+        return Ok(256);
+    }
+    bits.parse()
+        .ok()
+        .filter(|n: &usize| n.is_multiple_of(8) && (8..=256).contains(n))
+        .with_context(|| format!("unsupported type {}", ty))
+}
+
+// A signed integer's magnitude is at most 2^(bits-1) - 1, or 2^(bits-1) when
+// negative. Checked before negating: at 256 bits there are no sign-extension
+// bytes left to catch an overflow afterwards.
+pub fn ensure_int_magnitude(ty: &str, magnitude: &[u8; 32], negative: bool) -> Result<()> {
+    let bits = int_bits(ty.strip_prefix("int").unwrap_or(ty), ty)?;
+    let mut limit = [0u8; 32];
+    limit[31 - (bits - 1) / 8] = 1 << ((bits - 1) % 8);
+    if magnitude.as_slice() < limit.as_slice() || (negative && *magnitude == limit) {
+        return Ok(());
+    }
+    bail!("{} value out of range", ty)
+}
+
+fn ensure_zero(padding: &[u8], ty: &str) -> Result<()> {
+    if padding.iter().any(|b| *b != 0) {
+        bail!("{} value has dirty padding", ty);
+    }
+    Ok(())
+}
+
+fn word_at(data: &[u8], position: usize) -> Result<&[u8]> {
+    data.get(position..position.saturating_add(32))
+        .filter(|word| word.len() == 32)
+        .with_context(|| format!("data too short: need 32 bytes at offset {}", position))
+}
+
+fn read_usize(data: &[u8], position: usize) -> Result<usize> {
+    let word = word_at(data, position)?;
+    if word[..24].iter().any(|b| *b != 0) {
+        bail!("offset or length at {} is too large", position);
+    }
+    Ok(u64::from_be_bytes(word[24..].try_into().expect("8 bytes")) as usize)
+}
+
+// Two's complement negation of a 256-bit big-endian word
+fn negate(word: &[u8]) -> Vec<u8> {
+    let mut out: Vec<u8> = word.iter().map(|b| !b).collect();
+    for byte in out.iter_mut().rev() {
+        let (sum, overflow) = byte.overflowing_add(1);
+        *byte = sum;
+        if !overflow {
+            break;
+        }
+    }
+    out
+}
+
+// Big-endian unsigned integer to decimal, by repeated division by 10
+pub fn to_decimal(word: &[u8]) -> String {
+    let mut number = word.to_vec();
+    let mut digits = Vec::new();
+    while number.iter().any(|b| *b != 0) {
+        let mut remainder = 0u32;
+        for byte in number.iter_mut() {
+            let value = (remainder << 8) | *byte as u32;
+            *byte = (value / 10) as u8;
+            remainder = value % 10;
+        }
+        digits.push(b'0' + remainder as u8);
+    }
+    if digits.is_empty() {
+        return "0".to_string();
+    }
+    digits.reverse();
+    String::from_utf8(digits).expect("ascii digits")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signature::{parse_types, StructDefs};
+
+    const INT256_MAX: &str = "57896044618658097711785492504343953926634992332820282019728792003956564819967";
+    const INT256_MIN: &str = "-57896044618658097711785492504343953926634992332820282019728792003956564819968";
+    const UINT256_MAX: &str = "115792089237316195423570985008687907853269984665640564039457584007913129639935";
+
+    fn params(types: &str) -> Vec<AbiParam> {
+        parse_types(types, &StructDefs::new(&[])).expect("valid types")
+    }
+
+    fn encode_hex(types: &str, values: Value) -> Result<String> {
+        let values = values.as_array().expect("values array").clone();
+        Ok(to_hex(&encode(&params(types), &values)?))
+    }
+
+    fn round_trip(types: &str, values: Value) -> Value {
+        let params = params(types);
+        let encoded = encode(&params, values.as_array().expect("values array")).expect("encodes");
+        Value::Array(decode(&params, &encoded).expect("decodes"))
+    }
+
+    fn word(hex: &str) -> String {
+        format!("{:0>64}", hex)
+    }
+
+    #[test]
+    fn elementary_round_trips() {
+        let values = json!([
+            "0x6B175474E89094C44Da98b954EedeAC495271d0F",
+            true,
+            "1000000000000000000",
+            "-1",
+            "0xdeadbeef",
+            "0x00000000000000000000000000000000000000000000000000000000000000ff",
+        ]);
+        let decoded = round_trip("address,bool,uint256,int24,bytes4,bytes32", values.clone());
+        assert_eq!(decoded, values);
+    }
+
+    #[test]
+    fn integers_encode_as_twos_complement_words() {
+        assert_eq!(encode_hex("uint8", json!([255])).unwrap(), format!("0x{}", word("ff")));
+        assert_eq!(encode_hex("uint256", json!(["0x10"])).unwrap(), format!("0x{}", word("10")));
+        assert_eq!(encode_hex("int8", json!([-128])).unwrap(), format!("0x{}", "ff".repeat(31) + "80"));
+        assert_eq!(encode_hex("int256", json!(["-1"])).unwrap(), format!("0x{}", "ff".repeat(32)));
+    }
+
+    #[test]
+    fn uint_bounds() {
+        assert_eq!(round_trip("uint256", json!([UINT256_MAX])), json!([UINT256_MAX]));
+        assert!(encode_hex("uint256", json!(["115792089237316195423570985008687907853269984665640564039457584007913129639936"])).is_err());
+        assert_eq!(round_trip("uint8", json!(["255"])), json!(["255"]));
+        assert!(encode_hex("uint8", json!(["256"])).is_err());
+        assert!(encode_hex("uint256", json!(["-1"])).is_err());
+    }
+
+    #[test]
+    fn int_bounds() {
+        assert_eq!(round_trip("int256", json!([INT256_MAX])), json!([INT256_MAX]));
+        assert_eq!(round_trip("int256", json!([INT256_MIN])), json!([INT256_MIN]));
+        assert_eq!(encode_hex("int256", json!([INT256_MIN])).unwrap(), format!("0x80{}", "00".repeat(31)));
+        // 2^255 and -(2^255 + 1) would wrap to the opposite sign
+        assert!(encode_hex("int256", json!(["57896044618658097711785492504343953926634992332820282019728792003956564819968"])).is_err());
+        assert!(encode_hex("int256", json!(["-57896044618658097711785492504343953926634992332820282019728792003956564819969"])).is_err());
+
+        assert_eq!(round_trip("int8", json!(["127"])), json!(["127"]));
+        assert_eq!(round_trip("int8", json!(["-128"])), json!(["-128"]));
+        assert!(encode_hex("int8", json!(["128"])).is_err());
+        assert!(encode_hex("int8", json!(["-129"])).is_err());
+    }
+
+    #[test]
+    fn decoding_rejects_out_of_range_words() {
+        let dirty_uint8 = parse_hex(&word("100")).unwrap();
+        assert!(decode(&params("uint8"), &dirty_uint8).is_err());
+        let bad_sign_extension = parse_hex(&word("80")).unwrap();
+        assert!(decode(&params("int8"), &bad_sign_extension).is_err());
+        let dirty_address = parse_hex(&format!("01{}", "00".repeat(31))).unwrap();
+        assert!(decode(&params("address"), &dirty_address).is_err());
+        assert!(decode(&params("bool"), &parse_hex(&word("2")).unwrap()).is_err());
+    }
+
+    #[test]
+    fn dynamic_arrays() {
+        let encoded = encode_hex("uint256[]", json!([["1", "2"]])).unwrap();
+        assert_eq!(encoded, format!("0x{}{}{}{}", word("20"), word("2"), word("1"), word("2")));
+        assert_eq!(round_trip("uint256[]", json!([[]])), json!([[]]));
+        let strings = json!([["drosera", ""], "0xc0ffee"]);
+        assert_eq!(round_trip("string[],bytes", strings.clone()), strings);
+        let nested = json!([[["1", "2"], ["3", "4"]]]);
+        assert_eq!(round_trip("uint8[2][]", nested.clone()), nested);
+        assert!(encode_hex("uint8[2]", json!([["1"]])).is_err());
+    }
+
+    #[test]
+    fn tuples() {
+        // Unnamed members decode as arrays; objects are accepted when members are named
+        let unnamed = json!([["0x6B175474E89094C44Da98b954EedeAC495271d0F", "7", "drosera"]]);
+        assert_eq!(round_trip("(address,uint256,string)", unnamed.clone()), unnamed);
+
+        let mut named = params("(uint256,bytes)[]");
+        let components = &mut named[0].components;
+        components[0].name = "amount".to_string();
+        components[1].name = "data".to_string();
+        let values = vec![json!([{ "amount": "1", "data": "0x01" }, { "amount": "2", "data": "0x" }])];
+        let encoded = encode(&named, &values).unwrap();
+        assert_eq!(decode(&named, &encoded).unwrap(), values);
+        assert!(encode(&named, &[json!([{ "amount": "1" }])]).is_err());
+    }
+
+    #[test]
+    fn decoding_rejects_truncated_data() {
+        let encoded = parse_hex(&encode_hex("string", json!(["drosera"])).unwrap()).unwrap();
+        assert!(decode(&params("string"), &encoded[..encoded.len() - 32]).is_err());
+        assert!(decode(&params("uint256"), &[0u8; 31]).is_err());
+    }
+}
@@ -0,0 +1,126 @@
+use serde::Serialize;
+
+use crate::protocol::Protocol;
+use crate::signature::keccak256;
+
+// Normalized view of every address a protocol declares: misc_data contracts,
+// tokens and pools, plus the optional addresses.json deployments.
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AddressBookEntry {
+    pub protocol: String,
+    pub network: String,
+    pub role: String,
+    /// contract, token or pool
+    pub kind: &'static str,
+    /// EIP-55 checksummed form (the source value when it is not a valid address)
+    pub address: String,
+    /// Whether the data file already spells the address with a valid checksum
+    pub checksummed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deployment_block: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub abi: Option<String>,
+}
+
+// EIP-55 mixed-case checksum, or None when the input is not a 20-byte hex address
+pub fn to_checksum_address(address: &str) -> Option<String> {
+    let hex = address.strip_prefix("0x").or_else(|| address.strip_prefix("0X"))?;
+    if hex.len() != 40 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let lower = hex.to_ascii_lowercase();
+    let hash = keccak256(lower.as_bytes());
+    let mut checksummed = String::from("0x");
+    for (i, c) in lower.chars().enumerate() {
+        let nibble = (hash[i / 2] >> (if i % 2 == 0 { 4 } else { 0 })) & 0x0f;
+        if c.is_ascii_alphabetic() && nibble >= 8 {
+            checksummed.push(c.to_ascii_uppercase());
+        } else {
+            checksummed.push(c);
+        }
+    }
+    Some(checksummed)
+}
+
+pub fn is_checksummed(address: &str) -> bool {
+    to_checksum_address(address).as_deref() == Some(address)
+}
+
+pub fn entries(protocol_name: &str, protocol: &Protocol, network: &str) -> Vec<AddressBookEntry> {
+    let mut entries = Vec::new();
+    let mut push = |role: &str, kind: &'static str, address: &str, abi: Option<&String>, deployment_block: Option<u64>| {
+        entries.push(AddressBookEntry {
+            protocol: protocol_name.to_string(),
+            network: network.to_string(),
+            role: role.to_string(),
+            kind,
+            address: to_checksum_address(address).unwrap_or_else(|| address.to_string()),
+            checksummed: is_checksummed(address),
+            deployment_block,
+            abi: abi.cloned(),
+        });
+    };
+
+    if let Some(network_data) = protocol.networks.get(network) {
+        for (role, contract) in &network_data.contracts {
+            push(role, "contract", &contract.address, contract.abi.as_ref(), contract.deployment_block);
+        }
+        for (symbol, address) in &network_data.tokens {
+            push(symbol, "token", address, None, None);
+        }
+        for (name, pool) in &network_data.popular_pools {
+            push(name, "pool", &pool.address, pool.abi.as_ref(), None);
+        }
+    }
+    for record in protocol.addresses.get(network).into_iter().flatten() {
+        push(&record.role, "contract", &record.address, record.abi.as_ref(), record.deployment_block);
+    }
+
+    // addresses.json may repeat a misc_data contract to add its deployment block
+    let mut merged: Vec<AddressBookEntry> = Vec::new();
+    for entry in entries {
+        match merged.iter_mut().find(|e| e.role == entry.role && e.address.eq_ignore_ascii_case(&entry.address)) {
+            Some(existing) => {
+                existing.deployment_block = existing.deployment_block.or(entry.deployment_block);
+                existing.abi = existing.abi.take().or(entry.abi);
+                existing.checksummed &= entry.checksummed;
+            }
+            None => merged.push(entry),
+        }
+    }
+    merged
+}
+
+// Networks a protocol has addresses for
+pub fn networks(protocol: &Protocol) -> Vec<String> {
+    let mut networks: Vec<String> = protocol.networks.keys().chain(protocol.addresses.keys()).cloned().collect();
+    networks.sort();
+    networks.dedup();
+    networks
+}
+
+// 0x-prefixed 40-hex-digit literals in source code, with 1-based line numbers
+pub fn scan_addresses(source: &str) -> Vec<(usize, String)> {
+    let mut found = Vec::new();
+    for (line_index, line) in source.lines().enumerate() {
+        let bytes = line.as_bytes();
+        let mut i = 0;
+        while i + 1 < bytes.len() {
+            let starts_literal = bytes[i] == b'0'
+                && bytes[i + 1] == b'x'
+                && (i == 0 || !bytes[i - 1].is_ascii_alphanumeric());
+            if !starts_literal {
+                i += 1;
+                continue;
+            }
+            let digits = bytes[i + 2..].iter().take_while(|b| b.is_ascii_hexdigit()).count();
+            if digits == 40 {
+                found.push((line_index + 1, line[i..i + 42].to_string()));
+            }
+            i += 2 + digits;
+        }
+    }
+    found
+}
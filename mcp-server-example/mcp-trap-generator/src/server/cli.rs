@@ -0,0 +1,147 @@
+use anyhow::{bail, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::protocol::{self, DataFileKind};
+use crate::protocol_import::ImportOptions;
+
+const USAGE: &str = "Usage:
+  mcp-server [--strict]                 Run the MCP server on stdio
+  mcp-server validate-data [DATA_DIR]   Validate protocol data files (default: src/data)
+  mcp-server schema <abi|misc_data|addresses|storage_layout>
+                                        Print the JSON Schema for a protocol data file kind
+  mcp-server import-protocol <PROJECT_DIR> <PROTOCOL> [--network NAME] [--contracts A,B] [--data-dir DIR]
+                                        Write protocols/<PROTOCOL> from a Foundry project (out/ and
+                                        broadcast/) or hardhat-deploy deployments/";
+
+pub enum Command {
+    Serve { strict: bool },
+    ValidateData { data_dir: PathBuf },
+    Schema { kind: DataFileKind },
+    ImportProtocol(ImportOptions),
+}
+
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command> {
+    let args: Vec<String> = args.into_iter().collect();
+
+    match args.first().map(String::as_str) {
+        Some("validate-data") => {
+            let data_dir = args.get(1).map(PathBuf::from).unwrap_or_else(|| PathBuf::from("src/data"));
+            Ok(Command::ValidateData { data_dir })
+        }
+        Some("schema") => {
+            let kind = args.get(1).and_then(|name| DataFileKind::parse(name));
+            match kind {
+                Some(kind) => Ok(Command::Schema { kind }),
+                None => bail!("schema expects one of: abi, misc_data, addresses, storage_layout\n\n{}", USAGE),
+            }
+        }
+        Some("import-protocol") => parse_import_args(&args[1..]).map(Command::ImportProtocol),
+        Some("--help") | Some("-h") => bail!("{}", USAGE),
+        _ => {
+            let mut strict = false;
+            for arg in &args {
+                match arg.as_str() {
+                    "--strict" => strict = true,
+                    _ => bail!("Unknown argument: {}\n\n{}", arg, USAGE),
+                }
+            }
+            Ok(Command::Serve { strict })
+        }
+    }
+}
+
+fn parse_import_args(args: &[String]) -> Result<ImportOptions> {
+    let mut positional = Vec::new();
+    let mut network = None;
+    let mut contracts = Vec::new();
+    let mut data_dir = PathBuf::from("src/data");
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| match args.next() {
+            Some(value) => Ok(value.clone()),
+            None => bail!("{} expects a value\n\n{}", flag, USAGE),
+        };
+        match arg.as_str() {
+            "--network" => network = Some(value(arg)?),
+            "--contracts" => contracts.extend(
+                value(arg)?.split(',').map(str::trim).filter(|name| !name.is_empty()).map(str::to_string),
+            ),
+            "--data-dir" => data_dir = PathBuf::from(value(arg)?),
+            flag if flag.starts_with("--") => bail!("Unknown argument: {}\n\n{}", flag, USAGE),
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    let [project_dir, protocol] = positional.as_slice() else {
+        bail!("import-protocol expects <PROJECT_DIR> <PROTOCOL>\n\n{}", USAGE);
+    };
+    Ok(ImportOptions {
+        project_dir: PathBuf::from(project_dir),
+        protocol: protocol.clone(),
+        data_dir,
+        network,
+        contracts,
+    })
+}
+
+// Validates every protocol ABI, misc_data, addresses and storage layout file against its typed model.
+// Returns the number of invalid files.
+pub fn validate_data(data_dir: &Path) -> Result<usize> {
+    let protocols_dir = data_dir.join("protocols");
+    if !protocols_dir.exists() {
+        bail!("Protocols directory not found: {}", protocols_dir.display());
+    }
+
+    let mut checked = 0;
+    let mut invalid = 0;
+    let mut protocol_dirs: Vec<PathBuf> = fs::read_dir(&protocols_dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_dir())
+        .collect();
+    protocol_dirs.sort();
+
+    for protocol_dir in protocol_dirs {
+        let mut files = Vec::new();
+        let abis_dir = protocol_dir.join("abis");
+        if abis_dir.exists() {
+            files.extend(protocol::abi_file_paths(&abis_dir)?.into_iter().map(|path| (path, DataFileKind::Abi)));
+        }
+        let misc_data_dir = protocol_dir.join("misc_data");
+        if misc_data_dir.exists() {
+            files.extend(json_files(&misc_data_dir)?.into_iter().map(|path| (path, DataFileKind::MiscData)));
+        }
+        let addresses_file = protocol_dir.join("addresses.json");
+        if addresses_file.exists() {
+            files.push((addresses_file, DataFileKind::Addresses));
+        }
+        let storage_layout_dir = protocol_dir.join("storage_layout");
+        if storage_layout_dir.exists() {
+            files.extend(json_files(&storage_layout_dir)?.into_iter().map(|path| (path, DataFileKind::StorageLayout)));
+        }
+
+        for (file, kind) in files {
+            checked += 1;
+            match kind.validate(&file) {
+                Ok(()) => println!("ok     {}", file.display()),
+                Err(e) => {
+                    invalid += 1;
+                    println!("error  {}: {:#}", file.display(), e);
+                }
+            }
+        }
+    }
+
+    println!("\n{} file(s) checked, {} invalid", checked, invalid);
+    Ok(invalid)
+}
+
+fn json_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().and_then(|s| s.to_str()) == Some("json"))
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
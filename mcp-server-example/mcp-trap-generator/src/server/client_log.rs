@@ -0,0 +1,176 @@
+use serde_json::{json, Value};
+use std::fmt::Write as _;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+use crate::session::Session;
+
+const FLUSH_INTERVAL: Duration = Duration::from_millis(200);
+// Records held back before initialize; later ones are counted and dropped
+const MAX_PENDING: usize = 1000;
+
+// MCP log levels (RFC 5424 severities), ordered from least to most severe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Notice,
+    Warning,
+    Error,
+    Critical,
+    Alert,
+    Emergency,
+}
+
+impl LogLevel {
+    pub fn parse(level: &str) -> Option<Self> {
+        match level {
+            "debug" => Some(Self::Debug),
+            "info" => Some(Self::Info),
+            "notice" => Some(Self::Notice),
+            "warning" => Some(Self::Warning),
+            "error" => Some(Self::Error),
+            "critical" => Some(Self::Critical),
+            "alert" => Some(Self::Alert),
+            "emergency" => Some(Self::Emergency),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Debug => "debug",
+            Self::Info => "info",
+            Self::Notice => "notice",
+            Self::Warning => "warning",
+            Self::Error => "error",
+            Self::Critical => "critical",
+            Self::Alert => "alert",
+            Self::Emergency => "emergency",
+        }
+    }
+
+    fn from_tracing(level: &Level) -> Self {
+        match *level {
+            Level::ERROR => Self::Error,
+            Level::WARN => Self::Warning,
+            Level::INFO => Self::Info,
+            _ => Self::Debug,
+        }
+    }
+}
+
+pub struct LogRecord {
+    level: LogLevel,
+    logger: String,
+    message: String,
+}
+
+// Tracing layer that hands every event to the client log forwarder
+pub struct ClientLogLayer {
+    records: mpsc::UnboundedSender<LogRecord>,
+}
+
+impl ClientLogLayer {
+    pub fn new() -> (Self, mpsc::UnboundedReceiver<LogRecord>) {
+        let (records, records_rx) = mpsc::unbounded_channel();
+        (Self { records }, records_rx)
+    }
+}
+
+impl<S: Subscriber> Layer<S> for ClientLogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+
+        let _ = self.records.send(LogRecord {
+            level: LogLevel::from_tracing(event.metadata().level()),
+            logger: event.metadata().target().to_string(),
+            message: visitor.message,
+        });
+    }
+}
+
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{:?}", value);
+        } else {
+            let _ = write!(self.message, " {}={:?}", field.name(), value);
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            let _ = write!(self.message, " {}={}", field.name(), value);
+        }
+    }
+}
+
+// Sends log records to the client as notifications/message. Records emitted
+// before the client finished initializing (e.g. load-time warnings) are held
+// back, up to MAX_PENDING, and flushed once it has, filtered by the level set
+// via logging/setLevel.
+pub async fn forward_logs(
+    mut records: mpsc::UnboundedReceiver<LogRecord>,
+    session: Arc<Mutex<Session>>,
+    outgoing: mpsc::UnboundedSender<Value>,
+) {
+    let mut pending = Vec::new();
+    let mut dropped = 0;
+    let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+
+    loop {
+        tokio::select! {
+            record = records.recv() => match record {
+                Some(record) if pending.len() < MAX_PENDING => pending.push(record),
+                Some(_) => dropped += 1,
+                None => return,
+            },
+            _ = interval.tick() => {}
+        }
+
+        let session = session.lock().await;
+        if !session.is_initialized() {
+            continue;
+        }
+
+        if dropped > 0 {
+            pending.push(LogRecord {
+                level: LogLevel::Warning,
+                logger: env!("CARGO_CRATE_NAME").to_string(),
+                message: format!("{} log message(s) emitted before initialize were dropped", dropped),
+            });
+            dropped = 0;
+        }
+
+        for record in pending.drain(..) {
+            if record.level < session.log_level() {
+                continue;
+            }
+            let notification = json!({
+                "jsonrpc": "2.0",
+                "method": "notifications/message",
+                "params": {
+                    "level": record.level.as_str(),
+                    "logger": record.logger,
+                    "data": record.message
+                }
+            });
+            if outgoing.send(notification).is_err() {
+                return;
+            }
+        }
+    }
+}
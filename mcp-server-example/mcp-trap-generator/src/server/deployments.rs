@@ -0,0 +1,110 @@
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+// Extracts the Drosera deployment tables from
+// drosera-context/website/docs/pages/deployments.md:
+//
+//   ## Holesky Testnet          <- one section per network
+//   ### Contracts               <- | Contract | Address |
+//   ### Seed Node Relayer       <- | Host | Address |
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DroseraDeployments {
+    pub networks: Vec<NetworkDeployment>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NetworkDeployment {
+    /// Section heading, e.g. `Holesky Testnet`
+    pub heading: String,
+    /// Registry name the heading resolves to, e.g. `holesky`
+    pub network: Option<String>,
+    /// Contract name -> address
+    pub contracts: BTreeMap<String, String>,
+    pub relayers: Vec<Relayer>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Relayer {
+    pub host: String,
+    pub address: String,
+}
+
+impl NetworkDeployment {
+    // The contract trappers point drosera.toml at
+    pub fn drosera_address(&self) -> Option<&str> {
+        self.contracts
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("Drosera Proxy"))
+            .or_else(|| self.contracts.iter().find(|(name, _)| name.eq_ignore_ascii_case("Drosera")))
+            .map(|(_, address)| address.as_str())
+    }
+
+    pub fn relay_rpc(&self) -> Option<&str> {
+        self.relayers.first().map(|relayer| relayer.address.as_str())
+    }
+}
+
+pub fn parse(markdown: &str) -> Result<DroseraDeployments> {
+    let mut deployments = DroseraDeployments::default();
+    let mut subsection = String::new();
+    let mut table: Vec<Vec<String>> = Vec::new();
+
+    for line in markdown.lines().chain(std::iter::once("")) {
+        let line = line.trim();
+
+        if line.starts_with('|') {
+            table.push(line.trim_matches('|').split('|').map(|cell| cell.trim().to_string()).collect());
+            continue;
+        }
+        if !table.is_empty() {
+            let Some(section) = deployments.networks.last_mut() else {
+                bail!("deployment table under '{}' appears before any network heading", subsection);
+            };
+            add_table(section, &subsection, std::mem::take(&mut table))?;
+        }
+
+        if let Some(heading) = line.strip_prefix("## ") {
+            deployments.networks.push(NetworkDeployment {
+                heading: heading.trim().to_string(),
+                network: None,
+                contracts: BTreeMap::new(),
+                relayers: Vec::new(),
+            });
+            subsection.clear();
+        } else if let Some(heading) = line.strip_prefix("### ") {
+            subsection = heading.trim().to_string();
+        }
+    }
+
+    Ok(deployments)
+}
+
+fn add_table(section: &mut NetworkDeployment, subsection: &str, rows: Vec<Vec<String>>) -> Result<()> {
+    let mut rows = rows.into_iter();
+    let Some(header) = rows.next() else {
+        return Ok(());
+    };
+    // Skip the |---|---| separator row
+    let rows: Vec<Vec<String>> = rows
+        .filter(|row| !row.iter().all(|cell| cell.chars().all(|c| c == '-' || c == ':')))
+        .collect();
+
+    if header.len() != 2 {
+        bail!("table under '{}' in '{}' should have two columns", subsection, section.heading);
+    }
+    let is_relayer_table = header[0].eq_ignore_ascii_case("Host");
+
+    for row in rows {
+        let [name, address] = row.as_slice() else {
+            bail!("malformed row in '{}' of '{}': {:?}", subsection, section.heading, row);
+        };
+        if is_relayer_table {
+            section.relayers.push(Relayer { host: name.clone(), address: address.clone() });
+        } else {
+            section.contracts.insert(name.clone(), address.clone());
+        }
+    }
+    Ok(())
+}
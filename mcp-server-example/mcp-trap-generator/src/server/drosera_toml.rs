@@ -0,0 +1,210 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::network::{NetworkInfo, NetworkRegistry};
+
+// Renders drosera.toml in the layout the bundled trap examples use, with the
+// network section filled in from the network registry.
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TrapTomlConfig {
+    /// Key under [traps], e.g. `fee_change`
+    pub trap_name: String,
+    /// Trap contract name; the artifact path is out/{contract}.sol/{contract}.json
+    pub contract_name: String,
+    #[serde(default = "zero_address")]
+    pub response_contract: String,
+    pub response_function: String,
+    #[serde(default = "default_cooldown")]
+    pub cooldown_period_blocks: u64,
+    #[serde(default = "default_min_operators")]
+    pub min_number_of_operators: u64,
+    #[serde(default = "default_max_operators")]
+    pub max_number_of_operators: u64,
+    #[serde(default = "default_block_sample_size")]
+    pub block_sample_size: u64,
+    #[serde(default)]
+    pub private_trap: bool,
+    #[serde(default)]
+    pub whitelist: Vec<String>,
+}
+
+fn zero_address() -> String {
+    "0x0000000000000000000000000000000000000000".to_string()
+}
+
+fn default_cooldown() -> u64 {
+    33
+}
+
+fn default_min_operators() -> u64 {
+    1
+}
+
+fn default_max_operators() -> u64 {
+    2
+}
+
+fn default_block_sample_size() -> u64 {
+    10
+}
+
+pub fn render(network: &NetworkInfo, trap: &TrapTomlConfig) -> Result<String> {
+    let Some(drosera) = &network.drosera else {
+        bail!("no Drosera deployment is registered for network '{}'", network.name);
+    };
+    if trap.trap_name.is_empty() || !trap.trap_name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        bail!("trap_name '{}' must be a bare TOML key (letters, digits, '_' or '-')", trap.trap_name);
+    }
+    if trap.min_number_of_operators > trap.max_number_of_operators {
+        bail!("min_number_of_operators is greater than max_number_of_operators");
+    }
+
+    let mut toml = String::new();
+    toml.push_str(&format!("ethereum_rpc = {}\n", quote(&network.ethereum_rpc)));
+    toml.push_str(&format!("drosera_rpc = {}\n", quote(&drosera.relay_rpc)));
+    if let Some(chain_id) = network.chain_id {
+        toml.push_str(&format!("eth_chain_id = {}\n", chain_id));
+    }
+    toml.push_str(&format!("drosera_address = {}\n", quote(&drosera.address)));
+    toml.push_str("\n[traps]\n\n");
+
+    toml.push_str(&format!("[traps.{}]\n", trap.trap_name));
+    toml.push_str(&format!(
+        "path = {}\n",
+        quote(&format!("out/{0}.sol/{0}.json", trap.contract_name))
+    ));
+    toml.push_str(&format!("response_contract = {}\n", quote(&trap.response_contract)));
+    toml.push_str(&format!("response_function = {}\n", quote(&trap.response_function)));
+    toml.push_str(&format!("cooldown_period_blocks = {}\n", trap.cooldown_period_blocks));
+    toml.push_str(&format!("min_number_of_operators = {}\n", trap.min_number_of_operators));
+    toml.push_str(&format!("max_number_of_operators = {}\n", trap.max_number_of_operators));
+    toml.push_str(&format!("block_sample_size = {}\n", trap.block_sample_size));
+    toml.push_str(&format!("private_trap = {}\n", trap.private_trap));
+    let whitelist: Vec<String> = trap.whitelist.iter().map(|address| quote(address)).collect();
+    toml.push_str(&format!("whitelist = [{}]\n", whitelist.join(", ")));
+    Ok(toml)
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// A trap declared under [traps] in an existing drosera.toml
+#[derive(Debug, Clone, Serialize)]
+pub struct DeclaredTrap {
+    pub name: String,
+    pub path: Option<String>,
+    pub response_function: Option<String>,
+    /// Drosera collects a single block when the key is absent
+    pub block_sample_size: u64,
+    pub cooldown_period_blocks: Option<u64>,
+}
+
+impl DeclaredTrap {
+    // Contract name from the artifact path, e.g. out/Foo.sol/Foo.json -> Foo
+    pub fn contract_name(&self) -> Option<&str> {
+        self.path.as_deref()?.rsplit('/').next()?.strip_suffix(".json")
+    }
+}
+
+pub fn parse_traps(text: &str) -> Result<Vec<DeclaredTrap>> {
+    let document: toml::Table = text.parse().context("drosera.toml is not valid TOML")?;
+    let Some(traps) = document.get("traps").and_then(|traps| traps.as_table()) else {
+        bail!("drosera.toml has no [traps] table");
+    };
+    let mut declared = Vec::new();
+    for (name, trap) in traps {
+        let Some(trap) = trap.as_table() else {
+            bail!("traps.{} is not a table", name);
+        };
+        let integer = |key: &str| -> Result<Option<u64>> {
+            match trap.get(key) {
+                None => Ok(None),
+                Some(value) => match value.as_integer().and_then(|n| u64::try_from(n).ok()) {
+                    Some(n) => Ok(Some(n)),
+                    None => bail!("traps.{}.{} must be a non-negative integer", name, key),
+                },
+            }
+        };
+        declared.push(DeclaredTrap {
+            name: name.clone(),
+            path: trap.get("path").and_then(|v| v.as_str()).map(str::to_string),
+            response_function: trap.get("response_function").and_then(|v| v.as_str()).map(str::to_string),
+            block_sample_size: integer("block_sample_size")?.unwrap_or(1),
+            cooldown_period_blocks: integer("cooldown_period_blocks")?,
+        });
+    }
+    Ok(declared)
+}
+
+// drosera_address and drosera_rpc against the network's registered Drosera
+// deployment (deployments.md's, where it lists the network). The network is
+// eth_chain_id's, else the one whose deployment drosera_address names.
+pub fn check_deployment(text: &str, networks: &NetworkRegistry) -> Result<Vec<String>> {
+    let document: toml::Table = text.parse().context("drosera.toml is not valid TOML")?;
+    let address = document.get("drosera_address").and_then(|v| v.as_str());
+    let relay = document.get("drosera_rpc").and_then(|v| v.as_str());
+    let mut warnings = Vec::new();
+
+    let network = match document.get("eth_chain_id") {
+        Some(chain_id) => {
+            let Some(chain_id) = chain_id.as_integer() else {
+                bail!("eth_chain_id must be an integer");
+            };
+            let Some(network) = networks.resolve(&chain_id.to_string()) else {
+                warnings.push(format!("eth_chain_id {} is not a registered network; drosera_address and drosera_rpc were not checked", chain_id));
+                return Ok(warnings);
+            };
+            network
+        }
+        None => {
+            let registered = address.and_then(|address| {
+                networks.iter().find(|info| info.drosera.as_ref().is_some_and(|drosera| drosera.address.eq_ignore_ascii_case(address)))
+            });
+            match (registered, address) {
+                (Some(network), _) => network,
+                (None, Some(address)) => {
+                    warnings.push(format!("drosera_address {} is not a registered Drosera deployment; set eth_chain_id to check it", address));
+                    return Ok(warnings);
+                }
+                (None, None) => return Ok(warnings),
+            }
+        }
+    };
+    let Some(drosera) = &network.drosera else {
+        return Ok(warnings);
+    };
+
+    match address {
+        Some(address) if address.eq_ignore_ascii_case(&drosera.address) => {}
+        Some(address) => warnings.push(format!(
+            "drosera_address {} is not Drosera on {} ({}, from {})",
+            address, network.name, drosera.address, drosera.source
+        )),
+        None => warnings.push(format!("drosera_address is missing; Drosera on {} is {} (from {})", network.name, drosera.address, drosera.source)),
+    }
+    match relay {
+        Some(relay) if relay.trim_end_matches('/') == drosera.relay_rpc.trim_end_matches('/') => {}
+        Some(relay) => warnings.push(format!(
+            "drosera_rpc {} is not the {} relay ({}, from {})",
+            relay, network.name, drosera.relay_rpc, drosera.source
+        )),
+        None => warnings.push(format!("drosera_rpc is missing; the {} relay is {} (from {})", network.name, drosera.relay_rpc, drosera.source)),
+    }
+    Ok(warnings)
+}
+
+// The trap named `key`, the one whose artifact is `contract`, or the only one declared
+pub fn select_trap<'a>(traps: &'a [DeclaredTrap], key: Option<&str>, contract: &str) -> Result<&'a DeclaredTrap> {
+    if let Some(key) = key {
+        return traps.iter().find(|trap| trap.name == key).with_context(|| format!("no [traps.{}] in drosera.toml", key));
+    }
+    match traps {
+        [single] => Ok(single),
+        _ => traps
+            .iter()
+            .find(|trap| trap.contract_name() == Some(contract))
+            .with_context(|| format!("several traps in drosera.toml and none builds {}; pass 'trap'", contract)),
+    }
+}
@@ -0,0 +1,90 @@
+use anyhow::{bail, Result};
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+
+use crate::protocol::{AbiEvent, Protocol};
+use crate::{abi_codec, address_book, signature};
+
+// Every event in a protocol's ABIs, grouped by name and served as
+// protocol://{protocol}/events/{name}, plus log decoding against them.
+
+pub fn event_names(protocol: &Protocol) -> BTreeSet<&str> {
+    protocol.abis.values().flat_map(|abi| &abi.events).map(|event| event.name.as_str()).collect()
+}
+
+// All events called `name` (one per declaring interface), with their topic0,
+// indexed parameters and the address book entries that emit them
+pub fn catalog_entry(protocol_name: &str, protocol: &Protocol, name: &str) -> Option<Value> {
+    let mut events = Vec::new();
+    for (interface, abi) in &protocol.abis {
+        for event in abi.events.iter().filter(|event| event.name == name) {
+            let inputs: Vec<Value> = event
+                .inputs
+                .iter()
+                .map(|input| json!({
+                    "name": input.name,
+                    "type": signature::canonical_type(input),
+                    "indexed": input.indexed == Some(true),
+                }))
+                .collect();
+            let emitters: Vec<Value> = address_book::networks(protocol)
+                .iter()
+                .flat_map(|network| address_book::entries(protocol_name, protocol, network))
+                .filter(|entry| entry.abi.as_deref() == Some(interface.as_str()))
+                .map(|entry| json!({ "network": entry.network, "role": entry.role, "address": entry.address }))
+                .collect();
+            events.push(json!({
+                "interface": interface,
+                "signature": event.signature,
+                "canonical_signature": event.canonical_signature,
+                "topic0": event.topic0,
+                "anonymous": event.anonymous,
+                "description": event.description,
+                "inputs": inputs,
+                "emitters": emitters,
+            }));
+        }
+    }
+    if events.is_empty() {
+        return None;
+    }
+    Some(json!({
+        "protocol": protocol_name,
+        "name": name,
+        "events": events,
+    }))
+}
+
+// Decodes a log's indexed topics (after topic0) and data against one event
+pub fn decode_event(event: &AbiEvent, topics: &[Vec<u8>], data: &[u8]) -> Result<Vec<Value>> {
+    let indexed_count = event.inputs.iter().filter(|input| input.indexed == Some(true)).count();
+    if indexed_count != topics.len() {
+        bail!("expects {} indexed topic(s), the log has {}", indexed_count, topics.len());
+    }
+    let unindexed: Vec<_> = event.inputs.iter().filter(|input| input.indexed != Some(true)).cloned().collect();
+    let mut data_values = abi_codec::decode(&unindexed, data)?.into_iter();
+    let mut topics = topics.iter();
+
+    let mut args = Vec::new();
+    for input in &event.inputs {
+        let mut arg = json!({
+            "name": input.name,
+            "type": signature::canonical_type(input),
+            "indexed": input.indexed == Some(true),
+        });
+        if input.indexed == Some(true) {
+            let topic = topics.next().expect("topic count checked above");
+            if abi_codec::is_hashed_when_indexed(input) {
+                // Only the keccak256 of the value is recoverable
+                arg["value"] = Value::String(signature::to_hex(topic));
+                arg["hashed"] = Value::Bool(true);
+            } else {
+                arg["value"] = abi_codec::decode_word(input, topic)?;
+            }
+        } else {
+            arg["value"] = data_values.next().expect("one value per unindexed input");
+        }
+        args.push(arg);
+    }
+    Ok(args)
+}
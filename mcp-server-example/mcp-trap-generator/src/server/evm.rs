@@ -0,0 +1,238 @@
+use anyhow::{anyhow, bail, Result};
+use revm::interpreter::{CallInputs, CallOutcome as InterpreterCallOutcome, Gas, InstructionResult, InterpreterResult};
+use revm::primitives::{AccountInfo, Address, Bytecode, Bytes, ExecutionResult, Output, SpecId, TxKind, U256};
+use revm::{inspector_handle_register, Evm, EvmContext, InMemoryDB, Inspector};
+use std::collections::HashSet;
+
+use crate::abi_codec;
+use crate::signature;
+
+// Offline execution of trap code in an in-memory revm instance. There is no
+// RPC and no forked state: accounts hold only what is set up here, and calls
+// to other contracts either hit a mocked response or fail.
+
+const GAS_LIMIT: u64 = 30_000_000;
+
+/// Where runtime code is placed when no constructor runs
+pub const TRAP_ADDRESS: Address = Address::new([0xd5; 20]);
+/// Sender of deployments and of every call
+pub const CALLER: Address = Address::new([0xca; 20]);
+
+// Code given to mocked accounts that have none, so Solidity's extcodesize
+// check passes. Executing it (an unmocked call) halts.
+const PLACEHOLDER_CODE: [u8; 1] = [0xfe];
+
+pub enum Code {
+    /// Creation code (with constructor arguments appended), run as a deployment
+    Creation(Vec<u8>),
+    /// Runtime code, placed at TRAP_ADDRESS as is
+    Runtime(Vec<u8>),
+}
+
+#[derive(Debug, Clone)]
+pub struct CallOutcome {
+    pub success: bool,
+    pub output: Vec<u8>,
+    pub gas_used: u64,
+    /// Revert reason or halt cause when `success` is false
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockCall {
+    pub to: Address,
+    /// Matched as a prefix of the calldata: a bare selector answers every call of that function
+    pub calldata: Vec<u8>,
+    pub returndata: Vec<u8>,
+    pub reverts: bool,
+}
+
+// Answers calls from the mock table; the longest matching calldata prefix wins
+#[derive(Debug, Default)]
+struct Mocks {
+    calls: Vec<MockCall>,
+    placeholders: HashSet<Address>,
+    /// Calls to accounts without real code that no mock answered
+    misses: Vec<(Address, Vec<u8>)>,
+}
+
+impl Inspector<InMemoryDB> for Mocks {
+    fn call(&mut self, context: &mut EvmContext<InMemoryDB>, inputs: &mut CallInputs) -> Option<InterpreterCallOutcome> {
+        let target = inputs.target_address;
+        let mock = self
+            .calls
+            .iter()
+            .filter(|mock| mock.to == target && inputs.input.starts_with(&mock.calldata))
+            .max_by_key(|mock| mock.calldata.len());
+        let Some(mock) = mock else {
+            let has_code = context.db.accounts.get(&target).is_some_and(|account| account.info.code.as_ref().is_some_and(|code| !code.is_empty()));
+            let is_precompile = target.0[..19].iter().all(|b| *b == 0) && target.0[19] <= 0x0a;
+            if self.placeholders.contains(&target) || (!has_code && !is_precompile) {
+                self.misses.push((target, inputs.input.to_vec()));
+            }
+            return None;
+        };
+        let result = InterpreterResult {
+            result: if mock.reverts { InstructionResult::Revert } else { InstructionResult::Return },
+            output: Bytes::from(mock.returndata.clone()),
+            gas: Gas::new(inputs.gas_limit),
+        };
+        Some(InterpreterCallOutcome::new(result, inputs.return_memory_offset.clone()))
+    }
+}
+
+pub struct Sandbox {
+    evm: Evm<'static, Mocks, InMemoryDB>,
+}
+
+impl Sandbox {
+    pub fn new() -> Self {
+        let evm = Evm::builder()
+            .with_db(InMemoryDB::default())
+            .with_external_context(Mocks::default())
+            .with_spec_id(SpecId::CANCUN)
+            .modify_tx_env(|tx| {
+                tx.caller = CALLER;
+                tx.gas_limit = GAS_LIMIT;
+                tx.gas_price = U256::ZERO;
+            })
+            .append_handler_register(inspector_handle_register)
+            .build();
+        Self { evm }
+    }
+
+    pub fn set_block(&mut self, number: u64, timestamp: Option<u64>) {
+        let block = self.evm.block_mut();
+        block.number = U256::from(number);
+        if let Some(timestamp) = timestamp {
+            block.timestamp = U256::from(timestamp);
+        }
+    }
+
+    fn update_account(&mut self, address: Address, update: impl FnOnce(&mut AccountInfo)) {
+        let db = self.evm.db_mut();
+        let mut info = db.accounts.get(&address).map(|account| account.info.clone()).unwrap_or_default();
+        update(&mut info);
+        db.insert_account_info(address, info);
+    }
+
+    pub fn set_balance(&mut self, address: Address, balance: U256) {
+        self.update_account(address, |info| info.balance = balance);
+    }
+
+    pub fn set_nonce(&mut self, address: Address, nonce: u64) {
+        self.update_account(address, |info| info.nonce = nonce);
+    }
+
+    pub fn set_code(&mut self, address: Address, code: Vec<u8>) {
+        self.evm.context.external.placeholders.remove(&address);
+        let bytecode = Bytecode::new_raw(Bytes::from(code));
+        self.update_account(address, |info| {
+            info.code_hash = bytecode.hash_slow();
+            info.code = Some(bytecode);
+        });
+    }
+
+    pub fn set_storage(&mut self, address: Address, slot: U256, value: U256) -> Result<()> {
+        self.evm.db_mut().insert_account_storage(address, slot, value).map_err(|e| anyhow!("{:?}", e))
+    }
+
+    // Adds a mock, replacing one for the same target and calldata
+    pub fn mock(&mut self, mock: MockCall) {
+        let has_code = self.evm.db().accounts.get(&mock.to).is_some_and(|account| account.info.code.as_ref().is_some_and(|code| !code.is_empty()));
+        if !has_code {
+            self.set_code(mock.to, PLACEHOLDER_CODE.to_vec());
+            self.evm.context.external.placeholders.insert(mock.to);
+        }
+        let calls = &mut self.evm.context.external.calls;
+        calls.retain(|existing| !(existing.to == mock.to && existing.calldata == mock.calldata));
+        calls.push(mock);
+    }
+
+    // Unanswered calls since the last take, as (target, calldata)
+    pub fn take_misses(&mut self) -> Vec<(Address, Vec<u8>)> {
+        std::mem::take(&mut self.evm.context.external.misses)
+    }
+
+    pub fn deploy(&mut self, code: Code) -> Result<Address> {
+        match code {
+            Code::Runtime(code) => {
+                self.set_code(TRAP_ADDRESS, code);
+                Ok(TRAP_ADDRESS)
+            }
+            Code::Creation(code) => {
+                let tx = self.evm.tx_mut();
+                tx.transact_to = TxKind::Create;
+                tx.data = Bytes::from(code);
+                match self.evm.transact_commit().map_err(|e| anyhow!("deployment failed: {}", e))? {
+                    ExecutionResult::Success { output: Output::Create(_, Some(address)), .. } => Ok(address),
+                    ExecutionResult::Success { .. } => bail!("deployment returned no contract address"),
+                    ExecutionResult::Revert { output, .. } => bail!("constructor reverted: {}", revert_reason(&output)),
+                    ExecutionResult::Halt { reason, .. } => bail!("constructor halted: {:?}", reason),
+                }
+            }
+        }
+    }
+
+    // Calls `address` without committing state, as the operator's eth_call does
+    pub fn call(&mut self, address: Address, calldata: Vec<u8>) -> Result<CallOutcome> {
+        let tx = self.evm.tx_mut();
+        tx.transact_to = TxKind::Call(address);
+        tx.data = Bytes::from(calldata);
+        let result = self.evm.transact().map_err(|e| anyhow!("call failed: {}", e))?.result;
+        Ok(match result {
+            ExecutionResult::Success { output, gas_used, .. } => {
+                CallOutcome { success: true, output: output.into_data().to_vec(), gas_used, error: None }
+            }
+            ExecutionResult::Revert { output, gas_used } => {
+                CallOutcome { success: false, error: Some(revert_reason(&output)), output: output.to_vec(), gas_used }
+            }
+            ExecutionResult::Halt { reason, gas_used } => {
+                CallOutcome { success: false, output: Vec::new(), gas_used, error: Some(format!("halted: {:?}", reason)) }
+            }
+        })
+    }
+}
+
+pub fn selector(signature: &str) -> Vec<u8> {
+    signature::keccak256(signature.as_bytes())[..4].to_vec()
+}
+
+// shouldRespond(bytes[]) calldata for samples ordered like `data` (data[0] first)
+pub fn should_respond_calldata(samples: &[Vec<u8>]) -> Vec<u8> {
+    let param = signature::parse_types("bytes[]", &signature::StructDefs::new(&[])).expect("valid type");
+    let samples: Vec<serde_json::Value> = samples.iter().map(|sample| serde_json::Value::String(signature::to_hex(sample))).collect();
+    let mut calldata = selector("shouldRespond(bytes[])");
+    calldata.extend(abi_codec::encode(&param, &[serde_json::Value::Array(samples)]).expect("bytes[] encodes"));
+    calldata
+}
+
+// Error(string), Panic(uint256) or a custom error selector
+pub fn revert_reason(output: &[u8]) -> String {
+    let Some((selector, data)) = output.split_first_chunk::<4>() else {
+        return "reverted without a reason".to_string();
+    };
+    match selector {
+        [0x08, 0xc3, 0x79, 0xa0] => match signature::parse_types("string", &signature::StructDefs::new(&[])).and_then(|p| abi_codec::decode(&p, data)) {
+            Ok(values) => values[0].as_str().unwrap_or_default().to_string(),
+            Err(_) => format!("malformed Error(string): {}", signature::to_hex(output)),
+        },
+        [0x4e, 0x48, 0x7b, 0x71] if data.len() == 32 => {
+            let code = data[31];
+            let cause = match code {
+                0x01 => "assertion failed",
+                0x11 => "arithmetic overflow or underflow",
+                0x12 => "division or modulo by zero",
+                0x21 => "invalid enum value",
+                0x22 => "invalid storage byte array",
+                0x31 => "pop on an empty array",
+                0x32 => "array index out of bounds",
+                0x41 => "out of memory",
+                0x51 => "call to an uninitialized function",
+                _ => "unknown panic",
+            };
+            format!("panic 0x{:02x}: {}", code, cause)
+        }
+        _ => format!("custom error {} with data {}", signature::to_hex(selector), signature::to_hex(data)),
+    }
+}
@@ -0,0 +1,453 @@
+use anyhow::{bail, Context, Result};
+use revm::primitives::U256;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::address_book;
+use crate::network::NetworkInfo;
+
+// Turns a structured incident description into a trap project shaped like the
+// historical-exploit examples: a Snapshot struct of victim balances (with the
+// thresholds carried along, as EulerTrap does, so shouldRespond stays pure),
+// a fork test around the attack block and a README.
+
+// Beyond this many balances the Snapshot literal in collect() risks "stack too deep"
+const MAX_BALANCES: usize = 8;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IncidentSpec {
+    /// Protocol or incident name, e.g. `Euler Finance`
+    pub name: String,
+    /// Trap contract name (defaults to the name in PascalCase plus Trap)
+    #[serde(default)]
+    pub contract: Option<String>,
+    pub network: String,
+    /// First block containing the attack
+    pub attack_block: u64,
+    #[serde(default)]
+    pub date: Option<String>,
+    /// What happened, for the README
+    #[serde(default)]
+    pub summary: Option<String>,
+    /// The property the attack broke, in words
+    pub invariant: String,
+    pub victims: Vec<Victim>,
+    pub assets: Vec<Asset>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Victim {
+    /// Solidity identifier, e.g. `eulerCore`
+    pub name: String,
+    pub address: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Asset {
+    /// Token symbol used as its identifier, e.g. `DAI`
+    pub symbol: String,
+    /// ERC-20 address; omitted for the native currency
+    #[serde(default)]
+    pub address: Option<String>,
+    #[serde(default = "default_decimals")]
+    pub decimals: u8,
+    /// Victims holding the asset (defaults to all)
+    #[serde(default)]
+    pub held_by: Option<Vec<String>>,
+    /// Amount drained in the attack, in whole tokens
+    #[serde(default)]
+    pub stolen: Option<String>,
+    /// Per-block outflow that triggers, in whole tokens (defaults to half of `stolen`)
+    #[serde(default)]
+    pub max_outflow: Option<String>,
+    /// Per-block outflow that triggers, in basis points of the previous balance
+    #[serde(default)]
+    pub max_outflow_bps: Option<u64>,
+}
+
+fn default_decimals() -> u8 {
+    18
+}
+
+#[derive(Debug, Serialize)]
+pub struct Threshold {
+    /// Snapshot field holding it
+    pub field: String,
+    /// Solidity literal, in base units
+    pub value: String,
+    /// Where the value comes from
+    pub source: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PortedExploit {
+    pub contract: String,
+    /// Snapshot field -> what it holds
+    pub snapshot: BTreeMap<String, String>,
+    pub thresholds: Vec<Threshold>,
+    /// Project path -> content, laid out like the bundled examples
+    pub files: BTreeMap<String, String>,
+}
+
+// One balance the trap watches, with its thresholds
+struct Watched<'a> {
+    asset: &'a Asset,
+    victim: &'a Victim,
+    field: String,
+    absolute: Option<(String, String)>,
+    relative: Option<(String, u64)>,
+}
+
+pub fn port(spec: &IncidentSpec, network: &NetworkInfo) -> Result<PortedExploit> {
+    if spec.victims.is_empty() || spec.assets.is_empty() {
+        bail!("the incident needs at least one victim and one asset");
+    }
+    if spec.attack_block < 2 {
+        bail!("attack_block must leave two blocks before it for the fork tests");
+    }
+    let contract = match &spec.contract {
+        Some(name) if is_identifier(name) => name.clone(),
+        Some(name) => bail!("contract name '{}' is not a Solidity identifier", name),
+        None => format!("{}Trap", pascal_case(&spec.name)),
+    };
+    if !is_identifier(&contract) {
+        bail!("cannot derive a contract name from '{}'; pass 'contract'", spec.name);
+    }
+
+    let mut identifiers = BTreeSet::from([contract.clone(), "Snapshot".to_string()]);
+    let mut victims = BTreeMap::new();
+    for victim in &spec.victims {
+        checksummed(&victim.address).with_context(|| format!("victim {}", victim.name))?;
+        if !is_identifier(&victim.name) || !identifiers.insert(victim.name.clone()) {
+            bail!("victim name '{}' must be a unique Solidity identifier", victim.name);
+        }
+        victims.insert(victim.name.as_str(), victim);
+    }
+    for asset in &spec.assets {
+        if !is_identifier(&asset.symbol) || !identifiers.insert(asset.symbol.clone()) {
+            bail!("asset symbol '{}' must be a unique Solidity identifier", asset.symbol);
+        }
+        if let Some(address) = &asset.address {
+            checksummed(address).with_context(|| format!("asset {}", asset.symbol))?;
+        }
+        if asset.decimals > 77 {
+            bail!("asset {}: decimals {} do not fit a uint256", asset.symbol, asset.decimals);
+        }
+    }
+
+    let mut watched = Vec::new();
+    for asset in &spec.assets {
+        let holders: Vec<&Victim> = match &asset.held_by {
+            Some(names) => names
+                .iter()
+                .map(|name| victims.get(name.as_str()).copied().with_context(|| format!("asset {} is held by unknown victim '{}'", asset.symbol, name)))
+                .collect::<Result<_>>()?,
+            None => spec.victims.iter().collect(),
+        };
+        for victim in holders {
+            let field = format!("{}{}", camel_case(&asset.symbol), pascal_case(&victim.name));
+            let absolute = match (&asset.max_outflow, &asset.stolen) {
+                (Some(max), _) => {
+                    let max = base_units(max, asset.decimals).with_context(|| format!("asset {}: max_outflow", asset.symbol))?;
+                    Some((token_literal(max, asset.decimals), "max_outflow".to_string()))
+                }
+                (None, Some(stolen)) => {
+                    let half = base_units(stolen, asset.decimals).with_context(|| format!("asset {}: stolen", asset.symbol))? / U256::from(2);
+                    Some((token_literal(half, asset.decimals), format!("half of the {} {} stolen", stolen, asset.symbol)))
+                }
+                (None, None) => None,
+            };
+            let relative = match asset.max_outflow_bps {
+                Some(0) | Some(10_001..) => bail!("asset {}: max_outflow_bps must be between 1 and 10000", asset.symbol),
+                Some(bps) => Some(("max_outflow_bps".to_string(), bps)),
+                None => None,
+            };
+            if absolute.is_none() && relative.is_none() {
+                bail!("asset {} needs 'stolen', 'max_outflow' or 'max_outflow_bps' to pick a threshold", asset.symbol);
+            }
+            watched.push(Watched { asset, victim, field, absolute, relative });
+        }
+    }
+    if watched.len() > MAX_BALANCES {
+        bail!("{} victim balances exceed the {} one trap can snapshot; split the incident into several traps", watched.len(), MAX_BALANCES);
+    }
+
+    let mut snapshot = BTreeMap::new();
+    let mut thresholds = Vec::new();
+    for w in &watched {
+        snapshot.insert(w.field.clone(), format!("{} held by {}", w.asset.symbol, w.victim.name));
+        if let Some((value, source)) = &w.absolute {
+            snapshot.insert(format!("{}Threshold", w.field), format!("outflow of {} that triggers", w.field));
+            thresholds.push(Threshold { field: format!("{}Threshold", w.field), value: value.clone(), source: source.clone() });
+        }
+        if let Some((source, bps)) = &w.relative {
+            snapshot.insert(format!("{}MaxDropBps", w.field), format!("outflow of {} that triggers, in basis points", w.field));
+            thresholds.push(Threshold { field: format!("{}MaxDropBps", w.field), value: bps.to_string(), source: source.clone() });
+        }
+    }
+
+    let files = BTreeMap::from([
+        (format!("src/{}.sol", contract), render_trap(spec, &contract, &watched)?),
+        (format!("test/{}.t.sol", contract), render_test(spec, &contract, network)),
+        ("README.md".to_string(), render_readme(spec, &contract, &watched)),
+        ("foundry.toml".to_string(), render_foundry_toml(network)),
+        ("remappings.txt".to_string(), "forge-std/=node_modules/forge-std/src/\ndrosera-contracts/=node_modules/contracts/src/\n".to_string()),
+    ]);
+    Ok(PortedExploit { contract, snapshot, thresholds, files })
+}
+
+fn is_identifier(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
+
+// `Euler Finance` -> `EulerFinance`, `inverse-finance` -> `InverseFinance`
+fn pascal_case(name: &str) -> String {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            let first = chars.next().map(|c| c.to_ascii_uppercase()).unwrap_or_default();
+            first.to_string() + chars.as_str()
+        })
+        .collect()
+}
+
+// `DAI` -> `dai`, `wstETH` -> `wstETH`, `eDAI` -> `eDAI`
+fn camel_case(symbol: &str) -> String {
+    if symbol.chars().all(|c| !c.is_ascii_lowercase()) {
+        symbol.to_ascii_lowercase()
+    } else {
+        let mut chars = symbol.chars();
+        chars.next().map(|c| c.to_ascii_lowercase().to_string() + chars.as_str()).unwrap_or_default()
+    }
+}
+
+fn checksummed(address: &str) -> Result<String> {
+    address_book::to_checksum_address(address).with_context(|| format!("'{}' is not an address", address))
+}
+
+// Whole-token decimal amount ("8900000", "0.5") in base units
+fn base_units(amount: &str, decimals: u8) -> Result<U256> {
+    let amount = amount.replace('_', "");
+    let (whole, fraction) = amount.split_once('.').unwrap_or((&amount, ""));
+    if whole.is_empty() || !whole.chars().all(|c| c.is_ascii_digit()) || !fraction.chars().all(|c| c.is_ascii_digit()) {
+        bail!("'{}' is not a decimal token amount", amount);
+    }
+    if fraction.len() > decimals as usize {
+        bail!("'{}' has more than {} decimals", amount, decimals);
+    }
+    let digits = format!("{}{}{}", whole, fraction, "0".repeat(decimals as usize - fraction.len()));
+    digits.parse::<U256>().map_err(|_| anyhow::anyhow!("'{}' does not fit a uint256", amount))
+}
+
+// Base units as a Solidity literal, `15_000_000e18` when whole tokens
+fn token_literal(value: U256, decimals: u8) -> String {
+    let unit = U256::from(10).pow(U256::from(decimals));
+    if decimals > 0 && value % unit == U256::ZERO {
+        format!("{}e{}", group_digits(&(value / unit).to_string()), decimals)
+    } else {
+        group_digits(&value.to_string())
+    }
+}
+
+fn group_digits(digits: &str) -> String {
+    let mut grouped = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push('_');
+        }
+        grouped.push(c);
+    }
+    grouped
+}
+
+fn render_trap(spec: &IncidentSpec, contract: &str, watched: &[Watched]) -> Result<String> {
+    let mut source = String::new();
+    source.push_str("// SPDX-License-Identifier: MIT\n");
+    source.push_str("pragma solidity ^0.8.20;\n\n");
+    source.push_str("import {ITrap} from \"drosera-contracts/interfaces/ITrap.sol\";\n");
+    if spec.assets.iter().any(|a| a.address.is_some()) {
+        source.push_str("import {IERC20} from \"forge-std/interfaces/IERC20.sol\";\n");
+    }
+    source.push('\n');
+    source.push_str(&format!("// Ported from the {} exploit", spec.name));
+    if let Some(date) = &spec.date {
+        source.push_str(&format!(" ({})", date));
+    }
+    source.push_str(&format!(", first seen in block {}.\n", spec.attack_block));
+    source.push_str(&format!("// Invariant: {}\n", spec.invariant.trim()));
+    source.push_str(&format!("contract {} is ITrap {{\n", contract));
+
+    for asset in &spec.assets {
+        if let Some(address) = &asset.address {
+            source.push_str(&format!("    IERC20 {} = IERC20({});\n", asset.symbol, checksummed(address)?));
+        }
+    }
+    for victim in &spec.victims {
+        source.push_str(&format!("    address {} = {};\n", victim.name, checksummed(&victim.address)?));
+    }
+    for w in watched {
+        if let Some((value, _)) = &w.absolute {
+            source.push_str(&format!("    uint256 {}Threshold = {};\n", w.field, value));
+        }
+        if let Some((_, bps)) = &w.relative {
+            source.push_str(&format!("    uint256 {}MaxDropBps = {};\n", w.field, bps));
+        }
+    }
+
+    source.push_str("\n    struct Snapshot {\n");
+    for w in watched {
+        source.push_str(&format!("        uint256 {}; // {} held by {}\n", w.field, w.asset.symbol, w.victim.name));
+        if w.absolute.is_some() {
+            source.push_str(&format!("        uint256 {}Threshold;\n", w.field));
+        }
+        if w.relative.is_some() {
+            source.push_str(&format!("        uint256 {}MaxDropBps;\n", w.field));
+        }
+    }
+    source.push_str("    }\n\n");
+    source.push_str("    constructor() {}\n\n");
+
+    source.push_str("    function collect() external view returns (bytes memory) {\n");
+    source.push_str("        Snapshot memory s = Snapshot({\n");
+    let mut members = Vec::new();
+    for w in watched {
+        let balance = match w.asset.address {
+            Some(_) => format!("{}.balanceOf({})", w.asset.symbol, w.victim.name),
+            None => format!("{}.balance", w.victim.name),
+        };
+        members.push(format!("{}: {}", w.field, balance));
+        if w.absolute.is_some() {
+            members.push(format!("{0}Threshold: {0}Threshold", w.field));
+        }
+        if w.relative.is_some() {
+            members.push(format!("{0}MaxDropBps: {0}MaxDropBps", w.field));
+        }
+    }
+    source.push_str(&members.iter().map(|m| format!("            {}", m)).collect::<Vec<_>>().join(",\n"));
+    source.push_str("\n        });\n");
+    source.push_str("        return abi.encode(s);\n");
+    source.push_str("    }\n\n");
+
+    source.push_str("    function shouldRespond(bytes[] calldata data) external pure returns (bool, bytes memory) {\n");
+    source.push_str("        if (data.length < 2) return (false, \"\");\n\n");
+    source.push_str("        Snapshot memory cur = abi.decode(data[0], (Snapshot));\n");
+    source.push_str("        Snapshot memory prev = abi.decode(data[1], (Snapshot));\n");
+    for w in watched {
+        let f = &w.field;
+        source.push_str(&format!("\n        // how much {} left {}\n", w.asset.symbol, w.victim.name));
+        source.push_str(&format!("        uint256 {0}Outflow = prev.{0} > cur.{0} ? prev.{0} - cur.{0} : 0;\n", f));
+        if w.absolute.is_some() {
+            source.push_str(&format!("        if ({0}Outflow >= cur.{0}Threshold) return (true, bytes(\"\"));\n", f));
+        }
+        if w.relative.is_some() {
+            source.push_str(&format!("        if ({0}Outflow > 0 && {0}Outflow * 10_000 >= prev.{0} * cur.{0}MaxDropBps) return (true, bytes(\"\"));\n", f));
+        }
+    }
+    source.push_str("        return (false, \"\");\n");
+    source.push_str("    }\n");
+    source.push_str("}\n");
+    Ok(source)
+}
+
+fn render_test(spec: &IncidentSpec, contract: &str, network: &NetworkInfo) -> String {
+    let block = group_digits(&spec.attack_block.to_string());
+    let pre = group_digits(&(spec.attack_block - 1).to_string());
+    let quiet = group_digits(&(spec.attack_block - 2).to_string());
+    let mut source = String::new();
+    source.push_str("// SPDX-License-Identifier: UNLICENSED\n");
+    source.push_str("pragma solidity ^0.8.20;\n\n");
+    source.push_str("import \"forge-std/Test.sol\";\n");
+    source.push_str(&format!("import {{{0}}} from \"../src/{0}.sol\";\n\n", contract));
+    source.push_str(&format!("contract {}Test is Test {{\n", contract));
+    source.push_str("    uint256 quietFork;\n");
+    source.push_str("    uint256 preExploitFork;\n");
+    source.push_str("    uint256 exploitFork;\n\n");
+    source.push_str("    function setUp() public {\n");
+    source.push_str(&format!("        quietFork = vm.createSelectFork(vm.rpcUrl(\"{}\"), {});\n", network.name, quiet));
+    source.push_str(&format!("        preExploitFork = vm.createSelectFork(vm.rpcUrl(\"{}\"), {});\n", network.name, pre));
+    source.push_str(&format!("        exploitFork = vm.createSelectFork(vm.rpcUrl(\"{}\"), {}); // hacked block\n", network.name, block));
+    source.push_str("    }\n\n");
+    source.push_str("    function test_FindExploit() public {\n");
+    source.push_str("        bytes[] memory data = new bytes[](2);\n");
+    source.push_str("        vm.selectFork(preExploitFork);\n");
+    source.push_str(&format!("        data[1] = new {}().collect();\n\n", contract));
+    source.push_str("        vm.selectFork(exploitFork);\n");
+    source.push_str(&format!("        data[0] = new {}().collect();\n\n", contract));
+    source.push_str(&format!("        (bool shouldRespond,) = new {}().shouldRespond(data);\n", contract));
+    source.push_str("        assertEq(shouldRespond, true);\n");
+    source.push_str("    }\n\n");
+    source.push_str("    function test_NoResponseBeforeExploit() public {\n");
+    source.push_str("        bytes[] memory data = new bytes[](2);\n");
+    source.push_str("        vm.selectFork(quietFork);\n");
+    source.push_str(&format!("        data[1] = new {}().collect();\n\n", contract));
+    source.push_str("        vm.selectFork(preExploitFork);\n");
+    source.push_str(&format!("        data[0] = new {}().collect();\n\n", contract));
+    source.push_str(&format!("        (bool shouldRespond,) = new {}().shouldRespond(data);\n", contract));
+    source.push_str("        assertEq(shouldRespond, false);\n");
+    source.push_str("    }\n");
+    source.push_str("}\n");
+    source
+}
+
+fn render_readme(spec: &IncidentSpec, contract: &str, watched: &[Watched]) -> String {
+    let mut readme = format!("# {} Exploit Detection with Drosera Trap\n\n", spec.name);
+    readme.push_str("## Introduction\n\n");
+    readme.push_str(&format!(
+        "This example ports the {} exploit{} into a Drosera trap. `{}` snapshots the balances the attack drained and triggers when they flow out faster than the thresholds below.\n\n",
+        spec.name,
+        spec.date.as_ref().map(|d| format!(" ({})", d)).unwrap_or_default(),
+        contract
+    ));
+    readme.push_str("## The Attack\n\n");
+    if let Some(summary) = &spec.summary {
+        readme.push_str(summary.trim());
+        readme.push_str("\n\n");
+    }
+    readme.push_str(&format!("The attack first landed in block {}. It broke this invariant: {}\n\n", spec.attack_block, spec.invariant.trim()));
+    readme.push_str("| Victim | Address |\n|---|---|\n");
+    for victim in &spec.victims {
+        readme.push_str(&format!("| `{}` | `{}` |\n", victim.name, victim.address));
+    }
+    readme.push_str("\n| Asset | Address | Stolen |\n|---|---|---|\n");
+    for asset in &spec.assets {
+        readme.push_str(&format!(
+            "| {} | {} | {} |\n",
+            asset.symbol,
+            asset.address.as_ref().map(|a| format!("`{}`", a)).unwrap_or_else(|| "native".to_string()),
+            asset.stolen.as_deref().unwrap_or("-")
+        ));
+    }
+    readme.push_str(&format!("\n## {} Implementation\n\n", contract));
+    readme.push_str("`collect()` returns a `Snapshot` of every watched balance, with the thresholds copied in so `shouldRespond()` stays pure. `shouldRespond()` compares the two newest snapshots and triggers when any balance drops by at least:\n\n");
+    for w in watched {
+        let mut rules = Vec::new();
+        if let Some((value, source)) = &w.absolute {
+            rules.push(format!("`{}` base units ({})", value, source));
+        }
+        if let Some((_, bps)) = &w.relative {
+            rules.push(format!("{} bps of the previous balance", bps));
+        }
+        readme.push_str(&format!("- {} held by `{}`: {}\n", w.asset.symbol, w.victim.name, rules.join(" or ")));
+    }
+    readme.push_str("\nThe trap needs `block_sample_size = 2` in drosera.toml.\n\n");
+    readme.push_str("## Running the Tests\n\n");
+    readme.push_str(&format!(
+        "The tests fork the chain around the attack: `test_FindExploit` compares blocks {} and {} and expects a response, and `test_NoResponseBeforeExploit` compares the two blocks before them and expects none. They need an archive RPC endpoint in `foundry.toml`.\n\n",
+        spec.attack_block - 1,
+        spec.attack_block
+    ));
+    readme.push_str("```bash\nforge test -vvvvv\n```\n");
+    readme
+}
+
+fn render_foundry_toml(network: &NetworkInfo) -> String {
+    format!(
+        "[profile.default]\nsrc = \"src\"\nout = \"out\"\nlibs = [\"node_modules\"]\n\n[rpc_endpoints]\n{} = \"{}\"\n",
+        network.name, network.ethereum_rpc
+    )
+}
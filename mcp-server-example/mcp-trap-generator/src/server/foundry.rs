@@ -0,0 +1,541 @@
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use std::{env, process, thread};
+
+// Throwaway Foundry projects for submitted trap sources, built offline with a
+// locally installed `forge` or `solc`. Both report in solc's standard JSON
+// shape, which is normalized into diagnostics and per-contract artifacts.
+
+/// Import prefixes used by the trap examples (remappings.txt and forge's
+/// node_modules auto-detection), relative to their node_modules
+const REMAPPINGS: [(&str, &str); 4] = [
+    ("drosera-contracts/", "contracts/src/"),
+    ("contracts/", "contracts/src/"),
+    ("forge-std/", "forge-std/src/"),
+    ("@openzeppelin/contracts/", "@openzeppelin/contracts/"),
+];
+
+/// Vendored copy of the Drosera contracts' src/, used when no installed package provides it
+pub const DROSERA_LIB_DIR: &str = "src/data/drosera-lib";
+const DROSERA_PACKAGE: &str = "contracts/src/";
+
+/// EIP-170 limit on deployed code
+const MAX_CONTRACT_SIZE: usize = 24_576;
+
+const COMPILE_TIMEOUT: Duration = Duration::from_secs(120);
+const TEST_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Cheatcodes that need an RPC endpoint; test files using them are not run
+const FORK_CHEATCODES: [&str; 4] = ["createFork", "createSelectFork", "rollFork", "rpcUrl"];
+
+/// Environment variables through which forge would pick up an RPC endpoint
+const RPC_ENV_VARS: [&str; 3] = ["ETH_RPC_URL", "FOUNDRY_ETH_RPC_URL", "FOUNDRY_FORK_URL"];
+
+#[derive(Debug, Clone)]
+pub enum Compiler {
+    Forge(PathBuf),
+    Solc(PathBuf),
+}
+
+impl Compiler {
+    // `forge` or `solc` from PATH; without a preference forge wins
+    pub fn detect(preference: Option<&str>) -> Result<Self> {
+        match preference {
+            Some("forge") => find_executable("forge").map(Compiler::Forge).context("forge not found on PATH"),
+            Some("solc") => find_executable("solc").map(Compiler::Solc).context("solc not found on PATH"),
+            Some(other) => bail!("unknown compiler '{}' (expected forge or solc)", other),
+            None => find_executable("forge")
+                .map(Compiler::Forge)
+                .or_else(|| find_executable("solc").map(Compiler::Solc))
+                .context("neither forge nor solc found on PATH; install Foundry (foundryup) or solc"),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compiler::Forge(_) => "forge",
+            Compiler::Solc(_) => "solc",
+        }
+    }
+}
+
+fn find_executable(name: &str) -> Option<PathBuf> {
+    let path = env::var_os("PATH")?;
+    env::split_paths(&path)
+        .flat_map(|dir| [dir.join(name), dir.join(format!("{}.exe", name))])
+        .find(|candidate| candidate.is_file())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+    /// error, warning or info
+    pub severity: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CompiledContract {
+    pub file: String,
+    pub name: String,
+    pub abi: Value,
+    /// Creation code size in bytes
+    pub bytecode_size: usize,
+    /// Runtime code size in bytes
+    pub deployed_bytecode_size: usize,
+    pub exceeds_size_limit: bool,
+    /// Creation code as 0x-hex, for running the contract in an embedded EVM
+    #[serde(skip)]
+    pub bytecode: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CompileOutput {
+    pub compiler: &'static str,
+    pub success: bool,
+    pub diagnostics: Vec<Diagnostic>,
+    pub contracts: Vec<CompiledContract>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TestCase {
+    pub file: String,
+    pub contract: String,
+    pub name: String,
+    /// passed, failed or skipped
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// unit, fuzz or invariant
+    pub kind: String,
+    /// Gas of a unit test, mean gas of a fuzz test
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gas: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub runs: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub counterexample: Option<Value>,
+    /// console.log output and decoded events
+    pub logs: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TestOutput {
+    pub success: bool,
+    pub passed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    /// Compiler diagnostics; tests only run when there are no errors
+    pub diagnostics: Vec<Diagnostic>,
+    pub tests: Vec<TestCase>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TestFilter {
+    pub match_test: Option<String>,
+    pub match_contract: Option<String>,
+}
+
+pub fn is_fork_test(source: &str) -> bool {
+    FORK_CHEATCODES.iter().any(|cheatcode| source.contains(&format!("{}(", cheatcode)))
+}
+
+fn tool_error(message: String) -> Diagnostic {
+    Diagnostic { file: None, line: None, column: None, severity: "error".to_string(), code: None, message }
+}
+
+pub struct Project {
+    root: PathBuf,
+    /// Project-relative path -> source
+    sources: BTreeMap<String, String>,
+    remappings: Vec<String>,
+    allow_paths: Vec<PathBuf>,
+}
+
+impl Project {
+    // Writes `sources` (project-relative paths; bare file names go under src/ or test/)
+    // with remappings onto `dependencies`, a node_modules directory laid out
+    // like the trap examples' (forge-std, contracts, @openzeppelin/contracts).
+    // Drosera imports fall back to the vendored DROSERA_LIB_DIR.
+    pub fn create(sources: &BTreeMap<String, String>, dependencies: Option<&Path>, extra_remappings: &[String]) -> Result<Self> {
+        if sources.is_empty() {
+            bail!("no sources to compile");
+        }
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        let root = env::temp_dir().join(format!("drosera-trap-{}-{}", process::id(), NEXT_ID.fetch_add(1, Ordering::Relaxed)));
+        if root.exists() {
+            fs::remove_dir_all(&root).with_context(|| format!("failed to clear {}", root.display()))?;
+        }
+        fs::create_dir_all(&root).with_context(|| format!("failed to create {}", root.display()))?;
+        let mut project = Project { root, sources: BTreeMap::new(), remappings: Vec::new(), allow_paths: Vec::new() };
+
+        for (path, content) in sources {
+            let path = source_path(path)?;
+            let file = project.root.join(&path);
+            fs::create_dir_all(file.parent().expect("source paths have a parent"))?;
+            fs::write(&file, content).with_context(|| format!("failed to write {}", file.display()))?;
+            project.sources.insert(path, content.clone());
+        }
+
+        let dependencies = match dependencies {
+            Some(dir) => dir.canonicalize().with_context(|| format!("dependencies directory {} not found", dir.display()))?,
+            None => project.root.join("node_modules"),
+        };
+        let drosera_lib = Path::new(DROSERA_LIB_DIR).canonicalize().ok().filter(|_| !dependencies.join(DROSERA_PACKAGE).is_dir());
+        for (prefix, target) in REMAPPINGS {
+            match &drosera_lib {
+                Some(lib) if target == DROSERA_PACKAGE => project.remappings.push(format!("{}={}/", prefix, lib.display())),
+                _ => project.remappings.push(format!("{}={}", prefix, dependencies.join(target).display())),
+            }
+        }
+        project.allow_paths.push(dependencies);
+        project.allow_paths.extend(drosera_lib);
+        for remapping in extra_remappings {
+            let Some((prefix, target)) = remapping.split_once('=') else {
+                bail!("remapping '{}' is not of the form prefix=path", remapping);
+            };
+            // Later remappings with the same prefix take precedence, as in remappings.txt
+            project.remappings.retain(|r| !r.starts_with(&format!("{}=", prefix)));
+            project.remappings.push(remapping.clone());
+            project.allow_paths.push(PathBuf::from(target));
+        }
+
+        fs::write(project.root.join("remappings.txt"), project.remappings.join("\n") + "\n")?;
+        fs::write(project.root.join("foundry.toml"), project.foundry_toml())?;
+        Ok(project)
+    }
+
+    fn foundry_toml(&self) -> String {
+        let allow_paths: Vec<String> = self.allow_paths.iter().map(|p| format!("{:?}", p.display().to_string())).collect();
+        format!(
+            "[profile.default]\nsrc = \"src\"\ntest = \"test\"\nout = \"out\"\nlibs = [\"node_modules\"]\noffline = true\nallow_paths = [{}]\n",
+            allow_paths.join(", ")
+        )
+    }
+
+    pub fn compile(&self, compiler: &Compiler) -> Result<CompileOutput> {
+        let (standard_output, contracts) = match compiler {
+            Compiler::Forge(forge) => {
+                let mut command = self.forge_command(forge, "build");
+                let output = run(&mut command, None, COMPILE_TIMEOUT)?;
+                let standard_output = parse_json_output(&output);
+                let contracts = self.forge_artifacts()?;
+                (standard_output, contracts)
+            }
+            Compiler::Solc(solc) => {
+                let mut command = Command::new(solc);
+                command.arg("--standard-json").arg("--base-path").arg(&self.root);
+                for path in &self.allow_paths {
+                    command.arg("--allow-paths").arg(path);
+                }
+                command.current_dir(&self.root);
+                let input = self.standard_json_input();
+                let output = run(&mut command, Some(input.to_string().as_bytes()), COMPILE_TIMEOUT)?;
+                let standard_output = parse_json_output(&output);
+                let contracts = match &standard_output {
+                    Ok(value) => self.solc_contracts(value),
+                    Err(_) => Vec::new(),
+                };
+                (standard_output, contracts)
+            }
+        };
+
+        let diagnostics = match standard_output {
+            Ok(value) => self.diagnostics(&value),
+            // No JSON at all: the tool itself failed (missing solc version, bad config)
+            Err(message) => vec![tool_error(message)],
+        };
+        let success = !diagnostics.iter().any(|d| d.severity == "error");
+        Ok(CompileOutput {
+            compiler: compiler.name(),
+            success,
+            diagnostics,
+            contracts: if success { contracts } else { Vec::new() },
+        })
+    }
+
+    // Builds first so compile errors come back as diagnostics, then runs the
+    // tests offline with no RPC endpoint in reach
+    pub fn test(&self, compiler: &Compiler, filter: &TestFilter) -> Result<TestOutput> {
+        let Compiler::Forge(forge) = compiler else {
+            bail!("running tests needs forge");
+        };
+        let build = self.compile(compiler)?;
+        let mut output = TestOutput { success: false, passed: 0, failed: 0, skipped: 0, diagnostics: build.diagnostics, tests: Vec::new() };
+        if !build.success {
+            return Ok(output);
+        }
+
+        let mut command = self.forge_command(forge, "test");
+        if let Some(pattern) = &filter.match_test {
+            command.arg("--match-test").arg(pattern);
+        }
+        if let Some(pattern) = &filter.match_contract {
+            command.arg("--match-contract").arg(pattern);
+        }
+        match parse_json_output(&run(&mut command, None, TEST_TIMEOUT)?) {
+            Ok(results) => output.tests = parse_test_results(&results),
+            Err(message) => output.diagnostics.push(tool_error(message)),
+        }
+
+        for test in &output.tests {
+            match test.status.as_str() {
+                "passed" => output.passed += 1,
+                "failed" => output.failed += 1,
+                _ => output.skipped += 1,
+            }
+        }
+        output.success = output.failed == 0 && !output.diagnostics.iter().any(|d| d.severity == "error");
+        Ok(output)
+    }
+
+    fn forge_command(&self, forge: &Path, subcommand: &str) -> Command {
+        let mut command = Command::new(forge);
+        command.args([subcommand, "--offline", "--json", "--root"]).arg(&self.root).current_dir(&self.root);
+        for var in RPC_ENV_VARS {
+            command.env_remove(var);
+        }
+        command
+    }
+
+    fn standard_json_input(&self) -> Value {
+        let sources: BTreeMap<&String, Value> = self.sources.iter().map(|(path, content)| (path, json!({ "content": content }))).collect();
+        json!({
+            "language": "Solidity",
+            "sources": sources,
+            "settings": {
+                "remappings": self.remappings,
+                "optimizer": { "enabled": false, "runs": 200 },
+                "outputSelection": {
+                    "*": { "*": ["abi", "evm.bytecode.object", "evm.deployedBytecode.object"] }
+                }
+            }
+        })
+    }
+
+    fn diagnostics(&self, output: &Value) -> Vec<Diagnostic> {
+        output["errors"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|error| {
+                let formatted = error["formattedMessage"].as_str().unwrap_or_default();
+                let file = error["sourceLocation"]["file"].as_str().map(str::to_string);
+                let (line, column) = match (&file, error["sourceLocation"]["start"].as_u64()) {
+                    (Some(file), Some(start)) if self.sources.contains_key(file) => {
+                        let (line, column) = line_column(&self.sources[file], start as usize);
+                        (Some(line), Some(column))
+                    }
+                    _ => location_from_message(formatted),
+                };
+                Diagnostic {
+                    file,
+                    line,
+                    column,
+                    severity: error["severity"].as_str().unwrap_or("error").to_string(),
+                    code: error["errorCode"].as_str().map(str::to_string),
+                    message: error["message"].as_str().unwrap_or(formatted).to_string(),
+                }
+            })
+            .collect()
+    }
+
+    fn solc_contracts(&self, output: &Value) -> Vec<CompiledContract> {
+        let mut contracts = Vec::new();
+        for file in self.sources.keys() {
+            for (name, contract) in output["contracts"][file].as_object().into_iter().flatten() {
+                contracts.push(compiled_contract(file, name, &contract["abi"], &contract["evm"]["bytecode"], &contract["evm"]["deployedBytecode"]));
+            }
+        }
+        contracts
+    }
+
+    // forge writes out/{file name}/{Contract}.json for every contract a source declares
+    fn forge_artifacts(&self) -> Result<Vec<CompiledContract>> {
+        let mut contracts = Vec::new();
+        for file in self.sources.keys() {
+            let file_name = Path::new(file).file_name().expect("source paths have a file name");
+            let dir = self.root.join("out").join(file_name);
+            let Ok(entries) = fs::read_dir(&dir) else {
+                continue;
+            };
+            let mut paths: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect();
+            paths.sort();
+            for path in paths.into_iter().filter(|p| p.extension().and_then(|s| s.to_str()) == Some("json")) {
+                let artifact: Value = serde_json::from_str(&fs::read_to_string(&path)?)
+                    .with_context(|| format!("invalid forge artifact {}", path.display()))?;
+                let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+                contracts.push(compiled_contract(file, name, &artifact["abi"], &artifact["bytecode"], &artifact["deployedBytecode"]));
+            }
+        }
+        Ok(contracts)
+    }
+}
+
+impl Drop for Project {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+// `forge test --json`: {"test/X.t.sol:XTest": {"test_results": {"testY()": {...}}}}
+fn parse_test_results(results: &Value) -> Vec<TestCase> {
+    let mut tests = Vec::new();
+    for (suite, suite_result) in results.as_object().into_iter().flatten() {
+        let (file, contract) = suite.rsplit_once(':').unwrap_or(("", suite));
+        for (name, result) in suite_result["test_results"].as_object().into_iter().flatten() {
+            let status = match result["status"].as_str() {
+                Some("Success") => "passed",
+                Some("Failure") => "failed",
+                _ => "skipped",
+            };
+            let (kind, gas, runs) = match result["kind"].as_object().and_then(|kind| kind.iter().next()) {
+                Some((kind, details)) => (
+                    kind.to_ascii_lowercase(),
+                    details["gas"].as_u64().or_else(|| details["mean_gas"].as_u64()),
+                    details["runs"].as_u64(),
+                ),
+                None => ("unit".to_string(), None, None),
+            };
+            tests.push(TestCase {
+                file: file.to_string(),
+                contract: contract.to_string(),
+                name: name.clone(),
+                status: status.to_string(),
+                reason: result["reason"].as_str().map(str::to_string),
+                kind,
+                gas,
+                runs,
+                counterexample: Some(result["counterexample"].clone()).filter(|c| !c.is_null()),
+                logs: result["decoded_logs"].as_array().into_iter().flatten().filter_map(|log| log.as_str().map(str::to_string)).collect(),
+            });
+        }
+    }
+    tests
+}
+
+// Relative .sol path without `..`; bare file names go under test/ (*.t.sol) or src/
+fn source_path(path: &str) -> Result<String> {
+    let relative = Path::new(path);
+    if !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+        bail!("source path '{}' must be relative and must not contain '..'", path);
+    }
+    if relative.extension().and_then(|s| s.to_str()) != Some("sol") {
+        bail!("source path '{}' must end in .sol", path);
+    }
+    let path = path.replace('\\', "/");
+    if relative.components().count() == 1 {
+        Ok(format!("{}/{}", if path.ends_with(".t.sol") { "test" } else { "src" }, path))
+    } else {
+        Ok(path)
+    }
+}
+
+fn compiled_contract(file: &str, name: &str, abi: &Value, bytecode: &Value, deployed_bytecode: &Value) -> CompiledContract {
+    let size = |code: &Value| code["object"].as_str().map(|hex| hex.trim_start_matches("0x").len() / 2).unwrap_or(0);
+    let deployed_bytecode_size = size(deployed_bytecode);
+    CompiledContract {
+        file: file.to_string(),
+        name: name.to_string(),
+        abi: abi.clone(),
+        bytecode_size: size(bytecode),
+        deployed_bytecode_size,
+        exceeds_size_limit: deployed_bytecode_size > MAX_CONTRACT_SIZE,
+        bytecode: bytecode["object"].as_str().unwrap_or_default().to_string(),
+    }
+}
+
+// 1-based line and column of a byte offset
+fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source.as_bytes()[..offset.min(source.len())];
+    let line = before.iter().filter(|b| **b == b'\n').count() + 1;
+    let column = before.iter().rev().take_while(|b| **b != b'\n').count() + 1;
+    (line, column)
+}
+
+// solc's formattedMessage points at `--> path:line:column:`
+fn location_from_message(formatted: &str) -> (Option<usize>, Option<usize>) {
+    let Some(location) = formatted.split("--> ").nth(1).and_then(|rest| rest.lines().next()) else {
+        return (None, None);
+    };
+    let mut numbers = location.trim_end_matches(':').rsplit(':').map(|n| n.parse::<usize>().ok());
+    let column = numbers.next().flatten();
+    let line = numbers.next().flatten();
+    (line, column)
+}
+
+// The JSON document on stdout, or the tool's own error output
+pub fn parse_json_output(output: &Output) -> std::result::Result<Value, String> {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // forge may print progress lines before the JSON document
+    let json_start = stdout.find('{').unwrap_or(stdout.len());
+    match serde_json::from_str::<Value>(stdout[json_start..].trim()) {
+        Ok(value) => Ok(value),
+        Err(_) => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let message = if stderr.trim().is_empty() { stdout.trim() } else { stderr.trim() };
+            Err(if message.is_empty() { format!("exited with {}", output.status) } else { message.to_string() })
+        }
+    }
+}
+
+// Runs a command to completion, killing it after `timeout`
+pub fn run(command: &mut Command, stdin: Option<&[u8]>, timeout: Duration) -> Result<Output> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
+        .stdin(if stdin.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("failed to start {}", program))?;
+
+    if let (Some(input), Some(mut pipe)) = (stdin, child.stdin.take()) {
+        let input = input.to_vec();
+        thread::spawn(move || pipe.write_all(&input));
+    }
+    // Drain both pipes concurrently so a chatty child cannot block on a full pipe
+    let drain = |pipe: Option<Box<dyn Read + Send>>| {
+        thread::spawn(move || {
+            let mut buffer = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut buffer);
+            }
+            buffer
+        })
+    };
+    let stdout = drain(child.stdout.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
+    let stderr = drain(child.stderr.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
+
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if started.elapsed() > timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!("{} did not finish within {}s", program, timeout.as_secs());
+        }
+        thread::sleep(Duration::from_millis(50));
+    };
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
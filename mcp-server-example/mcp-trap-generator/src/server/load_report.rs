@@ -0,0 +1,58 @@
+use serde::Serialize;
+use std::fmt::Display;
+use std::path::Path;
+use tracing::warn;
+
+// A data file that could not be loaded, and what was left out because of it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LoadDiagnostic {
+    pub path: String,
+    pub error: String,
+    pub skipped: String,
+}
+
+// Loaded data that looks wrong, e.g. a drosera.toml that disagrees with its trap
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LoadWarning {
+    pub path: String,
+    pub warning: String,
+}
+
+// Warnings are advisory: they are served next to the diagnostics but are not
+// load failures, so they neither fail --strict nor count as broken files
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct LoadReport {
+    pub diagnostics: Vec<LoadDiagnostic>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<LoadWarning>,
+}
+
+impl LoadReport {
+    pub fn record(&mut self, path: &Path, error: impl Display, skipped: impl Into<String>) {
+        let diagnostic = LoadDiagnostic {
+            path: path.to_string_lossy().into_owned(),
+            error: format!("{:#}", error),
+            skipped: skipped.into(),
+        };
+        warn!(
+            "Failed to load {}: {} (skipped {})",
+            diagnostic.path, diagnostic.error, diagnostic.skipped
+        );
+        self.diagnostics.push(diagnostic);
+    }
+
+    pub fn warn(&mut self, path: &Path, warning: impl Display) {
+        let warning = LoadWarning { path: path.to_string_lossy().into_owned(), warning: warning.to_string() };
+        warn!("{}: {}", warning.path, warning.warning);
+        self.warnings.push(warning);
+    }
+
+    // Load failures only; warnings are not counted
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.diagnostics.len()
+    }
+}
@@ -0,0 +1,131 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::deployments::DroseraDeployments;
+
+// Registry of the chains traps can run on (src/data/networks.json). Protocol
+// data, address books and drosera.toml rendering resolve network names
+// through it, so `ethereum`, `eth`, `1` and `mainnet` all mean the same chain.
+
+/// Chain metadata and Drosera defaults for one network. The Drosera
+/// deployment from deployments.md takes precedence over the one listed here.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkInfo {
+    /// Canonical name, taken from the registry key
+    #[serde(default, skip_deserializing)]
+    pub name: String,
+    pub display_name: String,
+    /// Unset for ephemeral networks such as the Drosera devnet
+    #[serde(rename = "chainId", default, skip_serializing_if = "Option::is_none")]
+    pub chain_id: Option<u64>,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    pub block_time_seconds: u64,
+    #[serde(default)]
+    pub testnet: bool,
+    /// Default `ethereum_rpc` for drosera.toml
+    pub ethereum_rpc: String,
+    /// Drosera deployment, when the network has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub drosera: Option<DroseraDeployment>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DroseraDeployment {
+    /// Drosera proxy contract, used as `drosera_address`
+    pub address: String,
+    /// Seed node relayer, used as `drosera_rpc`
+    pub relay_rpc: String,
+    /// Where the address and relay were taken from: a path relative to this crate, or a URL
+    pub source: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct NetworkRegistry {
+    networks: BTreeMap<String, NetworkInfo>,
+}
+
+impl NetworkRegistry {
+    pub fn new(networks: BTreeMap<String, NetworkInfo>) -> Self {
+        let networks = networks
+            .into_iter()
+            .map(|(name, mut info)| {
+                info.name = name.clone();
+                (name, info)
+            })
+            .collect();
+        Self { networks }
+    }
+
+    // Looks a network up by canonical name, alias or chain id (case-insensitive)
+    pub fn resolve(&self, name: &str) -> Option<&NetworkInfo> {
+        let name = name.trim();
+        let chain_id = name.parse::<u64>().ok();
+        self.networks.values().find(|info| {
+            info.name.eq_ignore_ascii_case(name)
+                || info.aliases.iter().any(|alias| alias.eq_ignore_ascii_case(name))
+                || (chain_id.is_some() && info.chain_id == chain_id)
+        })
+    }
+
+    // Matches a human-written heading such as "Holesky Testnet" or "Ethereum Mainnet"
+    pub fn resolve_heading(&self, heading: &str) -> Option<&NetworkInfo> {
+        self.networks
+            .values()
+            .find(|info| info.display_name.eq_ignore_ascii_case(heading))
+            .or_else(|| heading.split_whitespace().find_map(|word| self.resolve(word)))
+    }
+
+    // Makes deployments.md the source of each network's Drosera address and
+    // relay, returning the registry values it disagreed with
+    pub fn apply_deployments(&mut self, deployments: &mut DroseraDeployments, source: &str) -> Vec<String> {
+        let mut mismatches = Vec::new();
+        for section in &mut deployments.networks {
+            let Some(name) = self.resolve_heading(&section.heading).map(|info| info.name.clone()) else {
+                continue;
+            };
+            section.network = Some(name.clone());
+            let (Some(address), Some(relay_rpc)) = (section.drosera_address(), section.relay_rpc()) else {
+                continue;
+            };
+
+            let info = self.networks.get_mut(&name).expect("resolved network is registered");
+            if let Some(registered) = &info.drosera {
+                if !registered.address.eq_ignore_ascii_case(address) {
+                    mismatches.push(format!(
+                        "{}: drosera address {} differs from deployments.md ({})",
+                        name, registered.address, address
+                    ));
+                }
+                if registered.relay_rpc != relay_rpc {
+                    mismatches.push(format!(
+                        "{}: relay {} differs from deployments.md ({})",
+                        name, registered.relay_rpc, relay_rpc
+                    ));
+                }
+            }
+            info.drosera = Some(DroseraDeployment {
+                address: address.to_string(),
+                relay_rpc: relay_rpc.to_string(),
+                source: source.to_string(),
+            });
+        }
+        mismatches
+    }
+
+    // Canonical name for a network, or the input unchanged when it is not registered
+    pub fn canonical_name(&self, name: &str) -> String {
+        self.resolve(name).map(|info| info.name.clone()).unwrap_or_else(|| name.to_string())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &NetworkInfo> {
+        self.networks.values()
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.networks.keys().map(String::as_str).collect()
+    }
+}
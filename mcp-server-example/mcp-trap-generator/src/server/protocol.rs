@@ -0,0 +1,413 @@
+use anyhow::{bail, Context, Result};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{signature, solc_abi, storage_layout};
+
+// Typed models for the protocol data files:
+//   protocols/{protocol}/abis/{Interface}.json     -> AbiFile
+//   protocols/{protocol}/misc_data/{network}.json -> NetworkData
+//   protocols/{protocol}/addresses.json            -> AddressesFile (optional)
+//   protocols/{protocol}/storage_layout/{Contract}.json -> StorageLayout (optional)
+
+#[derive(Debug, Clone, Default)]
+pub struct Protocol {
+    pub abis: BTreeMap<String, AbiFile>,
+    pub networks: BTreeMap<String, NetworkData>,
+    /// Network -> additional deployments from addresses.json
+    pub addresses: AddressesFile,
+    /// Contract -> solc storage layout
+    pub storage_layouts: BTreeMap<String, StorageLayout>,
+}
+
+/// Interface ABI with human-readable function and event signatures.
+///
+/// Standard solc/Foundry ABI arrays and build artifacts are accepted as well
+/// and normalized into this model.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AbiFile {
+    pub interface: String,
+    pub description: String,
+    #[serde(default)]
+    pub functions: Vec<AbiFunction>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<AbiEvent>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<AbiError>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub structs: Vec<AbiStruct>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AbiFunction {
+    pub name: String,
+    /// e.g. `function getPool(address tokenA, address tokenB, uint24 fee) external view returns (address pool)`
+    pub signature: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<AbiParam>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<AbiParam>,
+    /// pure, view, nonpayable or payable
+    #[serde(rename = "stateMutability", default, skip_serializing_if = "Option::is_none")]
+    pub state_mutability: Option<String>,
+    /// Computed on load, e.g. `getPool(address,address,uint24)`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(skip)]
+    pub canonical_signature: Option<String>,
+    /// Computed on load: first 4 bytes of keccak256(canonical_signature)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(skip)]
+    pub selector: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AbiEvent {
+    pub name: String,
+    /// e.g. `Swap(address indexed sender, address indexed recipient, int256 amount0, ...)`
+    pub signature: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<AbiParam>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub anonymous: bool,
+    /// Computed on load, e.g. `Swap(address,address,int256,int256,uint160,uint128,int24)`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(skip)]
+    pub canonical_signature: Option<String>,
+    /// Computed on load: keccak256(canonical_signature)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(skip)]
+    pub topic0: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AbiError {
+    pub name: String,
+    /// e.g. `error InsufficientLiquidity(uint256 available)`
+    pub signature: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<AbiParam>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(skip)]
+    pub canonical_signature: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(skip)]
+    pub selector: Option<String>,
+}
+
+/// A typed parameter, in the same shape solc uses for ABI JSON.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct AbiParam {
+    #[serde(default)]
+    pub name: String,
+    /// Canonical ABI type, e.g. `uint256`, `address[]`, `tuple`
+    #[serde(rename = "type")]
+    pub ty: String,
+    /// Solidity type, e.g. `struct ISwapRouter.ExactInputSingleParams`
+    #[serde(rename = "internalType", default, skip_serializing_if = "Option::is_none")]
+    pub internal_type: Option<String>,
+    /// Members of a `tuple` type
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub components: Vec<AbiParam>,
+    /// Only used for event parameters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub indexed: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AbiStruct {
+    pub name: String,
+    /// Comma-separated typed struct members, e.g. `address tokenIn, uint24 fee`
+    pub fields: String,
+}
+
+/// Protocol deployment and monitoring context for a single network.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkData {
+    pub name: String,
+    pub description: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub website: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub docs: Option<String>,
+    pub network: String,
+    #[serde(rename = "chainId")]
+    pub chain_id: u64,
+    #[serde(default)]
+    pub supported_trap_types: Vec<String>,
+    #[serde(default)]
+    pub trap_categories: BTreeMap<String, TrapCategory>,
+    #[serde(default)]
+    pub common_monitoring_scenarios: Vec<MonitoringScenario>,
+    #[serde(default)]
+    pub imports: Vec<String>,
+    #[serde(default)]
+    pub common_functions: Vec<String>,
+    /// Human-readable event signatures
+    #[serde(default)]
+    pub events: Vec<String>,
+    #[serde(default)]
+    pub contracts: BTreeMap<String, ContractEntry>,
+    /// Token symbol -> address
+    #[serde(default)]
+    pub tokens: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub popular_pools: BTreeMap<String, PoolEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TrapCategory {
+    pub description: String,
+    #[serde(default)]
+    pub common_targets: Vec<String>,
+    #[serde(default)]
+    pub example_traps: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MonitoringScenario {
+    pub name: String,
+    pub description: String,
+    /// Keys of `trap_categories` this scenario belongs to
+    #[serde(default)]
+    pub categories: Vec<String>,
+    /// Contract, token or pool roles, or `popular_pools` / `tokens` for all of them
+    #[serde(default)]
+    pub target_contracts: Vec<String>,
+    #[serde(default)]
+    pub key_events: Vec<String>,
+    #[serde(default)]
+    pub key_functions: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ContractEntry {
+    pub address: String,
+    /// Name of the ABI (interface) describing this contract
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub abi: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deployment_block: Option<u64>,
+}
+
+/// Deployments listed in a protocol's addresses.json, keyed by network.
+pub type AddressesFile = BTreeMap<String, Vec<AddressRecord>>;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AddressRecord {
+    /// Label for the contract, e.g. `factory` or `USDC`
+    pub role: String,
+    pub address: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub abi: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deployment_block: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PoolEntry {
+    pub address: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub abi: Option<String>,
+    pub token0: String,
+    pub token0_symbol: String,
+    pub token1: String,
+    pub token1_symbol: String,
+    pub fee: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// Storage layout of a contract, as solc emits it for `outputSelection`
+/// `storageLayout` (or Foundry's `extra_output = ["storageLayout"]`).
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct StorageLayout {
+    pub storage: Vec<StorageVariable>,
+    /// Type id, e.g. `t_mapping(t_address,t_uint256)` -> type description
+    #[serde(default)]
+    pub types: BTreeMap<String, StorageType>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct StorageVariable {
+    #[serde(rename = "astId", default, skip_serializing_if = "Option::is_none")]
+    pub ast_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contract: Option<String>,
+    pub label: String,
+    /// Byte offset within the slot, for packed variables
+    pub offset: u32,
+    /// Decimal slot number (relative to the struct for members)
+    pub slot: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct StorageType {
+    /// inplace, mapping, dynamic_array or bytes
+    pub encoding: String,
+    pub label: String,
+    #[serde(rename = "numberOfBytes")]
+    pub number_of_bytes: String,
+    /// Mapping key type id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// Mapping value type id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    /// Array element type id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base: Option<String>,
+    /// Struct members, with slots relative to the struct
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub members: Option<Vec<StorageVariable>>,
+}
+
+// The kinds of protocol data files, each with its own schema
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataFileKind {
+    Abi,
+    MiscData,
+    Addresses,
+    StorageLayout,
+}
+
+impl DataFileKind {
+    pub const ALL: [DataFileKind; 4] =
+        [DataFileKind::Abi, DataFileKind::MiscData, DataFileKind::Addresses, DataFileKind::StorageLayout];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DataFileKind::Abi => "abi",
+            DataFileKind::MiscData => "misc_data",
+            DataFileKind::Addresses => "addresses",
+            DataFileKind::StorageLayout => "storage_layout",
+        }
+    }
+
+    pub fn file_pattern(&self) -> &'static str {
+        match self {
+            DataFileKind::Abi => "protocols/*/abis/*.json",
+            DataFileKind::MiscData => "protocols/*/misc_data/*.json",
+            DataFileKind::Addresses => "protocols/*/addresses.json",
+            DataFileKind::StorageLayout => "protocols/*/storage_layout/*.json",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    pub fn schema(&self) -> schemars::schema::RootSchema {
+        match self {
+            DataFileKind::Abi => schemars::schema_for!(AbiFile),
+            DataFileKind::MiscData => schemars::schema_for!(NetworkData),
+            DataFileKind::Addresses => schemars::schema_for!(AddressesFile),
+            DataFileKind::StorageLayout => schemars::schema_for!(StorageLayout),
+        }
+    }
+
+    pub fn schema_uri(&self) -> String {
+        format!("schema://protocol/{}", self.name())
+    }
+
+    pub fn validate(&self, path: &Path) -> Result<()> {
+        match self {
+            DataFileKind::Abi => {
+                let mut abi = read_abi_file(path)?;
+                let problems: Vec<String> = signature::annotate_abi(&mut abi)
+                    .into_iter()
+                    .map(|(item, e)| format!("{}: {:#}", item, e))
+                    .collect();
+                if !problems.is_empty() {
+                    bail!("{}", problems.join("; "));
+                }
+                Ok(())
+            }
+            DataFileKind::MiscData => read_data_file::<NetworkData>(path).map(|_| ()),
+            DataFileKind::Addresses => read_data_file::<AddressesFile>(path).map(|_| ()),
+            DataFileKind::StorageLayout => read_storage_layout(path).map(|_| ()),
+        }
+    }
+}
+
+// JSON files in an abis/ directory, including artifacts nested one level deep
+// the way forge build lays them out (Name.sol/Name.json)
+pub fn abi_file_paths(abis_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(abis_dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            for nested in fs::read_dir(&path)? {
+                paths.push(nested?.path());
+            }
+        } else {
+            paths.push(path);
+        }
+    }
+    paths.retain(|path| path.extension().and_then(|s| s.to_str()) == Some("json"));
+    paths.sort();
+    Ok(paths)
+}
+
+// Reads an ABI in any supported format: this server's own format, a solc ABI
+// array, or a build artifact with an `abi` key (Foundry `out/*.json`)
+pub fn read_abi_file(path: &Path) -> Result<AbiFile> {
+    let data: serde_json::Value = read_data_file(path)?;
+    let interface = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+
+    match data {
+        serde_json::Value::Array(_) => solc_abi::normalize(interface, data),
+        serde_json::Value::Object(ref artifact) if artifact.contains_key("abi") => {
+            solc_abi::normalize(interface, artifact["abi"].clone())
+        }
+        _ => Ok(serde_json::from_value(data)?),
+    }
+}
+
+// Reads a storage layout on its own or from a build artifact's `storageLayout`
+// key, and checks that every referenced type is described
+pub fn read_storage_layout(path: &Path) -> Result<StorageLayout> {
+    let mut data: serde_json::Value = read_data_file(path)?;
+    if let Some(layout) = data.get_mut("storageLayout") {
+        data = layout.take();
+    }
+    let layout: StorageLayout = serde_json::from_value(data)?;
+    storage_layout::check(&layout)?;
+    Ok(layout)
+}
+
+pub fn read_data_file<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let data = serde_json::from_str(&content)?;
+    Ok(data)
+}
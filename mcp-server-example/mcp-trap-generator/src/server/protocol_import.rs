@@ -0,0 +1,521 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::address_book;
+use crate::network::{NetworkInfo, NetworkRegistry};
+use crate::protocol::{self, AbiFile, ContractEntry, DataFileKind, NetworkData};
+use crate::solc_abi;
+
+// Writes a protocol pack (abis/ + misc_data/{network}.json) from a local
+// project's build output and deployment records:
+//
+//   Foundry         out/{File}.sol/{Contract}.json
+//                   broadcast/{Script}.s.sol/{chainId}/run-latest.json
+//   hardhat-deploy  deployments/{network}/{Contract}.json (+ .chainId)
+//
+// Re-importing into an existing pack keeps its hand-written context: ABI
+// descriptions carry over by name and misc_data only gains contracts and events.
+
+pub struct ImportOptions {
+    pub project_dir: PathBuf,
+    pub protocol: String,
+    pub data_dir: PathBuf,
+    /// Only import deployments on this network
+    pub network: Option<String>,
+    /// Contracts whose ABIs are imported even without a deployment (Foundry only)
+    pub contracts: Vec<String>,
+}
+
+#[derive(Default)]
+struct Project {
+    /// Human-readable source, used in generated descriptions
+    source: &'static str,
+    deployments: Vec<Deployment>,
+    /// Contract name -> solc ABI array
+    abis: BTreeMap<String, Value>,
+}
+
+struct Deployment {
+    contract: String,
+    address: String,
+    /// Chain id or network name as the project records it
+    network: String,
+    deployment_block: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct Broadcast {
+    #[serde(default)]
+    transactions: Vec<BroadcastTransaction>,
+    #[serde(default)]
+    receipts: Vec<BroadcastReceipt>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BroadcastTransaction {
+    hash: Option<String>,
+    transaction_type: String,
+    contract_name: Option<String>,
+    contract_address: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BroadcastReceipt {
+    transaction_hash: String,
+    block_number: Value,
+}
+
+#[derive(Deserialize)]
+struct HardhatDeployment {
+    address: String,
+    abi: Value,
+    receipt: Option<HardhatReceipt>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HardhatReceipt {
+    block_number: Option<Value>,
+}
+
+// Imports the project into data_dir/protocols/{protocol}, printing each file
+// written. Returns the number of written files that fail validation.
+pub fn import_protocol(options: &ImportOptions) -> Result<usize> {
+    if options.protocol.is_empty()
+        || !options.protocol.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+    {
+        bail!("protocol name '{}' must be lowercase letters, digits, '-' or '_'", options.protocol);
+    }
+    let networks: BTreeMap<String, NetworkInfo> = protocol::read_data_file(&options.data_dir.join("networks.json"))
+        .context("failed to load the network registry")?;
+    let registry = NetworkRegistry::new(networks);
+
+    let dir = &options.project_dir;
+    let mut project = if dir.join("broadcast").is_dir() || (dir.join("out").is_dir() && !dir.join("deployments").is_dir()) {
+        read_foundry_project(dir, &options.contracts)?
+    } else if dir.join("deployments").is_dir() {
+        read_hardhat_deployments(dir, &options.contracts)?
+    } else {
+        bail!("{} has neither Foundry out/ and broadcast/ nor a hardhat-deploy deployments/ folder", dir.display());
+    };
+
+    // Resolve every deployment to a registered network before writing anything
+    let only_network = match &options.network {
+        Some(name) => match registry.resolve(name) {
+            Some(info) => Some(info.name.clone()),
+            None => bail!("unknown network '{}' (known: {})", name, registry.names().join(", ")),
+        },
+        None => None,
+    };
+    let mut by_network: BTreeMap<String, (u64, Vec<Deployment>)> = BTreeMap::new();
+    for deployment in project.deployments.drain(..) {
+        let info = registry.resolve(&deployment.network);
+        if let Some(only) = &only_network {
+            if info.map(|info| &info.name) != Some(only) {
+                continue;
+            }
+        }
+        let Some(info) = info else {
+            bail!(
+                "{} is deployed on '{}', which is not in networks.json; add it there or pass --network",
+                deployment.contract,
+                deployment.network
+            );
+        };
+        let Some(chain_id) = info.chain_id else {
+            bail!("network '{}' has no chainId, so it cannot have protocol misc_data", info.name);
+        };
+        by_network.entry(info.name.clone()).or_insert_with(|| (chain_id, Vec::new())).1.push(deployment);
+    }
+
+    let mut contracts: BTreeSet<String> = options.contracts.iter().cloned().collect();
+    contracts.extend(by_network.values().flat_map(|(_, deployments)| deployments).map(|d| d.contract.clone()));
+    if contracts.is_empty() {
+        bail!("no deployments found; pass --contracts to import ABIs without deployments");
+    }
+
+    let protocol_dir = options.data_dir.join("protocols").join(&options.protocol);
+    let mut written = Vec::new();
+
+    let mut abis = BTreeMap::new();
+    for contract in &contracts {
+        let Some(solc_abi) = project.abis.remove(contract) else {
+            bail!("no ABI found for {}", contract);
+        };
+        let path = protocol_dir.join("abis").join(format!("{}.json", contract));
+        let mut abi = solc_abi::normalize(contract, solc_abi)
+            .with_context(|| format!("failed to read the ABI of {}", contract))?;
+        abi.description = format!("{} (imported from {})", contract, project.source);
+        if path.exists() {
+            keep_descriptions(&mut abi, &protocol::read_abi_file(&path)?);
+        }
+        write_json(&path, &abi)?;
+        written.push((path, DataFileKind::Abi));
+        abis.insert(contract.clone(), abi);
+    }
+
+    for (network, (chain_id, deployments)) in by_network {
+        let path = protocol_dir.join("misc_data").join(format!("{}.json", network));
+        let mut data = if path.exists() {
+            protocol::read_data_file::<NetworkData>(&path)?
+        } else {
+            new_network_data(&options.protocol, project.source, &network, chain_id)
+        };
+
+        for deployment in deployments {
+            let Some(address) = address_book::to_checksum_address(&deployment.address) else {
+                bail!("{} on {} has an invalid address: {}", deployment.contract, network, deployment.address);
+            };
+            add_contract(&mut data, &deployment.contract, address, deployment.deployment_block);
+            for event in &abis[&deployment.contract].events {
+                if !data.events.contains(&event.signature) {
+                    data.events.push(event.signature.clone());
+                }
+            }
+        }
+        write_json(&path, &data)?;
+        written.push((path, DataFileKind::MiscData));
+    }
+
+    let mut invalid = 0;
+    for (path, kind) in written {
+        match kind.validate(&path) {
+            Ok(()) => println!("wrote  {}", path.display()),
+            Err(e) => {
+                invalid += 1;
+                println!("error  {}: {:#}", path.display(), e);
+            }
+        }
+    }
+    Ok(invalid)
+}
+
+fn read_foundry_project(project_dir: &Path, requested: &[String]) -> Result<Project> {
+    let mut project = Project { source: "Foundry artifacts", ..Default::default() };
+
+    let broadcast_dir = project_dir.join("broadcast");
+    if broadcast_dir.is_dir() {
+        for script_dir in sorted_entries(&broadcast_dir)?.into_iter().filter(|path| path.is_dir()) {
+            for chain_dir in sorted_entries(&script_dir)?.into_iter().filter(|path| path.is_dir()) {
+                let run_path = chain_dir.join("run-latest.json");
+                if !run_path.exists() {
+                    continue;
+                }
+                let chain_id = chain_dir.file_name().and_then(|s| s.to_str()).unwrap_or_default().to_string();
+                let broadcast: Broadcast = protocol::read_data_file(&run_path)?;
+
+                for tx in &broadcast.transactions {
+                    if tx.transaction_type != "CREATE" && tx.transaction_type != "CREATE2" {
+                        continue;
+                    }
+                    let (Some(contract), Some(address)) = (&tx.contract_name, &tx.contract_address) else {
+                        continue;
+                    };
+                    let deployment_block = broadcast
+                        .receipts
+                        .iter()
+                        .find(|receipt| Some(&receipt.transaction_hash) == tx.hash.as_ref())
+                        .and_then(|receipt| block_number(&receipt.block_number));
+                    push_deployment(&mut project, Deployment {
+                        contract: contract.clone(),
+                        address: address.clone(),
+                        network: chain_id.clone(),
+                        deployment_block,
+                    });
+                }
+            }
+        }
+    }
+
+    let out_dir = project_dir.join("out");
+    let contracts: BTreeSet<&String> = project.deployments.iter().map(|d| &d.contract).chain(requested).collect();
+    for contract in contracts {
+        let artifact_path = find_artifact(&out_dir, contract)?;
+        let artifact: Value = protocol::read_data_file(&artifact_path)?;
+        if !artifact["abi"].is_array() {
+            bail!("{} has no abi array", artifact_path.display());
+        }
+        project.abis.insert(contract.clone(), artifact["abi"].clone());
+    }
+    Ok(project)
+}
+
+fn read_hardhat_deployments(project_dir: &Path, requested: &[String]) -> Result<Project> {
+    let mut project = Project { source: "hardhat-deploy deployments", ..Default::default() };
+
+    for network_dir in sorted_entries(&project_dir.join("deployments"))?.into_iter().filter(|path| path.is_dir()) {
+        let dir_name = network_dir.file_name().and_then(|s| s.to_str()).unwrap_or_default().to_string();
+        // The .chainId file is authoritative; folder names are arbitrary labels
+        let network = fs::read_to_string(network_dir.join(".chainId"))
+            .map(|chain_id| chain_id.trim().to_string())
+            .unwrap_or(dir_name);
+
+        for path in sorted_entries(&network_dir)? {
+            let Some(contract) = path.file_stem().and_then(|s| s.to_str()).map(str::to_string) else {
+                continue;
+            };
+            if contract.starts_with('.') || path.extension().and_then(|s| s.to_str()) != Some("json") {
+                continue;
+            }
+            let deployment: HardhatDeployment = protocol::read_data_file(&path)?;
+            project.abis.insert(contract.clone(), deployment.abi);
+            push_deployment(&mut project, Deployment {
+                contract,
+                address: deployment.address,
+                network: network.clone(),
+                deployment_block: deployment.receipt.and_then(|r| r.block_number).as_ref().and_then(block_number),
+            });
+        }
+    }
+
+    if let Some(missing) = requested.iter().find(|contract| !project.abis.contains_key(*contract)) {
+        bail!("{} is not in deployments/; hardhat-deploy imports only cover deployed contracts", missing);
+    }
+    Ok(project)
+}
+
+// Later broadcasts of the same address (re-runs of a script) are not new deployments
+fn push_deployment(project: &mut Project, deployment: Deployment) {
+    let duplicate = project.deployments.iter().any(|existing| {
+        existing.network == deployment.network && existing.address.eq_ignore_ascii_case(&deployment.address)
+    });
+    if !duplicate {
+        project.deployments.push(deployment);
+    }
+}
+
+// out/{Contract}.sol/{Contract}.json, or the only artifact with that name
+fn find_artifact(out_dir: &Path, contract: &str) -> Result<PathBuf> {
+    let preferred = out_dir.join(format!("{}.sol", contract)).join(format!("{}.json", contract));
+    if preferred.exists() {
+        return Ok(preferred);
+    }
+    if !out_dir.is_dir() {
+        bail!("{} not found; run forge build first", out_dir.display());
+    }
+    let file_name = format!("{}.json", contract);
+    let mut matches = Vec::new();
+    for source_dir in sorted_entries(out_dir)?.into_iter().filter(|path| path.is_dir()) {
+        let candidate = source_dir.join(&file_name);
+        if candidate.exists() {
+            matches.push(candidate);
+        }
+    }
+    match matches.len() {
+        1 => Ok(matches.remove(0)),
+        0 => bail!("no artifact for {} in {}", contract, out_dir.display()),
+        _ => {
+            let found: Vec<String> = matches.iter().map(|path| path.display().to_string()).collect();
+            bail!("{} is ambiguous: {}", contract, found.join(", "))
+        }
+    }
+}
+
+// Block numbers are hex strings in Foundry receipts and integers in hardhat-deploy
+fn block_number(value: &Value) -> Option<u64> {
+    match value {
+        Value::Number(number) => number.as_u64(),
+        Value::String(hex) => u64::from_str_radix(hex.strip_prefix("0x")?, 16).ok(),
+        _ => None,
+    }
+}
+
+fn new_network_data(protocol: &str, source: &str, network: &str, chain_id: u64) -> NetworkData {
+    NetworkData {
+        name: protocol.to_string(),
+        description: format!("{} contracts (imported from {})", protocol, source),
+        version: None,
+        website: None,
+        docs: None,
+        network: network.to_string(),
+        chain_id,
+        supported_trap_types: Vec::new(),
+        trap_categories: BTreeMap::new(),
+        common_monitoring_scenarios: Vec::new(),
+        imports: Vec::new(),
+        common_functions: Vec::new(),
+        events: Vec::new(),
+        contracts: BTreeMap::new(),
+        tokens: BTreeMap::new(),
+        popular_pools: BTreeMap::new(),
+    }
+}
+
+// Updates the entry already holding this address, or adds one under the
+// contract name (Name_2, Name_3, ... when that role is taken)
+fn add_contract(data: &mut NetworkData, contract: &str, address: String, deployment_block: Option<u64>) {
+    let existing = data.contracts.values_mut().find(|entry| entry.address.eq_ignore_ascii_case(&address));
+    if let Some(entry) = existing {
+        entry.address = address;
+        entry.abi = Some(contract.to_string());
+        entry.deployment_block = deployment_block.or(entry.deployment_block);
+        return;
+    }
+
+    let mut role = contract.to_string();
+    let mut n = 2;
+    while data.contracts.contains_key(&role) {
+        role = format!("{}_{}", contract, n);
+        n += 1;
+    }
+    data.contracts.insert(role, ContractEntry { address, abi: Some(contract.to_string()), deployment_block });
+}
+
+fn keep_descriptions(abi: &mut AbiFile, existing: &AbiFile) {
+    abi.description = existing.description.clone();
+    for function in &mut abi.functions {
+        let previous = existing.functions.iter().find(|f| f.name == function.name);
+        function.description = previous.and_then(|f| f.description.clone());
+    }
+    for event in &mut abi.events {
+        let previous = existing.events.iter().find(|e| e.name == event.name);
+        event.description = previous.and_then(|e| e.description.clone());
+    }
+    for error in &mut abi.errors {
+        let previous = existing.errors.iter().find(|e| e.name == error.name);
+        error.description = previous.and_then(|e| e.description.clone());
+    }
+}
+
+fn sorted_entries(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .with_context(|| format!("failed to read {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+fn write_json(path: &Path, value: &impl serde::Serialize) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(value)? + "\n")
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const VAULT: &str = "0x5FbDB2315678afecb367f032d93F642f64180aa3";
+
+    fn write(path: &Path, value: &Value) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, serde_json::to_string_pretty(value).unwrap()).unwrap();
+    }
+
+    fn read(path: &Path) -> Value {
+        serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    // A Foundry project that deployed Vault on mainnet (twice, by re-running the script)
+    // and builds Oracle without deploying it
+    fn foundry_project(dir: &Path) {
+        let vault_abi = json!([
+            { "type": "function", "name": "deposit", "stateMutability": "nonpayable",
+              "inputs": [{ "name": "amount", "type": "uint256", "internalType": "uint256" }], "outputs": [] },
+            { "type": "function", "name": "totalAssets", "stateMutability": "view", "inputs": [],
+              "outputs": [{ "name": "", "type": "uint256", "internalType": "uint256" }] },
+            { "type": "event", "name": "Deposit", "anonymous": false, "inputs": [
+                { "name": "user", "type": "address", "indexed": true, "internalType": "address" },
+                { "name": "amount", "type": "uint256", "indexed": false, "internalType": "uint256" }] }
+        ]);
+        let oracle_abi = json!([
+            { "type": "function", "name": "price", "stateMutability": "view", "inputs": [],
+              "outputs": [{ "name": "", "type": "uint256", "internalType": "uint256" }] }
+        ]);
+        write(&dir.join("out/Vault.sol/Vault.json"), &json!({ "abi": vault_abi, "bytecode": { "object": "0x" } }));
+        write(&dir.join("out/Oracle.sol/Oracle.json"), &json!({ "abi": oracle_abi }));
+        let run = json!({
+            "transactions": [
+                { "hash": "0xaa", "transactionType": "CREATE", "contractName": "Vault", "contractAddress": VAULT.to_lowercase() },
+                { "hash": "0xbb", "transactionType": "CALL", "contractName": "Vault", "contractAddress": VAULT }
+            ],
+            "receipts": [{ "transactionHash": "0xaa", "blockNumber": "0x12d687" }]
+        });
+        write(&dir.join("broadcast/Deploy.s.sol/1/run-latest.json"), &run);
+        write(&dir.join("broadcast/Redeploy.s.sol/1/run-latest.json"), &run);
+    }
+
+    fn options(project: &Path, data: &Path) -> ImportOptions {
+        ImportOptions {
+            project_dir: project.to_path_buf(),
+            protocol: "vaults".to_string(),
+            data_dir: data.to_path_buf(),
+            network: None,
+            contracts: vec!["Oracle".to_string()],
+        }
+    }
+
+    #[test]
+    fn imports_foundry_project() {
+        let project = tempfile::tempdir().unwrap();
+        let data = tempfile::tempdir().unwrap();
+        foundry_project(project.path());
+        fs::copy("src/data/networks.json", data.path().join("networks.json")).unwrap();
+
+        assert_eq!(import_protocol(&options(project.path(), data.path())).unwrap(), 0);
+
+        let protocol_dir = data.path().join("protocols/vaults");
+        let vault = read(&protocol_dir.join("abis/Vault.json"));
+        assert_eq!(vault["interface"], "Vault");
+        let functions: Vec<&str> = vault["functions"].as_array().unwrap().iter().map(|f| f["name"].as_str().unwrap()).collect();
+        assert_eq!(functions, ["deposit", "totalAssets"]);
+        assert_eq!(vault["events"][0]["name"], "Deposit");
+        assert!(protocol_dir.join("abis/Oracle.json").exists());
+
+        let mainnet = read(&protocol_dir.join("misc_data/mainnet.json"));
+        assert_eq!(mainnet["chainId"], 1);
+        assert_eq!(mainnet["network"], "mainnet");
+        let contracts = mainnet["contracts"].as_object().unwrap();
+        assert_eq!(contracts.len(), 1, "re-broadcast deployments are not duplicated: {:?}", contracts);
+        assert_eq!(contracts["Vault"]["address"], VAULT);
+        assert_eq!(contracts["Vault"]["abi"], "Vault");
+        assert_eq!(contracts["Vault"]["deployment_block"], 1234567);
+        assert_eq!(mainnet["events"], json!([vault["events"][0]["signature"]]));
+        assert!(!protocol_dir.join("misc_data/holesky.json").exists());
+    }
+
+    #[test]
+    fn reimport_keeps_descriptions() {
+        let project = tempfile::tempdir().unwrap();
+        let data = tempfile::tempdir().unwrap();
+        foundry_project(project.path());
+        fs::copy("src/data/networks.json", data.path().join("networks.json")).unwrap();
+        import_protocol(&options(project.path(), data.path())).unwrap();
+
+        let abi_path = data.path().join("protocols/vaults/abis/Vault.json");
+        let mut vault = read(&abi_path);
+        vault["description"] = json!("Single-asset vault");
+        vault["functions"][0]["description"] = json!("Deposits the asset");
+        write(&abi_path, &vault);
+
+        import_protocol(&options(project.path(), data.path())).unwrap();
+        let vault = read(&abi_path);
+        assert_eq!(vault["description"], "Single-asset vault");
+        assert_eq!(vault["functions"][0]["description"], "Deposits the asset");
+        let mainnet = read(&data.path().join("protocols/vaults/misc_data/mainnet.json"));
+        assert_eq!(mainnet["contracts"].as_object().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn rejects_unknown_networks() {
+        let project = tempfile::tempdir().unwrap();
+        let data = tempfile::tempdir().unwrap();
+        foundry_project(project.path());
+        fs::rename(project.path().join("broadcast/Deploy.s.sol/1"), project.path().join("broadcast/Deploy.s.sol/424242")).unwrap();
+        fs::copy("src/data/networks.json", data.path().join("networks.json")).unwrap();
+
+        let error = import_protocol(&options(project.path(), data.path())).unwrap_err();
+        assert!(error.to_string().contains("'424242', which is not in networks.json"), "{}", error);
+        assert!(!data.path().join("protocols/vaults").exists(), "nothing is written before networks resolve");
+    }
+}
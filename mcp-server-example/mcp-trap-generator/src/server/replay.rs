@@ -0,0 +1,475 @@
+use anyhow::{bail, Context, Result};
+use revm::primitives::{Address, U256};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::abi_codec;
+use crate::evm::{self, MockCall, Sandbox};
+use crate::signature::{self, SignatureKind, StructDefs};
+
+// Block-state fixtures for replaying a trap offline. A fixture lists, per
+// block, the account state and external call results collect() reads; each
+// block holds only what changed since the previous one.
+//
+// Recorded fixtures are read from an archive node at each block, one entry per
+// call collect() makes, and name how in `captured_with`:
+//
+//   cast call <to> "balanceOf(address)(uint256)" <holder> --block <n> --rpc-url $RPC
+//   cast balance <address> --block <n> --rpc-url $RPC
+//   cast storage <address> <slot> --block <n> --rpc-url $RPC
+//
+// No fixtures ship with the trap examples, since capturing one needs an
+// archive node; callers pass their own to replay_trap.
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Fixture {
+    pub description: String,
+    pub network: String,
+    pub provenance: Provenance,
+    /// How the values were read (node, commands); required when recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub captured_with: Option<String>,
+    /// Trap contract the fixture was captured for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contract: Option<String>,
+    /// Appended to the creation code on every deployment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub constructor_args: Option<EncodedValues>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_sample_size: Option<u64>,
+    /// Block the exploit landed in, to measure detection against
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub incident_block: Option<u64>,
+    pub blocks: Vec<FixtureBlock>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Provenance {
+    /// Read from chain state at the listed blocks
+    Recorded,
+    /// Hand-written values shaped after the incident, not chain state
+    Illustrative,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EncodedValues {
+    /// Comma-separated ABI types, e.g. address,uint256
+    pub types: String,
+    pub values: Vec<Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FixtureBlock {
+    pub number: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub accounts: BTreeMap<String, FixtureAccount>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub calls: Vec<FixtureCall>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FixtureAccount {
+    /// Wei, decimal or 0x-hex
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub balance: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<u64>,
+    /// Runtime bytecode, 0x-hex
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    /// Slot -> value, decimal or 0x-hex
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub storage: BTreeMap<String, String>,
+}
+
+// The result of a call to an account whose code the fixture does not carry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FixtureCall {
+    pub to: String,
+    /// Function signature, e.g. balanceOf(address); without `args` it answers any arguments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub function: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub args: Option<Vec<Value>>,
+    /// Raw calldata prefix instead of `function` and `args`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub calldata: Option<String>,
+    /// Comma-separated return types, encoded from `values`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub returns: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub values: Vec<Value>,
+    /// Raw return data instead of `returns` and `values`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub returndata: Option<String>,
+    #[serde(default)]
+    pub reverts: bool,
+}
+
+struct PreparedAccount {
+    address: Address,
+    balance: Option<U256>,
+    nonce: Option<u64>,
+    code: Option<Vec<u8>>,
+    storage: Vec<(U256, U256)>,
+}
+
+struct PreparedBlock {
+    number: u64,
+    timestamp: Option<u64>,
+    accounts: Vec<PreparedAccount>,
+    calls: Vec<MockCall>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UnmockedCall {
+    pub to: String,
+    pub calldata: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BlockResult {
+    pub number: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub collect: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub collect_error: Option<String>,
+    /// Calls collect() made that neither the fixture nor real code answered
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unmocked_calls: Vec<UnmockedCall>,
+    /// Samples passed to shouldRespond; absent until the window is full
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub samples: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub should_respond: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub should_respond_error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Replay {
+    pub provenance: Provenance,
+    pub block_sample_size: u64,
+    pub incident_block: Option<u64>,
+    /// First block whose shouldRespond returned true
+    pub triggered_at: Option<u64>,
+    /// triggered_at minus incident_block
+    pub detection_delay_blocks: Option<i64>,
+    pub blocks: Vec<BlockResult>,
+    pub warnings: Vec<String>,
+}
+
+impl Fixture {
+    // Checks addresses, numbers and encodings up front, locating errors by block and entry
+    fn prepare(&self) -> Result<Vec<PreparedBlock>> {
+        if self.blocks.is_empty() {
+            bail!("fixture has no blocks");
+        }
+        if self.provenance == Provenance::Recorded && self.captured_with.as_deref().is_none_or(|how| how.trim().is_empty()) {
+            bail!("recorded fixtures must say how their values were read in 'captured_with'");
+        }
+        if self.block_sample_size == Some(0) {
+            bail!("block_sample_size must be at least 1");
+        }
+        if let Some(args) = &self.constructor_args {
+            encode_values(args).context("constructor_args")?;
+        }
+        let mut previous = None;
+        let mut blocks = Vec::with_capacity(self.blocks.len());
+        for (i, block) in self.blocks.iter().enumerate() {
+            if previous.is_some_and(|previous| block.number <= previous) {
+                bail!("blocks[{}]: block {} does not follow block {}", i, block.number, previous.unwrap_or_default());
+            }
+            previous = Some(block.number);
+            let accounts = block
+                .accounts
+                .iter()
+                .map(|(address, account)| prepare_account(address, account).with_context(|| format!("blocks[{}].accounts.{}", i, address)))
+                .collect::<Result<_>>()?;
+            let calls = block
+                .calls
+                .iter()
+                .enumerate()
+                .map(|(j, call)| prepare_call(call).with_context(|| format!("blocks[{}].calls[{}]", i, j)))
+                .collect::<Result<_>>()?;
+            blocks.push(PreparedBlock { number: block.number, timestamp: block.timestamp, accounts, calls });
+        }
+        Ok(blocks)
+    }
+
+    pub fn constructor_calldata(&self) -> Result<Vec<u8>> {
+        match &self.constructor_args {
+            Some(args) => encode_values(args).context("constructor_args"),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+fn encode_values(encoded: &EncodedValues) -> Result<Vec<u8>> {
+    let params = signature::parse_types(&encoded.types, &StructDefs::new(&[]))?;
+    abi_codec::encode(&params, &encoded.values)
+}
+
+fn parse_address(address: &str) -> Result<Address> {
+    address.parse().map_err(|_| anyhow::anyhow!("invalid address '{}'", address))
+}
+
+fn parse_u256(value: &str) -> Result<U256> {
+    value.parse().map_err(|_| anyhow::anyhow!("'{}' is not a decimal or 0x-hex integer", value))
+}
+
+fn prepare_account(address: &str, account: &FixtureAccount) -> Result<PreparedAccount> {
+    Ok(PreparedAccount {
+        address: parse_address(address)?,
+        balance: account.balance.as_deref().map(parse_u256).transpose().context("balance")?,
+        nonce: account.nonce,
+        code: account.code.as_deref().map(abi_codec::parse_hex).transpose().context("code")?,
+        storage: account
+            .storage
+            .iter()
+            .map(|(slot, value)| Ok((parse_u256(slot)?, parse_u256(value)?)))
+            .collect::<Result<_>>()
+            .context("storage")?,
+    })
+}
+
+fn prepare_call(call: &FixtureCall) -> Result<MockCall> {
+    let no_structs = StructDefs::new(&[]);
+    let calldata = match (&call.function, &call.calldata) {
+        (Some(function), None) => {
+            let parsed = signature::parse_signature(function, SignatureKind::Function, &no_structs)?;
+            let mut calldata = evm::selector(&signature::canonical_signature(&parsed.name, &parsed.inputs));
+            if let Some(args) = &call.args {
+                calldata.extend(abi_codec::encode(&parsed.inputs, args).context("args")?);
+            }
+            calldata
+        }
+        (None, Some(calldata)) if call.args.is_none() => abi_codec::parse_hex(calldata).context("calldata")?,
+        (None, Some(_)) => bail!("'args' needs 'function', not 'calldata'"),
+        (Some(_), Some(_)) => bail!("give 'function' or 'calldata', not both"),
+        (None, None) => bail!("give 'function' or 'calldata'"),
+    };
+    let returndata = match (&call.returns, &call.returndata) {
+        (Some(types), None) => encode_values(&EncodedValues { types: types.clone(), values: call.values.clone() }).context("values")?,
+        (None, Some(returndata)) if call.values.is_empty() => abi_codec::parse_hex(returndata).context("returndata")?,
+        (None, None) if call.values.is_empty() => Vec::new(),
+        (None, _) => bail!("'values' needs 'returns'"),
+        (Some(_), Some(_)) => bail!("give 'returns' or 'returndata', not both"),
+    };
+    Ok(MockCall { to: parse_address(&call.to)?, calldata, returndata, reverts: call.reverts })
+}
+
+// Runs collect() at every fixture block and shouldRespond over the newest
+// `sample_size` collected samples, as operators do once the window is full
+pub fn replay(fixture: &Fixture, code: &evm::Code, sample_size: u64) -> Result<Replay> {
+    if sample_size == 0 {
+        bail!("block_sample_size must be at least 1");
+    }
+    let blocks = fixture.prepare()?;
+    let constructor_args = fixture.constructor_calldata()?;
+    let mut warnings = Vec::new();
+    if fixture.provenance == Provenance::Illustrative {
+        warnings.push("fixture values are illustrative, not recorded chain state: the replay shows the trap's logic, not what it would have seen".to_string());
+    }
+    if matches!(code, evm::Code::Runtime(_)) && !constructor_args.is_empty() {
+        warnings.push("constructor_args are ignored for deployed_bytecode; the trap's constructor-set state is empty".to_string());
+    }
+    if (blocks.len() as u64) < sample_size {
+        warnings.push(format!("the fixture has {} blocks, fewer than block_sample_size {}: shouldRespond never runs", blocks.len(), sample_size));
+    }
+
+    let no_structs = StructDefs::new(&[]);
+    let returns = signature::parse_types("bool,bytes", &no_structs).expect("valid types");
+    let mut sandbox = Sandbox::new();
+    let mut window: VecDeque<Vec<u8>> = VecDeque::new();
+    let mut results = Vec::with_capacity(blocks.len());
+    let mut triggered_at = None;
+    for block in blocks {
+        for account in block.accounts {
+            if let Some(balance) = account.balance {
+                sandbox.set_balance(account.address, balance);
+            }
+            if let Some(nonce) = account.nonce {
+                sandbox.set_nonce(account.address, nonce);
+            }
+            if let Some(code) = account.code {
+                sandbox.set_code(account.address, code);
+            }
+            for (slot, value) in account.storage {
+                sandbox.set_storage(account.address, slot, value)?;
+            }
+        }
+        for call in block.calls {
+            sandbox.mock(call);
+        }
+        sandbox.set_block(block.number, block.timestamp);
+
+        // A fresh deployment per block, like the operator's shadow fork
+        let mut result = BlockResult {
+            number: block.number,
+            collect: None,
+            collect_error: None,
+            unmocked_calls: Vec::new(),
+            samples: None,
+            should_respond: None,
+            response: None,
+            should_respond_error: None,
+        };
+        let deployment = match code {
+            evm::Code::Creation(creation) => evm::Code::Creation([creation.as_slice(), &constructor_args].concat()),
+            evm::Code::Runtime(runtime) => evm::Code::Runtime(runtime.clone()),
+        };
+        let address = match sandbox.deploy(deployment) {
+            Ok(address) => address,
+            Err(e) => bail!("block {}: {:#}", block.number, e),
+        };
+        let collected = sandbox.call(address, evm::selector("collect()"))?;
+        result.unmocked_calls = sandbox
+            .take_misses()
+            .into_iter()
+            .map(|(to, calldata)| UnmockedCall { to: to.to_checksum(None), calldata: signature::to_hex(&calldata) })
+            .collect();
+        if !collected.success {
+            result.collect_error = collected.error;
+            results.push(result);
+            continue;
+        }
+        let sample = match abi_codec::decode(&signature::parse_types("bytes", &no_structs).expect("valid type"), &collected.output) {
+            Ok(values) => abi_codec::parse_hex(values[0].as_str().unwrap_or("0x"))?,
+            Err(e) => {
+                result.collect_error = Some(format!("collect() did not return bytes: {:#}", e));
+                results.push(result);
+                continue;
+            }
+        };
+        result.collect = Some(signature::to_hex(&sample));
+        window.push_front(sample);
+        window.truncate(sample_size as usize);
+
+        if window.len() as u64 == sample_size {
+            let samples: Vec<Vec<u8>> = window.iter().cloned().collect();
+            result.samples = Some(samples.len());
+            let outcome = sandbox.call(address, evm::should_respond_calldata(&samples))?;
+            if !outcome.success {
+                result.should_respond_error = outcome.error;
+            } else {
+                match abi_codec::decode(&returns, &outcome.output) {
+                    Ok(decoded) => {
+                        let should_respond = decoded[0].as_bool().unwrap_or_default();
+                        if should_respond && triggered_at.is_none() {
+                            triggered_at = Some(block.number);
+                        }
+                        result.should_respond = Some(should_respond);
+                        result.response = decoded[1].as_str().map(str::to_string);
+                    }
+                    Err(e) => result.should_respond_error = Some(format!("shouldRespond did not return (bool, bytes): {:#}", e)),
+                }
+            }
+        }
+        results.push(result);
+    }
+
+    if let (Some(incident), Some(triggered)) = (fixture.incident_block, triggered_at) {
+        if triggered < incident {
+            warnings.push(format!("the trap triggered at block {}, before the incident at block {}: a false positive on pre-incident state", triggered, incident));
+        }
+    }
+    Ok(Replay {
+        provenance: fixture.provenance,
+        block_sample_size: sample_size,
+        incident_block: fixture.incident_block,
+        triggered_at,
+        detection_delay_blocks: fixture.incident_block.zip(triggered_at).map(|(incident, triggered)| triggered as i64 - incident as i64),
+        blocks: results,
+        warnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // Hand-assembled trap: collect() returns DAI.balanceOf(HOLDER) and
+    // shouldRespond(data) responds when the newest sample is below data[1]
+    const DROP_TRAP: &str = "0x60ac8060095f395ff360003560e01c8063e52253811461001e576344e6e4ce1461008c575f5ffd5b7f70a08231000000000000000000000000000000000000000000000000000000005f527327182842e098f60e3d576794a5bffb0777e025d36004526020608060245f736b175474e89094c44da98b954eedeac495271d0f5afa5060205f52602060205260805160405260605ff35b604435606401356064356064013590105f5260406020525f60405260605ff3";
+    const DAI: &str = "0x6B175474E89094C44Da98b954EedeAC495271d0F";
+    const HOLDER: &str = "0x27182842E098f60e3D576794A5bFFb0777E025d3";
+
+    fn fixture(provenance: &str, balances: &[(u64, &str)]) -> Value {
+        let blocks: Vec<Value> = balances
+            .iter()
+            .map(|(number, balance)| {
+                json!({
+                    "number": number,
+                    "calls": [{
+                        "to": DAI,
+                        "function": "balanceOf(address)",
+                        "args": [HOLDER],
+                        "returns": "uint256",
+                        "values": [balance]
+                    }]
+                })
+            })
+            .collect();
+        json!({
+            "description": "DAI balance of HOLDER halving at block 102",
+            "network": "ethereum",
+            "provenance": provenance,
+            "block_sample_size": 2,
+            "incident_block": 102,
+            "blocks": blocks
+        })
+    }
+
+    fn drop_trap() -> evm::Code {
+        evm::Code::Creation(abi_codec::parse_hex(DROP_TRAP).unwrap())
+    }
+
+    #[test]
+    fn replays_to_the_triggering_block() {
+        let fixture: Fixture = serde_json::from_value(fixture("illustrative", &[(100, "1000"), (101, "1000"), (102, "500"), (103, "500")])).unwrap();
+        let replay = replay(&fixture, &drop_trap(), 2).unwrap();
+
+        assert_eq!(replay.triggered_at, Some(102));
+        assert_eq!(replay.detection_delay_blocks, Some(0));
+        let outcomes: Vec<(u64, Option<bool>)> = replay.blocks.iter().map(|b| (b.number, b.should_respond)).collect();
+        assert_eq!(outcomes, [(100, None), (101, Some(false)), (102, Some(true)), (103, Some(false))]);
+        assert_eq!(replay.blocks[2].collect.as_deref(), Some("0x00000000000000000000000000000000000000000000000000000000000001f4"));
+        assert!(replay.blocks.iter().all(|b| b.unmocked_calls.is_empty()));
+        assert_eq!(replay.warnings.len(), 1, "{:?}", replay.warnings);
+        assert!(replay.warnings[0].starts_with("fixture values are illustrative"));
+    }
+
+    #[test]
+    fn recorded_fixtures_say_how_they_were_captured() {
+        let mut value = fixture("recorded", &[(101, "1000"), (102, "500")]);
+        let fixture_without: Fixture = serde_json::from_value(value.clone()).unwrap();
+        let error = replay(&fixture_without, &drop_trap(), 2).unwrap_err();
+        assert!(error.to_string().contains("captured_with"), "{}", error);
+
+        value["captured_with"] = json!("cast call --block <n> against an archive node");
+        let fixture: Fixture = serde_json::from_value(value).unwrap();
+        let replay = replay(&fixture, &drop_trap(), 2).unwrap();
+        assert_eq!(replay.triggered_at, Some(102));
+        assert!(replay.warnings.is_empty(), "{:?}", replay.warnings);
+    }
+
+    #[test]
+    fn rejects_out_of_order_blocks() {
+        let fixture: Fixture = serde_json::from_value(fixture("illustrative", &[(101, "1000"), (100, "500")])).unwrap();
+        let error = replay(&fixture, &drop_trap(), 2).unwrap_err();
+        assert_eq!(error.to_string(), "blocks[1]: block 100 does not follow block 101");
+    }
+}
@@ -0,0 +1,202 @@
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::address_book;
+use crate::network::NetworkInfo;
+use crate::protocol::AbiParam;
+use crate::signature::{self, SignatureKind, StructDefs};
+use crate::solc_abi;
+
+// Renders a response contract for a drosera.toml `response_function`: the
+// function itself, callable only by the network's Drosera contract, emitting
+// an event with its arguments, plus a Foundry test exercising both paths.
+
+const EVENT_NAME: &str = "ResponseExecuted";
+const MAX_INDEXED: usize = 3;
+
+#[derive(Debug, Serialize)]
+pub struct ResponseScaffold {
+    pub contract: String,
+    pub network: String,
+    pub drosera_address: String,
+    /// Canonical signature for drosera.toml's response_function
+    pub response_function: String,
+    pub selector: String,
+    /// Project path -> source, laid out for compile_trap and test_trap
+    pub sources: BTreeMap<String, String>,
+}
+
+pub fn scaffold(network: &NetworkInfo, response_function: &str, contract: Option<&str>) -> Result<ResponseScaffold> {
+    let Some(drosera) = &network.drosera else {
+        bail!("no Drosera deployment is registered for network '{}'", network.name);
+    };
+    let Some(drosera_address) = address_book::to_checksum_address(&drosera.address) else {
+        bail!("Drosera address '{}' of network '{}' is not an address", drosera.address, network.name);
+    };
+
+    let mut function = signature::parse_signature(response_function, SignatureKind::Function, &StructDefs::new(&[]))?;
+    if !function.outputs.is_empty() {
+        bail!("response function '{}' declares return values; Drosera ignores them", function.name);
+    }
+    if let Some(param) = function.inputs.iter().find(|p| p.ty.starts_with("tuple")) {
+        bail!(
+            "parameter '{}' of '{}' is a tuple; scaffolding supports elementary types and arrays of them",
+            signature::canonical_type(param),
+            function.name
+        );
+    }
+    for (i, param) in function.inputs.iter_mut().enumerate() {
+        if param.name.is_empty() {
+            param.name = format!("arg{}", i);
+        }
+        // Names the generated contract and test declare themselves
+        if ["DROSERA", "response", "stranger"].contains(&param.name.as_str()) {
+            param.name.push('_');
+        }
+    }
+
+    let contract = match contract {
+        Some(name) if is_identifier(name) => name.to_string(),
+        Some(name) => bail!("contract name '{}' is not a Solidity identifier", name),
+        None => format!("{}Response", pascal_case(&function.name)),
+    };
+    let canonical = signature::canonical_signature(&function.name, &function.inputs);
+    let contract_source = render_contract(&contract, network, &drosera_address, &function.name, &function.inputs, &canonical);
+    let test_source = render_test(&contract, &function.name, &function.inputs);
+
+    Ok(ResponseScaffold {
+        sources: BTreeMap::from([
+            (format!("src/{}.sol", contract), contract_source),
+            (format!("test/{}.t.sol", contract), test_source),
+        ]),
+        contract,
+        network: network.name.clone(),
+        drosera_address,
+        selector: signature::selector(&canonical),
+        response_function: canonical,
+    })
+}
+
+fn is_identifier(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
+
+fn pascal_case(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+        None => String::new(),
+    }
+}
+
+// Event parameters: the function's, with the first address parameters indexed
+fn event_params(inputs: &[AbiParam]) -> Vec<AbiParam> {
+    let mut indexed = 0;
+    inputs
+        .iter()
+        .map(|param| {
+            let mut param = param.clone();
+            if param.ty == "address" && indexed < MAX_INDEXED {
+                param.indexed = Some(true);
+                indexed += 1;
+            }
+            param
+        })
+        .collect()
+}
+
+fn render_contract(contract: &str, network: &NetworkInfo, drosera_address: &str, function: &str, inputs: &[AbiParam], canonical: &str) -> String {
+    let args: Vec<&str> = inputs.iter().map(|p| p.name.as_str()).collect();
+    let mut source = String::new();
+    source.push_str("// SPDX-License-Identifier: MIT\n");
+    source.push_str("pragma solidity ^0.8.12;\n\n");
+    source.push_str(&format!("/// @notice Response contract for traps with response_function = \"{}\".\n", canonical));
+    source.push_str(&format!("/// @dev Only Drosera on {} may call the response function.\n", network.display_name));
+    source.push_str(&format!("contract {} {{\n", contract));
+    source.push_str(&format!("    /// @notice Drosera contract on {}, from the network registry\n", network.display_name));
+    source.push_str(&format!("    address public constant DROSERA = {};\n\n", drosera_address));
+    source.push_str(&format!("    event {};\n\n", solc_abi::event_signature(EVENT_NAME, &event_params(inputs), false)));
+    source.push_str("    error NotDrosera(address caller);\n\n");
+    source.push_str("    modifier onlyDrosera() {\n");
+    source.push_str("        if (msg.sender != DROSERA) revert NotDrosera(msg.sender);\n");
+    source.push_str("        _;\n");
+    source.push_str("    }\n\n");
+    source.push_str(&format!("    {} onlyDrosera {{\n", solc_abi::function_signature(function, inputs, &[], "nonpayable")));
+    source.push_str("        // TODO: the response itself (pause, withdraw, notify, ...)\n");
+    source.push_str(&format!("        emit {}({});\n", EVENT_NAME, args.join(", ")));
+    source.push_str("    }\n");
+    source.push_str("}\n");
+    source
+}
+
+fn render_test(contract: &str, function: &str, inputs: &[AbiParam]) -> String {
+    let mut locals = String::new();
+    for param in inputs {
+        for line in sample_declaration(param) {
+            locals.push_str(&format!("        {}\n", line));
+        }
+    }
+    let args: Vec<&str> = inputs.iter().map(|p| p.name.as_str()).collect();
+    let args = args.join(", ");
+
+    let mut source = String::new();
+    source.push_str("// SPDX-License-Identifier: MIT\n");
+    source.push_str("pragma solidity ^0.8.12;\n\n");
+    source.push_str("import {Test} from \"forge-std/Test.sol\";\n");
+    source.push_str(&format!("import {{{}}} from \"../src/{}.sol\";\n\n", contract, contract));
+    source.push_str(&format!("contract {}Test is Test {{\n", contract));
+    source.push_str(&format!("    event {};\n\n", solc_abi::event_signature(EVENT_NAME, &event_params(inputs), false)));
+    source.push_str(&format!("    {} response;\n\n", contract));
+    source.push_str("    function setUp() public {\n");
+    source.push_str(&format!("        response = new {}();\n", contract));
+    source.push_str("    }\n\n");
+    source.push_str("    function test_DroseraCanRespond() public {\n");
+    source.push_str(&locals);
+    source.push_str("        vm.expectEmit(true, true, true, true, address(response));\n");
+    source.push_str(&format!("        emit {}({});\n", EVENT_NAME, args));
+    source.push_str("        vm.prank(response.DROSERA());\n");
+    source.push_str(&format!("        response.{}({});\n", function, args));
+    source.push_str("    }\n\n");
+    source.push_str("    function test_RevertWhen_CallerIsNotDrosera() public {\n");
+    source.push_str(&locals);
+    source.push_str("        address stranger = makeAddr(\"stranger\");\n");
+    source.push_str(&format!("        vm.expectRevert(abi.encodeWithSelector({}.NotDrosera.selector, stranger));\n", contract));
+    source.push_str("        vm.prank(stranger);\n");
+    source.push_str(&format!("        response.{}({});\n", function, args));
+    source.push_str("    }\n");
+    source.push_str("}\n");
+    source
+}
+
+// Local variable declaration(s) holding a sample argument for `param`
+fn sample_declaration(param: &AbiParam) -> Vec<String> {
+    let ty = &param.ty;
+    let name = &param.name;
+    if let Some(element) = ty.strip_suffix("[]") {
+        let mut lines = vec![format!("{} memory {} = new {}(1);", ty, name, ty)];
+        if !element.ends_with(']') {
+            lines.push(format!("{}[0] = {};", name, sample_value(element)));
+        }
+        return lines;
+    }
+    if ty.ends_with(']') {
+        return vec![format!("{} memory {};", ty, name)];
+    }
+    let location = if ty == "string" || ty == "bytes" { " memory" } else { "" };
+    vec![format!("{}{} {} = {};", ty, location, name, sample_value(ty))]
+}
+
+fn sample_value(ty: &str) -> String {
+    match ty {
+        "address" => "makeAddr(\"target\")".to_string(),
+        "bool" => "true".to_string(),
+        "string" => "\"drosera\"".to_string(),
+        "bytes" => "hex\"c0ffee\"".to_string(),
+        _ if ty.starts_with("uint") => "42".to_string(),
+        _ if ty.starts_with("int") => "-42".to_string(),
+        _ if ty.starts_with("bytes") => format!("{}(keccak256(\"drosera\"))", ty),
+        _ => format!("{}(0)", ty),
+    }
+}
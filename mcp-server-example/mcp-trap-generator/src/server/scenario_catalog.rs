@@ -0,0 +1,167 @@
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashMap};
+
+use crate::address_book;
+use crate::protocol::{MonitoringScenario, NetworkData, Protocol};
+
+// Monitoring scenarios from every network file of a protocol, merged by name
+// and served as scenario://{protocol}/{name}, with their trap categories,
+// example traps, target addresses and the ABI functions and events they need.
+
+pub fn scenario_names(protocol: &Protocol) -> BTreeSet<&str> {
+    protocol
+        .networks
+        .values()
+        .flat_map(|network_data| &network_data.common_monitoring_scenarios)
+        .map(|scenario| scenario.name.as_str())
+        .collect()
+}
+
+// Target names that stand for a whole section of the network file
+fn target_matches(target: &str, entry: &address_book::AddressBookEntry) -> bool {
+    match target {
+        "popular_pools" => entry.kind == "pool",
+        "tokens" => entry.kind == "token",
+        _ => entry.role == target,
+    }
+}
+
+pub fn unresolved_targets<'a>(protocol_name: &str, protocol: &Protocol, network: &str, scenario: &'a MonitoringScenario) -> Vec<&'a str> {
+    let entries = address_book::entries(protocol_name, protocol, network);
+    scenario
+        .target_contracts
+        .iter()
+        .filter(|target| !entries.iter().any(|entry| target_matches(target, entry)))
+        .map(String::as_str)
+        .collect()
+}
+
+pub fn scenario_entry(protocol_name: &str, protocol: &Protocol, name: &str, trap_examples: &HashMap<String, Value>) -> Option<Value> {
+    let occurrences: Vec<(&String, &NetworkData, &MonitoringScenario)> = protocol
+        .networks
+        .iter()
+        .filter_map(|(network, network_data)| {
+            let scenario = network_data.common_monitoring_scenarios.iter().find(|scenario| scenario.name == name)?;
+            Some((network, network_data, scenario))
+        })
+        .collect();
+    let (_, _, first) = occurrences.first()?;
+
+    let mut categories: Vec<Value> = Vec::new();
+    let mut example_traps: Vec<&str> = Vec::new();
+    let mut targets = Vec::new();
+    let mut target_abis = BTreeSet::new();
+    for (network, network_data, scenario) in &occurrences {
+        for category_name in &scenario.categories {
+            if categories.iter().any(|c| c["name"] == category_name.as_str()) {
+                continue;
+            }
+            let category = network_data.trap_categories.get(category_name);
+            categories.push(json!({
+                "name": category_name,
+                "description": category.map(|c| &c.description),
+                "common_targets": category.map(|c| &c.common_targets),
+            }));
+            for example in category.into_iter().flat_map(|c| &c.example_traps) {
+                if !example_traps.contains(&example.as_str()) {
+                    example_traps.push(example);
+                }
+            }
+        }
+
+        let entries = address_book::entries(protocol_name, protocol, network);
+        for target in &scenario.target_contracts {
+            for entry in entries.iter().filter(|entry| target_matches(target, entry)) {
+                target_abis.extend(entry.abi.clone());
+                targets.push(json!({
+                    "target": target,
+                    "network": network,
+                    "role": entry.role,
+                    "kind": entry.kind,
+                    "address": entry.address,
+                    "abi": entry.abi,
+                }));
+            }
+        }
+    }
+
+    // A name declared by a target contract's ABI wins over the same name elsewhere in the protocol
+    let preferred = |interfaces: Vec<&String>| -> Vec<String> {
+        let from_targets: Vec<String> = interfaces.iter().filter(|i| target_abis.contains(**i)).map(|i| i.to_string()).collect();
+        if from_targets.is_empty() {
+            interfaces.into_iter().cloned().collect()
+        } else {
+            from_targets
+        }
+    };
+
+    let mut key_functions = Vec::new();
+    for function_name in &first.key_functions {
+        let declaring = protocol.abis.iter().filter(|(_, abi)| abi.functions.iter().any(|f| &f.name == function_name));
+        for interface in preferred(declaring.map(|(interface, _)| interface).collect()) {
+            for function in protocol.abis[&interface].functions.iter().filter(|f| &f.name == function_name) {
+                key_functions.push(json!({
+                    "name": function.name,
+                    "interface": interface,
+                    "signature": function.signature,
+                    "canonical_signature": function.canonical_signature,
+                    "selector": function.selector,
+                    "stateMutability": function.state_mutability,
+                    "abi_uri": format!("protocol://{}/abi/{}", protocol_name, interface),
+                    "interface_uri": format!("protocol://{}/interface/{}.sol", protocol_name, interface),
+                }));
+            }
+        }
+    }
+
+    let mut key_events = Vec::new();
+    for event_name in &first.key_events {
+        let declaring = protocol.abis.iter().filter(|(_, abi)| abi.events.iter().any(|e| &e.name == event_name));
+        for interface in preferred(declaring.map(|(interface, _)| interface).collect()) {
+            for event in protocol.abis[&interface].events.iter().filter(|e| &e.name == event_name) {
+                key_events.push(json!({
+                    "name": event.name,
+                    "interface": interface,
+                    "signature": event.signature,
+                    "topic0": event.topic0,
+                    "uri": format!("protocol://{}/events/{}", protocol_name, event.name),
+                }));
+            }
+        }
+    }
+
+    let example_traps: Vec<Value> = example_traps
+        .into_iter()
+        .map(|example| json!({
+            "name": example,
+            "uri": trap_examples.contains_key(example).then(|| format!("trap-example://{}", example)),
+        }))
+        .collect();
+
+    Some(json!({
+        "uri": format!("scenario://{}/{}", protocol_name, name),
+        "protocol": protocol_name,
+        "name": name,
+        "description": first.description,
+        "networks": occurrences.iter().map(|(network, _, _)| network).collect::<Vec<_>>(),
+        "categories": categories,
+        "example_traps": example_traps,
+        "target_contracts": targets,
+        "key_functions": key_functions,
+        "key_events": key_events,
+    }))
+}
+
+// Whether a scenario entry needs `function`, given as a name, canonical signature or selector
+pub fn uses_function(entry: &Value, function: &str) -> bool {
+    let function = function.trim();
+    entry["key_functions"].as_array().into_iter().flatten().any(|f| {
+        ["name", "canonical_signature", "selector"]
+            .iter()
+            .any(|key| f[key].as_str().is_some_and(|value| value.eq_ignore_ascii_case(function)))
+    })
+}
+
+pub fn in_category(entry: &Value, category: &str) -> bool {
+    entry["categories"].as_array().into_iter().flatten().any(|c| c["name"] == category)
+}
@@ -0,0 +1,51 @@
+use std::collections::HashSet;
+
+use crate::client_log::LogLevel;
+
+// Per-connection state. The stdio transport serves a single client, so there
+// is exactly one session for the lifetime of the process.
+pub struct Session {
+    initialized: bool,
+    log_level: LogLevel,
+    subscriptions: HashSet<String>,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            initialized: false,
+            log_level: LogLevel::Info,
+            subscriptions: HashSet::new(),
+        }
+    }
+}
+
+impl Session {
+    pub fn mark_initialized(&mut self) {
+        self.initialized = true;
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.initialized
+    }
+
+    pub fn set_log_level(&mut self, level: LogLevel) {
+        self.log_level = level;
+    }
+
+    pub fn log_level(&self) -> LogLevel {
+        self.log_level
+    }
+
+    pub fn subscribe(&mut self, uri: &str) {
+        self.subscriptions.insert(uri.to_string());
+    }
+
+    pub fn unsubscribe(&mut self, uri: &str) {
+        self.subscriptions.remove(uri);
+    }
+
+    pub fn is_subscribed(&self, uri: &str) -> bool {
+        self.subscriptions.contains(uri)
+    }
+}
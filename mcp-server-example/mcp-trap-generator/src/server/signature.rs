@@ -0,0 +1,564 @@
+use anyhow::{anyhow, bail, Result};
+use std::collections::{HashMap, HashSet};
+use tiny_keccak::{Hasher, Keccak};
+
+use crate::protocol::{AbiFile, AbiParam, AbiStruct};
+
+// Parser for human-readable Solidity signatures as stored in protocol ABIs:
+//   function getPool(address tokenA, address tokenB, uint24 fee) external view returns (address pool)
+//   Swap(address indexed sender, address indexed recipient, int256 amount0, ...)
+//   error InsufficientLiquidity(uint256 available)
+// Struct parameters (`ExactInputSingleParams calldata params`) are resolved
+// through the `structs` section of the ABI file; contract, interface and enum
+// parameters only when their names are known, as from trap sources.
+
+const MAX_STRUCT_DEPTH: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureKind {
+    Function,
+    Event,
+    Error,
+}
+
+impl SignatureKind {
+    fn keyword(&self) -> &'static str {
+        match self {
+            SignatureKind::Function => "function",
+            SignatureKind::Event => "event",
+            SignatureKind::Error => "error",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ParsedSignature {
+    pub name: String,
+    pub inputs: Vec<AbiParam>,
+    pub outputs: Vec<AbiParam>,
+    pub state_mutability: Option<String>,
+    pub anonymous: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            '(' => tokens.push(Token::LParen),
+            ')' => tokens.push(Token::RParen),
+            '[' => tokens.push(Token::LBracket),
+            ']' => tokens.push(Token::RBracket),
+            ',' => tokens.push(Token::Comma),
+            ';' => {}
+            c if c.is_whitespace() => {}
+            c if c.is_ascii_alphanumeric() || c == '_' || c == '$' || c == '.' => {
+                let mut ident = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_ascii_alphanumeric() || c == '_' || c == '$' || c == '.' {
+                        ident.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                tokens.push(Token::Ident(ident));
+                continue;
+            }
+            other => bail!("unexpected character '{}'", other),
+        }
+        chars.next();
+    }
+
+    Ok(tokens)
+}
+
+// Struct name -> member list, parsed lazily while resolving types, plus the
+// contract, interface and enum names known to be in scope
+pub struct StructDefs<'a> {
+    defs: HashMap<&'a str, &'a str>,
+    /// Encoded as address
+    contracts: HashSet<&'a str>,
+    /// Encoded as uint8
+    enums: HashSet<&'a str>,
+}
+
+impl<'a> StructDefs<'a> {
+    pub fn new(structs: &'a [AbiStruct]) -> Self {
+        Self {
+            defs: structs.iter().map(|s| (s.name.as_str(), s.fields.as_str())).collect(),
+            contracts: HashSet::new(),
+            enums: HashSet::new(),
+        }
+    }
+
+    pub fn with_names(mut self, contracts: &'a [String], enums: &'a [String]) -> Self {
+        self.contracts.extend(contracts.iter().map(String::as_str));
+        self.enums.extend(enums.iter().map(String::as_str));
+        self
+    }
+
+    fn resolve(&self, name: &str, depth: usize) -> Result<Vec<AbiParam>> {
+        // Qualified names (ISwapRouter.ExactInputSingleParams) resolve by their last segment
+        let short_name = name.rsplit('.').next().unwrap_or(name);
+        let fields = self.defs.get(short_name).ok_or_else(|| {
+            anyhow!(
+                "unknown type '{}' (not an elementary type or a known struct; write contract and interface types as address and enums as uint8)",
+                name
+            )
+        })?;
+        if depth >= MAX_STRUCT_DEPTH {
+            bail!("struct '{}' is nested too deeply or recursive", name);
+        }
+        let mut parser = Parser { tokens: tokenize(fields)?, pos: 0, structs: self, depth: depth + 1 };
+        let members = parser.params(None)?;
+        if parser.pos != parser.tokens.len() {
+            bail!("unexpected tokens in members of struct '{}'", name);
+        }
+        Ok(members)
+    }
+}
+
+struct Parser<'a, 'b> {
+    tokens: Vec<Token>,
+    pos: usize,
+    structs: &'b StructDefs<'a>,
+    depth: usize,
+}
+
+impl Parser<'_, '_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_ident(&self) -> Option<&str> {
+        match self.peek() {
+            Some(Token::Ident(ident)) => Some(ident),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, token: Token) -> Result<()> {
+        match self.tokens.get(self.pos) {
+            Some(t) if *t == token => {
+                self.pos += 1;
+                Ok(())
+            }
+            other => bail!("expected {:?}, found {:?}", token, other),
+        }
+    }
+
+    fn ident(&mut self) -> Result<String> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Ident(ident)) => {
+                self.pos += 1;
+                Ok(ident.clone())
+            }
+            other => bail!("expected identifier, found {:?}", other),
+        }
+    }
+
+    // Parses `(param, param, ...)` when `open` is Some, or a bare comma-separated list otherwise
+    fn params(&mut self, open: Option<Token>) -> Result<Vec<AbiParam>> {
+        let delimited = open.is_some();
+        if let Some(open) = open {
+            self.expect(open)?;
+        }
+
+        let mut params = Vec::new();
+        loop {
+            match self.peek() {
+                Some(Token::RParen) if delimited => {
+                    self.pos += 1;
+                    break;
+                }
+                None if !delimited => break,
+                None => bail!("unterminated parameter list"),
+                _ => {}
+            }
+            params.push(self.param()?);
+            match self.peek() {
+                Some(Token::Comma) => self.pos += 1,
+                Some(Token::RParen) if delimited => {}
+                None if !delimited => {}
+                other => bail!("expected ',' or ')', found {:?}", other),
+            }
+        }
+        Ok(params)
+    }
+
+    fn param(&mut self) -> Result<AbiParam> {
+        // `tuple(...)` as written in canonical ABI types is the same as `(...)`
+        if self.peek_ident() == Some("tuple") && self.tokens.get(self.pos + 1) == Some(&Token::LParen) {
+            self.pos += 1;
+        }
+        let mut param = if self.peek() == Some(&Token::LParen) {
+            let components = self.params(Some(Token::LParen))?;
+            AbiParam { name: String::new(), ty: "tuple".to_string(), internal_type: None, components, indexed: None }
+        } else {
+            let type_name = self.ident()?;
+            self.resolve_type(&type_name)?
+        };
+
+        // Array suffixes: uint256[], address[3], Params[][2]
+        while self.peek() == Some(&Token::LBracket) {
+            self.pos += 1;
+            let mut suffix = String::from("[");
+            if let Some(size) = self.peek_ident() {
+                if !size.chars().all(|c| c.is_ascii_digit()) {
+                    bail!("invalid array size '{}'", size);
+                }
+                suffix.push_str(size);
+                self.pos += 1;
+            }
+            self.expect(Token::RBracket)?;
+            suffix.push(']');
+            param.ty.push_str(&suffix);
+            if let Some(internal_type) = &mut param.internal_type {
+                internal_type.push_str(&suffix);
+            }
+        }
+
+        // Modifiers and the optional parameter name
+        while let Some(ident) = self.peek_ident() {
+            match ident {
+                "memory" | "calldata" | "storage" | "payable" => self.pos += 1,
+                "indexed" => {
+                    param.indexed = Some(true);
+                    self.pos += 1;
+                }
+                _ => {
+                    param.name = ident.to_string();
+                    self.pos += 1;
+                    break;
+                }
+            }
+        }
+
+        Ok(param)
+    }
+
+    fn resolve_type(&self, type_name: &str) -> Result<AbiParam> {
+        let elementary = |ty: &str| AbiParam {
+            name: String::new(),
+            ty: ty.to_string(),
+            internal_type: None,
+            components: Vec::new(),
+            indexed: None,
+        };
+
+        match type_name {
+            "uint" => return Ok(elementary("uint256")),
+            "int" => return Ok(elementary("int256")),
+            "byte" => return Ok(elementary("bytes1")),
+            "address" | "bool" | "string" | "bytes" => return Ok(elementary(type_name)),
+            _ => {}
+        }
+        for (prefix, max_bits, step) in [("uint", 256, 8), ("int", 256, 8), ("bytes", 32, 1)] {
+            if let Some(size) = type_name.strip_prefix(prefix).and_then(|s| s.parse::<u32>().ok()) {
+                if size == 0 || size > max_bits || size % step != 0 {
+                    bail!("invalid type '{}'", type_name);
+                }
+                return Ok(elementary(type_name));
+            }
+        }
+
+        // Contracts and interfaces are addresses and enums uint8 in the ABI,
+        // unless a struct of the same name is in scope
+        let short_name = type_name.rsplit('.').next().unwrap_or(type_name);
+        if !self.structs.defs.contains_key(short_name) {
+            let alias = if self.structs.contracts.contains(short_name) {
+                Some(("address", "contract"))
+            } else if self.structs.enums.contains(short_name) {
+                Some(("uint8", "enum"))
+            } else {
+                None
+            };
+            if let Some((ty, kind)) = alias {
+                return Ok(AbiParam { internal_type: Some(format!("{} {}", kind, short_name)), ..elementary(ty) });
+            }
+        }
+
+        let components = self.structs.resolve(type_name, self.depth)?;
+        Ok(AbiParam {
+            name: String::new(),
+            ty: "tuple".to_string(),
+            internal_type: Some(format!("struct {}", short_name)),
+            components,
+            indexed: None,
+        })
+    }
+}
+
+pub fn parse_signature(signature: &str, kind: SignatureKind, structs: &StructDefs) -> Result<ParsedSignature> {
+    let mut parser = Parser { tokens: tokenize(signature)?, pos: 0, structs, depth: 0 };
+
+    if parser.peek_ident() == Some(kind.keyword()) {
+        parser.pos += 1;
+    }
+    let name = parser.ident()?;
+    let inputs = parser.params(Some(Token::LParen))?;
+
+    let mut parsed = ParsedSignature {
+        name,
+        inputs,
+        outputs: Vec::new(),
+        state_mutability: None,
+        anonymous: false,
+    };
+
+    while parser.pos < parser.tokens.len() {
+        let modifier = parser.ident()?;
+        match modifier.as_str() {
+            "returns" if kind == SignatureKind::Function => {
+                parsed.outputs = parser.params(Some(Token::LParen))?;
+            }
+            "view" | "pure" | "payable" | "nonpayable" if kind == SignatureKind::Function => {
+                parsed.state_mutability = Some(modifier);
+            }
+            "external" | "public" | "virtual" | "override" if kind == SignatureKind::Function => {}
+            "anonymous" if kind == SignatureKind::Event => parsed.anonymous = true,
+            other => bail!("unexpected '{}' in {} signature", other, kind.keyword()),
+        }
+    }
+
+    if kind == SignatureKind::Function && parsed.state_mutability.is_none() {
+        parsed.state_mutability = Some("nonpayable".to_string());
+    }
+    Ok(parsed)
+}
+
+// A comma-separated type list such as `(address,uint256)[], bool` or `FeeData[]`
+pub fn parse_types(types: &str, structs: &StructDefs) -> Result<Vec<AbiParam>> {
+    let mut parser = Parser { tokens: tokenize(types)?, pos: 0, structs, depth: 0 };
+    let params = parser.params(None)?;
+    if parser.pos != parser.tokens.len() {
+        bail!("unexpected tokens in type list '{}'", types);
+    }
+    Ok(params)
+}
+
+// Canonical ABI type as used in selectors: tuples expand to their components
+pub fn canonical_type(param: &AbiParam) -> String {
+    match param.ty.strip_prefix("tuple") {
+        Some(array_suffix) => {
+            let members: Vec<String> = param.components.iter().map(canonical_type).collect();
+            format!("({}){}", members.join(","), array_suffix)
+        }
+        None => param.ty.clone(),
+    }
+}
+
+pub fn canonical_signature(name: &str, inputs: &[AbiParam]) -> String {
+    let types: Vec<String> = inputs.iter().map(canonical_type).collect();
+    format!("{}({})", name, types.join(","))
+}
+
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak::v256();
+    hasher.update(data);
+    let mut output = [0u8; 32];
+    hasher.finalize(&mut output);
+    output
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(2 + bytes.len() * 2);
+    hex.push_str("0x");
+    for byte in bytes {
+        hex.push_str(&format!("{:02x}", byte));
+    }
+    hex
+}
+
+pub fn selector(canonical_signature: &str) -> String {
+    to_hex(&keccak256(canonical_signature.as_bytes())[..4])
+}
+
+pub fn topic(canonical_signature: &str) -> String {
+    to_hex(&keccak256(canonical_signature.as_bytes()))
+}
+
+// Fills in typed parameters parsed from human-readable signatures (where the
+// ABI did not already carry them) and computes canonical signatures,
+// selectors and event topics. Returns the entries that could not be resolved.
+pub fn annotate_abi(abi: &mut AbiFile) -> Vec<(String, anyhow::Error)> {
+    let mut problems = Vec::new();
+    let structs = abi.structs.clone();
+    let structs = StructDefs::new(&structs);
+
+    for function in &mut abi.functions {
+        if function.inputs.is_empty() && function.outputs.is_empty() {
+            match parse_signature(&function.signature, SignatureKind::Function, &structs) {
+                Ok(parsed) if parsed.name != function.name => {
+                    problems.push((
+                        format!("function {}", function.name),
+                        anyhow!("signature declares '{}' instead", parsed.name),
+                    ));
+                    continue;
+                }
+                Ok(parsed) => {
+                    function.inputs = parsed.inputs;
+                    function.outputs = parsed.outputs;
+                    function.state_mutability = function.state_mutability.take().or(parsed.state_mutability);
+                }
+                Err(e) => {
+                    problems.push((format!("function {}", function.name), e));
+                    continue;
+                }
+            }
+        }
+        let canonical = canonical_signature(&function.name, &function.inputs);
+        function.selector = Some(selector(&canonical));
+        function.canonical_signature = Some(canonical);
+    }
+
+    for event in &mut abi.events {
+        if event.inputs.is_empty() {
+            match parse_signature(&event.signature, SignatureKind::Event, &structs) {
+                Ok(parsed) => {
+                    event.inputs = parsed.inputs;
+                    event.anonymous |= parsed.anonymous;
+                }
+                Err(e) => {
+                    problems.push((format!("event {}", event.name), e));
+                    continue;
+                }
+            }
+        }
+        let canonical = canonical_signature(&event.name, &event.inputs);
+        event.topic0 = Some(topic(&canonical));
+        event.canonical_signature = Some(canonical);
+    }
+
+    for error in &mut abi.errors {
+        if error.inputs.is_empty() {
+            match parse_signature(&error.signature, SignatureKind::Error, &structs) {
+                Ok(parsed) => error.inputs = parsed.inputs,
+                Err(e) => {
+                    problems.push((format!("error {}", error.name), e));
+                    continue;
+                }
+            }
+        }
+        let canonical = canonical_signature(&error.name, &error.inputs);
+        error.selector = Some(selector(&canonical));
+        error.canonical_signature = Some(canonical);
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exact_input_single_params() -> Vec<AbiStruct> {
+        vec![AbiStruct {
+            name: "ExactInputSingleParams".to_string(),
+            fields: "address tokenIn, address tokenOut, uint24 fee, address recipient, uint256 deadline, uint256 amountIn, uint256 amountOutMinimum, uint160 sqrtPriceLimitX96".to_string(),
+        }]
+    }
+
+    fn canonical(signature: &str, kind: SignatureKind, structs: &StructDefs) -> String {
+        let parsed = parse_signature(signature, kind, structs).expect("signature parses");
+        canonical_signature(&parsed.name, &parsed.inputs)
+    }
+
+    #[test]
+    fn function_selectors() {
+        let no_structs = StructDefs::new(&[]);
+        let get_pool = canonical("function getPool(address tokenA, address tokenB, uint24 fee) external view returns (address pool)", SignatureKind::Function, &no_structs);
+        assert_eq!(get_pool, "getPool(address,address,uint24)");
+        assert_eq!(selector(&get_pool), "0x1698ee82");
+        assert_eq!(selector("transfer(address,uint256)"), "0xa9059cbb");
+
+        let structs = exact_input_single_params();
+        let exact_input_single = canonical(
+            "function exactInputSingle(ISwapRouter.ExactInputSingleParams calldata params) external payable returns (uint256 amountOut)",
+            SignatureKind::Function,
+            &StructDefs::new(&structs),
+        );
+        assert_eq!(exact_input_single, "exactInputSingle((address,address,uint24,address,uint256,uint256,uint256,uint160))");
+        assert_eq!(selector(&exact_input_single), "0x414bf389");
+    }
+
+    #[test]
+    fn event_topics() {
+        let no_structs = StructDefs::new(&[]);
+        let swap = canonical(
+            "event Swap(address indexed sender, address indexed recipient, int256 amount0, int256 amount1, uint160 sqrtPriceX96, uint128 liquidity, int24 tick)",
+            SignatureKind::Event,
+            &no_structs,
+        );
+        assert_eq!(swap, "Swap(address,address,int256,int256,uint160,uint128,int24)");
+        assert_eq!(topic(&swap), "0xc42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8004e115fbcca67");
+        assert_eq!(topic("Transfer(address,address,uint256)"), "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef");
+    }
+
+    #[test]
+    fn parses_modifiers_names_and_outputs() {
+        let parsed = parse_signature(
+            "function quote(uint[] memory amounts, bytes32[2] calldata keys, (address, bool)[] routes) external view returns (uint256 out, bytes memory)",
+            SignatureKind::Function,
+            &StructDefs::new(&[]),
+        )
+        .unwrap();
+        assert_eq!(parsed.name, "quote");
+        let inputs: Vec<(&str, String)> = parsed.inputs.iter().map(|p| (p.name.as_str(), canonical_type(p))).collect();
+        assert_eq!(inputs, [("amounts", "uint256[]".to_string()), ("keys", "bytes32[2]".to_string()), ("routes", "(address,bool)[]".to_string())]);
+        let outputs: Vec<(&str, &str)> = parsed.outputs.iter().map(|p| (p.name.as_str(), p.ty.as_str())).collect();
+        assert_eq!(outputs, [("out", "uint256"), ("", "bytes")]);
+        assert_eq!(parsed.state_mutability.as_deref(), Some("view"));
+
+        let event = parse_signature("Deposit(address indexed user, uint256 amount) anonymous", SignatureKind::Event, &StructDefs::new(&[])).unwrap();
+        assert_eq!(event.inputs[0].indexed, Some(true));
+        assert_eq!(event.inputs[1].indexed, None);
+        assert!(event.anonymous);
+
+        let default_mutability = parse_signature("pause()", SignatureKind::Function, &StructDefs::new(&[])).unwrap();
+        assert_eq!(default_mutability.state_mutability.as_deref(), Some("nonpayable"));
+    }
+
+    #[test]
+    fn parses_canonical_tuple_types() {
+        let params = parse_types("tuple(uint256,address)[], bool", &StructDefs::new(&[])).unwrap();
+        let types: Vec<String> = params.iter().map(canonical_type).collect();
+        assert_eq!(types, ["(uint256,address)[]", "bool"]);
+    }
+
+    #[test]
+    fn contract_and_enum_types_need_known_names() {
+        let unknown = parse_signature("f(IERC20 token)", SignatureKind::Function, &StructDefs::new(&[])).unwrap_err();
+        assert!(unknown.to_string().contains("write contract and interface types as address"), "{}", unknown);
+
+        let contracts = vec!["IERC20".to_string()];
+        let enums = vec!["Status".to_string()];
+        let defs = StructDefs::new(&[]).with_names(&contracts, &enums);
+        let parsed = parse_signature("f(IERC20[] tokens, Pool.Status status)", SignatureKind::Function, &defs).unwrap();
+        assert_eq!(canonical_signature(&parsed.name, &parsed.inputs), "f(address[],uint8)");
+        assert_eq!(parsed.inputs[0].internal_type.as_deref(), Some("contract IERC20[]"));
+        assert_eq!(parsed.inputs[1].internal_type.as_deref(), Some("enum Status"));
+    }
+
+    #[test]
+    fn rejects_invalid_types() {
+        let no_structs = StructDefs::new(&[]);
+        for types in ["uint7", "uint264", "bytes33", "int0", "uint256[x]", "(uint256", "uint256 a b c"] {
+            assert!(parse_types(types, &no_structs).is_err(), "{} should not parse", types);
+        }
+        let recursive = vec![AbiStruct { name: "Node".to_string(), fields: "Node next".to_string() }];
+        assert!(parse_types("Node", &StructDefs::new(&recursive)).is_err());
+    }
+}
@@ -1,44 +1,162 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
+use serde::de::DeserializeOwned;
 use serde_json::{json, Value};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
-use std::io::{self, BufRead, Write};
-use std::path::Path;
-use tracing::info;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::{mpsc, Mutex, RwLock};
+use tracing::{info, warn};
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+mod abi_codec;
+mod address_book;
+mod cli;
+mod client_log;
+mod deployments;
+mod drosera_toml;
+mod event_catalog;
+mod evm;
+mod exploit_port;
+mod foundry;
+mod load_report;
+mod network;
+mod protocol;
+mod protocol_import;
+mod replay;
+mod response_scaffold;
+mod scenario_catalog;
+mod session;
+mod signature;
+mod solc_abi;
+mod solidity_interface;
+mod storage_layout;
+mod tools;
+mod trap_analysis;
+mod watcher;
+
+use cli::Command;
+use client_log::{ClientLogLayer, LogLevel};
+use deployments::DroseraDeployments;
+use load_report::LoadReport;
+use network::NetworkRegistry;
+use protocol::{DataFileKind, Protocol};
+use session::Session;
+
+const DEPLOYMENTS_PATH: &str = "src/data/drosera-context/website/docs/pages/deployments.md";
 
 // Simple MCP server implementation that works with stdio
 // This implements the basic MCP protocol directly
 
 struct DroseraServer {
-    protocols: HashMap<String, Value>,
+    networks: NetworkRegistry,
+    deployments: Option<DroseraDeployments>,
+    protocols: HashMap<String, Protocol>,
     drosera_context: HashMap<String, Value>,
+    // Path under drosera-lib/ -> vendored Solidity source
+    drosera_lib: BTreeMap<String, String>,
     trap_examples: HashMap<String, Value>,
     prompts: HashMap<String, String>,
     index: Option<Value>,
+    // Backing file -> resource URIs served from it, used for update notifications
+    resource_files: HashMap<PathBuf, HashSet<String>>,
+    load_report: LoadReport,
 }
 
 impl DroseraServer {
     fn new() -> Result<Self> {
         let mut server = Self {
+            networks: NetworkRegistry::default(),
+            deployments: None,
             protocols: HashMap::new(),
             drosera_context: HashMap::new(),
+            drosera_lib: BTreeMap::new(),
             trap_examples: HashMap::new(),
             prompts: HashMap::new(),
             index: None,
+            resource_files: HashMap::new(),
+            load_report: LoadReport::default(),
         };
-        server.load_protocols()?;
-        server.load_drosera_context()?;
-        server.load_trap_examples()?;
-        server.load_prompts()?;
-        server.load_index()?;
+        
+        // A failure here means a whole data directory could not be read.
+        // Networks come first: protocol data is keyed by canonical network name.
+        if let Err(e) = server.load_networks() {
+            server.load_report.record(Path::new("src/data/networks.json"), e, "network registry");
+        }
+        if let Err(e) = server.load_deployments() {
+            server.load_report.record(Path::new(DEPLOYMENTS_PATH), e, "structured Drosera deployments");
+        }
+        if let Err(e) = server.load_protocols() {
+            server.load_report.record(Path::new("src/data/protocols"), e, "remaining protocols");
+        }
+        if let Err(e) = server.load_drosera_context() {
+            server.load_report.record(Path::new("src/data/drosera-context"), e, "remaining documentation");
+        }
+        if let Err(e) = server.load_drosera_lib(Path::new(foundry::DROSERA_LIB_DIR)) {
+            server.load_report.record(Path::new(foundry::DROSERA_LIB_DIR), e, "remaining vendored Drosera contracts");
+        }
+        if let Err(e) = server.load_trap_examples() {
+            server.load_report.record(Path::new("src/data/trap-examples"), e, "remaining trap examples");
+        }
+        if let Err(e) = server.load_prompts() {
+            server.load_report.record(Path::new("src/data/prompts"), e, "generate-trap prompt");
+        }
+        if let Err(e) = server.load_index() {
+            server.load_report.record(Path::new("src/data/index.json"), e, "context index");
+        }
         Ok(server)
     }
 
+    fn load_networks(&mut self) -> Result<()> {
+        let networks_path = Path::new("src/data/networks.json");
+
+        if !networks_path.exists() {
+            warn!("Network registry not found");
+            return Ok(());
+        }
+
+        if let Some(networks) = self.read_data_file_as(networks_path, "network registry") {
+            self.networks = NetworkRegistry::new(networks);
+            let names: Vec<String> = self.networks.names().iter().map(|name| name.to_string()).collect();
+            for name in &names {
+                self.track_file(networks_path, &format!("network://{}", name));
+            }
+            info!("Loaded {} networks", names.len());
+        }
+
+        Ok(())
+    }
+
+    fn load_deployments(&mut self) -> Result<()> {
+        let deployments_path = Path::new(DEPLOYMENTS_PATH);
+
+        if !deployments_path.exists() {
+            warn!("Drosera deployments page not found");
+            return Ok(());
+        }
+
+        let mut deployments = deployments::parse(&fs::read_to_string(deployments_path)?)?;
+        for mismatch in self.networks.apply_deployments(&mut deployments, DEPLOYMENTS_PATH) {
+            self.load_report.record(Path::new("src/data/networks.json"), mismatch, "nothing (deployments.md values used)");
+        }
+        self.track_file(deployments_path, "drosera://deployments.json");
+        for network in deployments.networks.iter().filter_map(|section| section.network.clone()) {
+            self.track_file(deployments_path, &format!("network://{}", network));
+        }
+        self.deployments = Some(deployments);
+
+        Ok(())
+    }
+
     fn load_protocols(&mut self) -> Result<()> {
         let protocols_dir = Path::new("src/data/protocols");
         
         if !protocols_dir.exists() {
-            info!("Protocols directory not found");
+            warn!("Protocols directory not found");
             return Ok(());
         }
 
@@ -50,8 +168,11 @@ impl DroseraServer {
             if path.is_dir() {
                 if let Some(protocol_name) = path.file_name().and_then(|s| s.to_str()) {
                     info!("Loading protocol: {}", protocol_name);
-                    if let Ok(protocol_data) = self.load_structured_protocol(&path) {
-                        self.protocols.insert(protocol_name.to_string(), protocol_data);
+                    match self.load_structured_protocol(protocol_name, &path) {
+                        Ok(protocol_data) => {
+                            self.protocols.insert(protocol_name.to_string(), protocol_data);
+                        }
+                        Err(e) => self.load_report.record(&path, e, format!("protocol {}", protocol_name)),
                     }
                 }
             }
@@ -60,56 +181,165 @@ impl DroseraServer {
         Ok(())
     }
 
-    fn load_structured_protocol(&self, protocol_dir: &Path) -> Result<Value> {
-        let mut protocol_data = json!({
-            "networks": {},
-            "abis": {}
-        });
+    fn load_structured_protocol(&mut self, protocol_name: &str, protocol_dir: &Path) -> Result<Protocol> {
+        let mut protocol_data = Protocol::default();
+        let mut abi_files = Vec::new();
+        let mut misc_data_files = Vec::new();
         
-        // Load ABIs
+        // Load ABIs (custom format, solc ABI arrays or build artifacts)
         let abis_dir = protocol_dir.join("abis");
         if abis_dir.exists() {
-            let mut abis = json!({});
-            for entry in fs::read_dir(&abis_dir)? {
-                let entry = entry?;
-                let path = entry.path();
-                if path.extension().and_then(|s| s.to_str()) == Some("json") {
-                    if let Some(interface) = path.file_stem().and_then(|s| s.to_str()) {
-                        let content = fs::read_to_string(&path)?;
-                        let abi_data: Value = serde_json::from_str(&content)?;
-                        abis[interface] = abi_data;
+            for path in protocol::abi_file_paths(&abis_dir)? {
+                if let Some(interface) = path.file_stem().and_then(|s| s.to_str()) {
+                    match protocol::read_abi_file(&path) {
+                        Ok(mut abi_data) => {
+                            for (item, e) in signature::annotate_abi(&mut abi_data) {
+                                let skipped = format!("selector for {} in ABI {} of protocol {}", item, interface, protocol_name);
+                                self.load_report.record(&path, e, skipped);
+                            }
+                            protocol_data.abis.insert(interface.to_string(), abi_data);
+                            abi_files.push((path.clone(), interface.to_string()));
+                        }
+                        Err(e) => {
+                            let skipped = format!("ABI {} of protocol {}", interface, protocol_name);
+                            self.load_report.record(&path, e, skipped);
+                        }
                     }
                 }
             }
-            protocol_data["abis"] = abis;
         }
         
         // Load misc_data (which now contains all protocol info per network)
         let misc_data_dir = protocol_dir.join("misc_data");
         if misc_data_dir.exists() {
-            let mut networks = json!({});
             for entry in fs::read_dir(&misc_data_dir)? {
                 let entry = entry?;
                 let path = entry.path();
                 if path.extension().and_then(|s| s.to_str()) == Some("json") {
-                    if let Some(network) = path.file_stem().and_then(|s| s.to_str()) {
-                        let content = fs::read_to_string(&path)?;
-                        let network_data: Value = serde_json::from_str(&content)?;
-                        networks[network] = network_data;
+                    if let Some(file_network) = path.file_stem().and_then(|s| s.to_str()) {
+                        let skipped = format!("{} network data of protocol {}", file_network, protocol_name);
+                        if let Some(network_data) = self.read_data_file_as::<protocol::NetworkData>(&path, skipped) {
+                            let network = self.check_network(&path, file_network, network_data.chain_id);
+                            for scenario in &network_data.common_monitoring_scenarios {
+                                self.track_file(&path, &format!("scenario://{}/{}", protocol_name, scenario.name));
+                            }
+                            protocol_data.networks.insert(network.clone(), network_data);
+                            self.track_file(&path, &format!("protocol://{}/misc_data/{}", protocol_name, network));
+                            self.track_file(&path, &format!("address://{}/{}", network, protocol_name));
+                            misc_data_files.push((path.clone(), network));
+                        }
+                    }
+                }
+            }
+        }
+
+        // Optional extra deployments (with deployment blocks) for the address book
+        let addresses_path = protocol_dir.join("addresses.json");
+        if addresses_path.exists() {
+            let skipped = format!("addresses.json of protocol {}", protocol_name);
+            if let Some(addresses) = self.read_data_file_as::<protocol::AddressesFile>(&addresses_path, skipped) {
+                for (network, records) in addresses {
+                    let network = self.networks.canonical_name(&network);
+                    self.track_file(&addresses_path, &format!("address://{}/{}", network, protocol_name));
+                    protocol_data.addresses.entry(network).or_default().extend(records);
+                }
+            }
+        }
+
+        // Scenarios link to ABIs and to addresses.json roles, so check them once both are in
+        for (path, network) in misc_data_files {
+            self.check_scenarios(&path, protocol_name, &protocol_data, &network);
+        }
+        
+        // Optional solc storage layouts for the storage_slot tool
+        let storage_layout_dir = protocol_dir.join("storage_layout");
+        if storage_layout_dir.exists() {
+            for entry in fs::read_dir(&storage_layout_dir)? {
+                let path = entry?.path();
+                if path.extension().and_then(|s| s.to_str()) != Some("json") {
+                    continue;
+                }
+                let Some(contract) = path.file_stem().and_then(|s| s.to_str()).map(str::to_string) else {
+                    continue;
+                };
+                match protocol::read_storage_layout(&path) {
+                    Ok(layout) => {
+                        self.track_file(&path, &format!("protocol://{}/storage_layout/{}", protocol_name, contract));
+                        protocol_data.storage_layouts.insert(contract, layout);
+                    }
+                    Err(e) => {
+                        let skipped = format!("storage layout {} of protocol {}", contract, protocol_name);
+                        self.load_report.record(&path, e, skipped);
                     }
                 }
             }
-            protocol_data["networks"] = networks;
+        }
+
+        // misc_data responses embed every ABI, so an ABI change updates those too;
+        // scenarios resolve their key functions and events against the ABIs
+        let network_names: Vec<String> = protocol_data.networks.keys().cloned().collect();
+        let scenario_names: Vec<String> = scenario_catalog::scenario_names(&protocol_data).into_iter().map(str::to_string).collect();
+        for (path, interface) in abi_files {
+            self.track_file(&path, &format!("protocol://{}/abi/{}", protocol_name, interface));
+            self.track_file(&path, &format!("protocol://{}/interface/{}.sol", protocol_name, interface));
+            for event in &protocol_data.abis[&interface].events {
+                self.track_file(&path, &format!("protocol://{}/events/{}", protocol_name, event.name));
+            }
+            for network in &network_names {
+                self.track_file(&path, &format!("protocol://{}/misc_data/{}", protocol_name, network));
+            }
+            for scenario in &scenario_names {
+                self.track_file(&path, &format!("scenario://{}/{}", protocol_name, scenario));
+            }
         }
         
         Ok(protocol_data)
     }
 
+    // Monitoring scenarios name their events, functions, categories and targets
+    // bare; each must resolve for the scenario:// entry to link it
+    fn check_scenarios(&mut self, path: &Path, protocol_name: &str, protocol_data: &Protocol, network: &str) {
+        let network_data = &protocol_data.networks[network];
+        let known_events = event_catalog::event_names(protocol_data);
+        for scenario in &network_data.common_monitoring_scenarios {
+            for event in scenario.key_events.iter().filter(|event| !known_events.contains(event.as_str())) {
+                self.load_report.record(
+                    path,
+                    format!("key event {} of scenario {} is not declared in any ABI", event, scenario.name),
+                    "nothing (no event catalog entry to link)",
+                );
+            }
+            for function in &scenario.key_functions {
+                if !protocol_data.abis.values().any(|abi| abi.functions.iter().any(|f| &f.name == function)) {
+                    self.load_report.record(
+                        path,
+                        format!("key function {} of scenario {} is not declared in any ABI", function, scenario.name),
+                        "nothing (no ABI function to link)",
+                    );
+                }
+            }
+            for category in scenario.categories.iter().filter(|c| !network_data.trap_categories.contains_key(*c)) {
+                self.load_report.record(
+                    path,
+                    format!("category {} of scenario {} is not in trap_categories", category, scenario.name),
+                    "nothing (no example traps to link)",
+                );
+            }
+            for target in scenario_catalog::unresolved_targets(protocol_name, protocol_data, network, scenario) {
+                self.load_report.record(
+                    path,
+                    format!("target {} of scenario {} has no address on {}", target, scenario.name, network),
+                    "nothing (no address to link)",
+                );
+            }
+        }
+    }
+
     fn load_drosera_context(&mut self) -> Result<()> {
         let context_dir = Path::new("src/data/drosera-context");
         
         if !context_dir.exists() {
-            info!("Drosera context directory not found");
+            warn!("Drosera context directory not found");
             return Ok(());
         }
 
@@ -121,12 +351,14 @@ impl DroseraServer {
                 
                 if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("md") {
                     if let Some(name) = path.file_stem().and_then(|s| s.to_str()) {
-                        let content = fs::read_to_string(&path)?;
-                        self.drosera_context.insert(name.to_string(), json!({
-                            "type": "documentation",
-                            "content": content,
-                            "path": path.to_string_lossy()
-                        }));
+                        if let Some(content) = self.read_data_file(&path, format!("documentation {}", name)) {
+                            self.track_file(&path, &format!("drosera://{}", name));
+                            self.drosera_context.insert(name.to_string(), json!({
+                                "type": "documentation",
+                                "content": content,
+                                "path": path.to_string_lossy()
+                            }));
+                        }
                     }
                 }
             }
@@ -143,14 +375,16 @@ impl DroseraServer {
                         
                         if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("md") {
                             if let Some(name) = path.file_stem().and_then(|s| s.to_str()) {
-                                let content = fs::read_to_string(&path)?;
                                 let key = format!("{}/{}", subdir, name);
-                                self.drosera_context.insert(key, json!({
-                                    "type": "documentation",
-                                    "category": subdir,
-                                    "content": content,
-                                    "path": path.to_string_lossy()
-                                }));
+                                if let Some(content) = self.read_data_file(&path, format!("documentation {}", key)) {
+                                    self.track_file(&path, &format!("drosera://{}", key));
+                                    self.drosera_context.insert(key, json!({
+                                        "type": "documentation",
+                                        "category": subdir,
+                                        "content": content,
+                                        "path": path.to_string_lossy()
+                                    }));
+                                }
                             }
                         }
                     }
@@ -163,102 +397,187 @@ impl DroseraServer {
         for doc_name in general_docs {
             let doc_path = context_dir.join("website/docs/pages").join(format!("{}.md", doc_name));
             if doc_path.exists() {
-                let content = fs::read_to_string(&doc_path)?;
                 let key = format!("general/{}", doc_name);
-                self.drosera_context.insert(key, json!({
-                    "type": "documentation",
-                    "category": "general",
-                    "content": content,
-                    "path": doc_path.to_string_lossy()
-                }));
+                if let Some(content) = self.read_data_file(&doc_path, format!("documentation {}", key)) {
+                    self.track_file(&doc_path, &format!("drosera://{}", key));
+                    self.drosera_context.insert(key, json!({
+                        "type": "documentation",
+                        "category": "general",
+                        "content": content,
+                        "path": doc_path.to_string_lossy()
+                    }));
+                }
             }
         }
         
         Ok(())
     }
 
+    // Vendored Drosera contracts, served as drosera-lib://{path}
+    fn load_drosera_lib(&mut self, dir: &Path) -> Result<()> {
+        if !dir.exists() {
+            warn!("Drosera library directory not found");
+            return Ok(());
+        }
+
+        let mut entries: Vec<PathBuf> = fs::read_dir(dir)?.map(|entry| entry.map(|e| e.path())).collect::<std::io::Result<_>>()?;
+        entries.sort();
+        for path in entries {
+            if path.is_dir() {
+                self.load_drosera_lib(&path)?;
+            } else if path.extension().and_then(|s| s.to_str()) == Some("sol") {
+                let relative = path.strip_prefix(foundry::DROSERA_LIB_DIR).unwrap_or(&path).to_string_lossy().replace('\\', "/");
+                if let Some(source) = self.read_data_file(&path, format!("vendored Drosera contract {}", relative)) {
+                    self.track_file(&path, &format!("drosera-lib://{}", relative));
+                    self.drosera_lib.insert(relative, source);
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn load_trap_examples(&mut self) -> Result<()> {
         let examples_dir = Path::new("src/data/trap-examples");
         
         if !examples_dir.exists() {
-            info!("Trap examples directory not found");
+            warn!("Trap examples directory not found");
             return Ok(());
         }
 
-        // Load each example directory
+        // Top-level directories are either examples or categories grouping examples
+        // (defi-automation, historical-exploits)
         for entry in fs::read_dir(examples_dir)? {
             let entry = entry?;
             let path = entry.path();
             
             if path.is_dir() {
-                if let Some(example_name) = path.file_name().and_then(|s| s.to_str()) {
-                    info!("Loading trap example: {}", example_name);
-                    
-                    let mut example_data = json!({
-                        "name": example_name,
-                        "type": "trap_example"
-                    });
-                    
-                    // Load README if exists
-                    let readme_path = path.join("README.md");
-                    if readme_path.exists() {
-                        let content = fs::read_to_string(&readme_path)?;
-                        example_data["readme"] = json!(content);
-                    }
-                    
-                    // Load source files
-                    let src_dir = path.join("src");
-                    if src_dir.exists() {
-                        let mut sources = json!({});
-                        if let Ok(entries) = src_dir.read_dir() {
-                            for src_entry in entries {
-                                let src_entry = src_entry?;
-                                let src_path = src_entry.path();
-                                
-                                if src_path.is_file() && src_path.extension().and_then(|s| s.to_str()) == Some("sol") {
-                                    if let Some(file_name) = src_path.file_name().and_then(|s| s.to_str()) {
-                                        let content = fs::read_to_string(&src_path)?;
-                                        sources[file_name] = json!(content);
-                                    }
+                if let Some(dir_name) = path.file_name().and_then(|s| s.to_str()) {
+                    if is_trap_example_dir(&path) {
+                        self.load_trap_example(&path, dir_name, None)?;
+                    } else {
+                        for example_entry in fs::read_dir(&path)? {
+                            let example_path = example_entry?.path();
+                            if example_path.is_dir() {
+                                if let Some(example_name) = example_path.file_name().and_then(|s| s.to_str()) {
+                                    self.load_trap_example(&example_path, example_name, Some(dir_name))?;
                                 }
                             }
                         }
-                        example_data["sources"] = sources;
                     }
-                    
-                    // Load test files
-                    let test_dir = path.join("test");
-                    if test_dir.exists() {
-                        let mut tests = json!({});
-                        if let Ok(entries) = test_dir.read_dir() {
-                            for test_entry in entries {
-                                let test_entry = test_entry?;
-                                let test_path = test_entry.path();
-                                
-                                if test_path.is_file() && test_path.extension().and_then(|s| s.to_str()) == Some("sol") {
-                                    if let Some(file_name) = test_path.file_name().and_then(|s| s.to_str()) {
-                                        let content = fs::read_to_string(&test_path)?;
-                                        tests[file_name] = json!(content);
-                                    }
+                }
+            }
+        }
+        
+        Ok(())
+    }
+
+    fn load_trap_example(&mut self, path: &Path, example_name: &str, category: Option<&str>) -> Result<()> {
+        info!("Loading trap example: {}", example_name);
+        let uri = format!("trap-example://{}", example_name);
+        
+        let mut example_data = json!({
+            "name": example_name,
+            "type": "trap_example"
+        });
+        if let Some(category) = category {
+            example_data["category"] = json!(category);
+        }
+        
+        // Load README if exists
+        let readme_path = path.join("README.md");
+        if readme_path.exists() {
+            if let Some(content) = self.read_data_file(&readme_path, format!("README of trap example {}", example_name)) {
+                example_data["readme"] = json!(content);
+                self.track_file(&readme_path, &uri);
+            }
+        }
+
+        let toml_path = path.join("drosera.toml");
+        if toml_path.exists() {
+            if let Some(content) = self.read_data_file(&toml_path, format!("drosera.toml of trap example {}", example_name)) {
+                example_data["drosera_toml"] = json!(content);
+                self.track_file(&toml_path, &uri);
+            }
+        }
+
+        // Load source and test files
+        for (dir_name, key) in [("src", "sources"), ("test", "tests")] {
+            let dir = path.join(dir_name);
+            if dir.exists() {
+                let mut files = json!({});
+                if let Ok(entries) = dir.read_dir() {
+                    for file_entry in entries {
+                        let file_entry = file_entry?;
+                        let file_path = file_entry.path();
+                        
+                        if file_path.is_file() && file_path.extension().and_then(|s| s.to_str()) == Some("sol") {
+                            if let Some(file_name) = file_path.file_name().and_then(|s| s.to_str()) {
+                                let skipped = format!("{} of trap example {}", file_name, example_name);
+                                if let Some(content) = self.read_data_file(&file_path, skipped) {
+                                    files[file_name] = json!(content);
+                                    self.track_file(&file_path, &uri);
                                 }
                             }
                         }
-                        example_data["tests"] = tests;
                     }
-                    
-                    self.trap_examples.insert(example_name.to_string(), example_data);
                 }
+                example_data[key] = files;
             }
         }
-        
+
+        self.check_trap_config(&path.join("drosera.toml"), &example_data);
+        self.trap_examples.insert(example_name.to_string(), example_data);
         Ok(())
     }
 
+    // An example's drosera.toml against the Drosera deployments, and every
+    // trap in it against what its shouldRespond reads
+    fn check_trap_config(&mut self, toml_path: &Path, example_data: &Value) {
+        let Some(toml_text) = example_data["drosera_toml"].as_str() else {
+            return;
+        };
+        match drosera_toml::check_deployment(toml_text, &self.networks) {
+            Ok(warnings) => {
+                for warning in warnings {
+                    self.load_report.warn(toml_path, warning);
+                }
+            }
+            Err(e) => {
+                self.load_report.record(toml_path, e, "drosera.toml consistency checks");
+                return;
+            }
+        }
+        let sources: BTreeMap<String, String> = serde_json::from_value(example_data["sources"].clone()).unwrap_or_default();
+        if sources.is_empty() {
+            return;
+        }
+        let traps = match drosera_toml::parse_traps(toml_text) {
+            Ok(traps) => traps,
+            Err(e) => {
+                self.load_report.record(toml_path, e, "drosera.toml consistency checks");
+                return;
+            }
+        };
+        for trap in &traps {
+            let contract = trap.contract_name().filter(|name| sources.keys().any(|file| file.trim_end_matches(".sol") == *name));
+            let analysis = match trap_analysis::analyze(&sources, contract) {
+                Ok(analysis) => analysis,
+                Err(e) => {
+                    self.load_report.record(toml_path, e, format!("consistency checks of traps.{}", trap.name));
+                    continue;
+                }
+            };
+            for warning in trap_analysis::check_config(&analysis, trap, trap_analysis::DEFAULT_RESPONSE_EFFECT_BLOCKS) {
+                self.load_report.warn(toml_path, warning);
+            }
+        }
+    }
+
     fn load_prompts(&mut self) -> Result<()> {
         let prompts_dir = Path::new("src/data/prompts");
         
         if !prompts_dir.exists() {
-            info!("Prompts directory not found");
+            warn!("Prompts directory not found");
             return Ok(());
         }
 
@@ -268,8 +587,9 @@ impl DroseraServer {
         // Load main prompt
         let main_prompt_path = prompts_dir.join("trap-generation-prompt.md");
         if main_prompt_path.exists() {
-            let main_content = fs::read_to_string(&main_prompt_path)?;
-            combined_prompt.push_str(&main_content);
+            if let Some(main_content) = self.read_data_file(&main_prompt_path, "main trap generation prompt") {
+                combined_prompt.push_str(&main_content);
+            }
         }
 
         // Add references to other guides
@@ -278,17 +598,19 @@ impl DroseraServer {
         // Load testing guide
         let testing_guide_path = prompts_dir.join("trap-testing-guide.md");
         if testing_guide_path.exists() {
-            let testing_content = fs::read_to_string(&testing_guide_path)?;
-            combined_prompt.push_str("### Testing Guide\n\n");
-            combined_prompt.push_str(&testing_content);
+            if let Some(testing_content) = self.read_data_file(&testing_guide_path, "testing guide prompt section") {
+                combined_prompt.push_str("### Testing Guide\n\n");
+                combined_prompt.push_str(&testing_content);
+            }
         }
 
         // Load quick reference
         let quick_ref_path = prompts_dir.join("quick-reference.md");
         if quick_ref_path.exists() {
-            let quick_ref_content = fs::read_to_string(&quick_ref_path)?;
-            combined_prompt.push_str("\n\n### Quick Reference\n\n");
-            combined_prompt.push_str(&quick_ref_content);
+            if let Some(quick_ref_content) = self.read_data_file(&quick_ref_path, "quick reference prompt section") {
+                combined_prompt.push_str("\n\n### Quick Reference\n\n");
+                combined_prompt.push_str(&quick_ref_content);
+            }
         }
 
         self.prompts.insert("generate-trap".to_string(), combined_prompt);
@@ -301,27 +623,100 @@ impl DroseraServer {
         let index_path = Path::new("src/data/index.json");
         
         if index_path.exists() {
-            let content = fs::read_to_string(index_path)?;
-            let index_data: Value = serde_json::from_str(&content)?;
-            self.index = Some(index_data);
-            info!("Loaded context index");
+            if let Some(index_data) = self.read_data_file_as::<Value>(index_path, "context index") {
+                self.index = Some(index_data);
+                self.track_file(index_path, "index://context");
+                info!("Loaded context index");
+            }
         } else {
-            info!("Context index not found");
+            warn!("Context index not found");
         }
         
         Ok(())
     }
 
+    // Resolves a misc_data file's network through the registry, recording a
+    // diagnostic when it is unknown or its chainId disagrees. The data is kept
+    // either way, under the canonical network name when there is one.
+    fn check_network(&mut self, path: &Path, network: &str, chain_id: u64) -> String {
+        let resolved = self.networks.resolve(network).map(|info| (info.name.clone(), info.chain_id));
+        match resolved {
+            Some((name, Some(expected))) if expected != chain_id => {
+                let error = format!("chainId {} does not match {} ({}) in the network registry", chain_id, name, expected);
+                self.load_report.record(path, error, "nothing (data kept as-is)");
+                name
+            }
+            Some((name, _)) => name,
+            None => {
+                let error = format!("network '{}' is not in src/data/networks.json", network);
+                self.load_report.record(path, error, format!("chain metadata for {}", network));
+                network.to_string()
+            }
+        }
+    }
+
+    // Reads a data file, recording a load diagnostic instead of failing
+    fn read_data_file(&mut self, path: &Path, skipped: impl Into<String>) -> Option<String> {
+        match fs::read_to_string(path) {
+            Ok(content) => Some(content),
+            Err(e) => {
+                self.load_report.record(path, e, skipped);
+                None
+            }
+        }
+    }
+
+    fn read_data_file_as<T: DeserializeOwned>(&mut self, path: &Path, skipped: impl Into<String>) -> Option<T> {
+        match protocol::read_data_file(path) {
+            Ok(data) => Some(data),
+            Err(e) => {
+                self.load_report.record(path, e, skipped);
+                None
+            }
+        }
+    }
+
+    fn log_load_summary(&self) {
+        info!(
+            "Loaded {} protocols, {} documentation pages, {} trap examples",
+            self.protocols.len(),
+            self.drosera_context.len(),
+            self.trap_examples.len()
+        );
+        if !self.load_report.is_empty() {
+            warn!(
+                "{} data file(s) could not be loaded, see diagnostics://load",
+                self.load_report.len()
+            );
+        }
+    }
+
+    fn track_file(&mut self, path: &Path, uri: &str) {
+        self.resource_files
+            .entry(path.to_path_buf())
+            .or_default()
+            .insert(uri.to_string());
+    }
+
+    fn is_known_resource(&self, uri: &str) -> bool {
+        uri == "diagnostics://load" || self.resource_files.values().any(|uris| uris.contains(uri))
+    }
+
+}
+
+fn is_trap_example_dir(path: &Path) -> bool {
+    ["README.md", "src", "test"].iter().any(|name| path.join(name).exists())
 }
 
 impl DroseraServer {
-    async fn handle_request(&self, request: Value) -> Result<Value> {
+    async fn handle_request(self: &Arc<Self>, session: &Mutex<Session>, request: Value) -> Result<Value> {
         let method = request["method"].as_str().unwrap_or("");
         let id = request.get("id").cloned().unwrap_or(Value::Null);
         
         // Handle notifications (requests without id)
         if id.is_null() {
             if method == "notifications/initialized" {
+                session.lock().await.mark_initialized();
                 return Ok(Value::Null); // No response for notifications
             } else {
                 // For requests that should have an ID but don't, return an error with null ID
@@ -348,8 +743,11 @@ impl DroseraServer {
                     "result": {
                         "protocolVersion": "2024-11-05",
                         "capabilities": {
+                            "logging": {},
                             "tools": {},
-                            "resources": {}
+                            "resources": {
+                                "subscribe": true
+                            }
                         },
                         "serverInfo": {
                             "name": "drosera-traps-mcp",
@@ -363,17 +761,44 @@ impl DroseraServer {
                     "jsonrpc": "2.0",
                     "id": id,
                     "result": {
-                        "tools": []
+                        "tools": tools::definitions()
                     }
                 }))
             }
             "tools/call" => {
+                let name = request["params"]["name"].as_str().unwrap_or("");
+                let arguments = &request["params"]["arguments"];
+                if !tools::definitions().iter().any(|tool| tool["name"] == name) {
+                    return Ok(json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": {
+                            "code": -32602,
+                            "message": format!("Unknown tool: {}", name)
+                        }
+                    }));
+                }
+
+                // Tools may run forge or solc for minutes, so they run on the blocking
+                // pool against this snapshot of the data. Tool failures are reported
+                // in the result so the model can see them
+                let server = Arc::clone(self);
+                let (name, arguments) = (name.to_string(), arguments.clone());
+                let output = tokio::task::spawn_blocking(move || server.call_tool(&name, &arguments)).await?;
+                let (text, is_error) = match output {
+                    Ok(Value::String(text)) => (text, false),
+                    Ok(output) => (serde_json::to_string_pretty(&output)?, false),
+                    Err(e) => (format!("{:#}", e), true),
+                };
                 Ok(json!({
                     "jsonrpc": "2.0",
                     "id": id,
-                    "error": {
-                        "code": -32601,
-                        "message": "No tools available - use resources instead"
+                    "result": {
+                        "content": [{
+                            "type": "text",
+                            "text": text
+                        }],
+                        "isError": is_error
                     }
                 }))
             }
@@ -383,36 +808,92 @@ impl DroseraServer {
                 // Add protocol resources
                 for (protocol_name, protocol_data) in &self.protocols {
                     // Add network resources from misc_data
-                    if let Some(networks) = protocol_data.get("networks").and_then(|n| n.as_object()) {
-                        for network in networks.keys() {
-                            resources.push(json!({
-                                "uri": format!("protocol://{}/misc_data/{}", protocol_name, network),
-                                "name": format!("{} {} Protocol Data", 
-                                    protocol_name.chars().next().unwrap().to_uppercase().collect::<String>() + &protocol_name[1..],
-                                    network.chars().next().unwrap().to_uppercase().collect::<String>() + &network[1..]
-                                ),
-                                "description": format!("Complete protocol data including contracts, tokens, pools, ABIs, and functions for {} on {}", protocol_name, network),
-                                "mimeType": "application/json"
-                            }));
-                        }
+                    for network in protocol_data.networks.keys() {
+                        resources.push(json!({
+                            "uri": format!("protocol://{}/misc_data/{}", protocol_name, network),
+                            "name": format!("{} {} Protocol Data", 
+                                protocol_name.chars().next().unwrap().to_uppercase().collect::<String>() + &protocol_name[1..],
+                                network.chars().next().unwrap().to_uppercase().collect::<String>() + &network[1..]
+                            ),
+                            "description": format!("Complete protocol data including contracts, tokens, pools, ABIs, and functions for {} on {}", protocol_name, network),
+                            "mimeType": "application/json"
+                        }));
                     }
                     
                     // Add ABI resources
-                    if let Some(abis) = protocol_data.get("abis").and_then(|a| a.as_object()) {
-                        for interface_name in abis.keys() {
-                            resources.push(json!({
-                                "uri": format!("protocol://{}/abi/{}", protocol_name, interface_name),
-                                "name": format!("{} {} Interface", protocol_name, interface_name),
-                                "description": format!("ABI and function signatures for {} interface", interface_name),
-                                "mimeType": "application/json"
-                            }));
-                        }
+                    for interface_name in protocol_data.abis.keys() {
+                        resources.push(json!({
+                            "uri": format!("protocol://{}/abi/{}", protocol_name, interface_name),
+                            "name": format!("{} {} Interface", protocol_name, interface_name),
+                            "description": format!("ABI and function signatures for {} interface", interface_name),
+                            "mimeType": "application/json"
+                        }));
+                        resources.push(json!({
+                            "uri": format!("protocol://{}/interface/{}.sol", protocol_name, interface_name),
+                            "name": format!("{} {} Solidity Interface", protocol_name, interface_name),
+                            "description": format!("Compilable Solidity interface with the view functions of {}", interface_name),
+                            "mimeType": "text/x-solidity"
+                        }));
+                    }
+
+                    // Add storage layout resources
+                    for contract in protocol_data.storage_layouts.keys() {
+                        resources.push(json!({
+                            "uri": format!("protocol://{}/storage_layout/{}", protocol_name, contract),
+                            "name": format!("{} {} Storage Layout", protocol_name, contract),
+                            "description": format!("solc storage layout of {} (variables, slots, offsets and types)", contract),
+                            "mimeType": "application/json"
+                        }));
+                    }
+
+                    // Add event catalog resources
+                    for event_name in event_catalog::event_names(protocol_data) {
+                        resources.push(json!({
+                            "uri": format!("protocol://{}/events/{}", protocol_name, event_name),
+                            "name": format!("{} {} Event", protocol_name, event_name),
+                            "description": format!("Signature, topic0, indexed parameters and emitting contracts of {} {}", protocol_name, event_name),
+                            "mimeType": "application/json"
+                        }));
                     }
                     
                 }
                 
+                // Add network registry resources
+                for info in self.networks.iter() {
+                    resources.push(json!({
+                        "uri": format!("network://{}", info.name),
+                        "name": format!("{} Network", info.display_name),
+                        "description": format!("Chain id, aliases, block time and Drosera deployment for {}", info.name),
+                        "mimeType": "application/json"
+                    }));
+                }
+
+                // Add address book resources
+                for (protocol_name, protocol_data) in &self.protocols {
+                    for network in address_book::networks(protocol_data) {
+                        resources.push(json!({
+                            "uri": format!("address://{}/{}", network, protocol_name),
+                            "name": format!("{} {} Address Book", protocol_name, network),
+                            "description": format!("Checksummed {} contract, token and pool addresses on {}", protocol_name, network),
+                            "mimeType": "application/json"
+                        }));
+                    }
+                }
+
+                // Add monitoring scenario resources
+                for (protocol_name, protocol_data) in &self.protocols {
+                    for scenario_name in scenario_catalog::scenario_names(protocol_data) {
+                        resources.push(json!({
+                            "uri": format!("scenario://{}/{}", protocol_name, scenario_name),
+                            "name": format!("{} {} Scenario", protocol_name, scenario_name),
+                            "description": format!("Monitoring scenario {} of {} with its trap categories, example traps, target addresses and required ABI functions", scenario_name, protocol_name),
+                            "mimeType": "application/json"
+                        }));
+                    }
+                }
+
                 // Add Drosera context resources
-                for (doc_name, _doc_data) in &self.drosera_context {
+                for doc_name in self.drosera_context.keys() {
                     resources.push(json!({
                         "uri": format!("drosera://{}", doc_name),
                         "name": format!("Drosera {}", doc_name.replace('-', " ").replace('/', " - ")),
@@ -420,9 +901,25 @@ impl DroseraServer {
                         "mimeType": "text/markdown"
                     }));
                 }
+                for lib_path in self.drosera_lib.keys() {
+                    resources.push(json!({
+                        "uri": format!("drosera-lib://{}", lib_path),
+                        "name": format!("Drosera {}", lib_path),
+                        "description": format!("Vendored Drosera contract source, importable as drosera-contracts/{}", lib_path),
+                        "mimeType": "text/x-solidity"
+                    }));
+                }
+                if self.deployments.is_some() {
+                    resources.push(json!({
+                        "uri": "drosera://deployments.json",
+                        "name": "Drosera Deployments",
+                        "description": "Drosera contract addresses and relay endpoints per network, parsed from the deployments page",
+                        "mimeType": "application/json"
+                    }));
+                }
                 
                 // Add trap example resources
-                for (example_name, _example_data) in &self.trap_examples {
+                for example_name in self.trap_examples.keys() {
                     resources.push(json!({
                         "uri": format!("trap-example://{}", example_name),
                         "name": format!("{} Trap Example", example_name.replace('-', " ").chars().collect::<String>()),
@@ -441,6 +938,28 @@ impl DroseraServer {
                     }));
                 }
 
+                // Add protocol data schema resources
+                for kind in DataFileKind::ALL {
+                    resources.push(json!({
+                        "uri": kind.schema_uri(),
+                        "name": format!("Protocol {} Schema", kind.name()),
+                        "description": format!("JSON Schema for {} files", kind.file_pattern()),
+                        "mimeType": "application/schema+json"
+                    }));
+                }
+
+                // Add load diagnostics resource
+                resources.push(json!({
+                    "uri": "diagnostics://load",
+                    "name": "Load Diagnostics",
+                    "description": format!(
+                        "Data files that could not be loaded ({} diagnostics) and configuration warnings ({})",
+                        self.load_report.len(),
+                        self.load_report.warnings.len()
+                    ),
+                    "mimeType": "application/json"
+                }));
+
                 Ok(json!({
                     "jsonrpc": "2.0",
                     "id": id,
@@ -460,85 +979,247 @@ impl DroseraServer {
                         let protocol_name = parts[0];
                         
                         if let Some(protocol_data) = self.protocols.get(protocol_name) {
-                            match parts.len() {
-                                3 => {
-                                    let resource_type = parts[1];
-                                    let resource_name = parts[2];
-                                    
-                                    match resource_type {
-                                        "abi" => {
-                                            // protocol://uniswap/abi/IUniswapV3Pool
-                                            if let Some(abi_data) = protocol_data.get("abis")
-                                                .and_then(|a| a.get(resource_name)) {
-                                                
-                                                return Ok(json!({
-                                                    "jsonrpc": "2.0",
-                                                    "id": id,
-                                                    "result": {
-                                                        "contents": [{
-                                                            "type": "text",
-                                                            "text": serde_json::to_string_pretty(&abi_data)?
-                                                        }]
-                                                    }
-                                                }));
-                                            }
+                            if parts.len() == 3 {
+                                let resource_type = parts[1];
+                                let resource_name = parts[2];
+                                
+                                match resource_type {
+                                    "abi" => {
+                                        // protocol://uniswap/abi/IUniswapV3Pool
+                                        if let Some(abi_data) = protocol_data.abis.get(resource_name) {
+                                            
+                                            return Ok(json!({
+                                                "jsonrpc": "2.0",
+                                                "id": id,
+                                                "result": {
+                                                    "contents": [{
+                                                        "type": "text",
+                                                        "text": serde_json::to_string_pretty(&abi_data)?
+                                                    }]
+                                                }
+                                            }));
+                                        }
+                                    }
+                                    "interface" => {
+                                        // protocol://uniswap/interface/IUniswapV3Pool.sol
+                                        let interface_name = resource_name.strip_suffix(".sol").unwrap_or(resource_name);
+                                        if let Some(abi_data) = protocol_data.abis.get(interface_name) {
+                                            let functions = solidity_interface::select_functions(abi_data, None)?;
+                                            let abi_uri = format!("protocol://{}/abi/{}", protocol_name, interface_name);
+                                            let source = solidity_interface::render_interface(abi_data, &functions, &abi_uri)?;
+
+                                            return Ok(json!({
+                                                "jsonrpc": "2.0",
+                                                "id": id,
+                                                "result": {
+                                                    "contents": [{
+                                                        "type": "text",
+                                                        "text": source
+                                                    }]
+                                                }
+                                            }));
                                         }
-                                        "misc_data" => {
-                                            // protocol://uniswap/misc_data/mainnet
-                                            if let Some(network_data) = protocol_data.get("networks")
-                                                .and_then(|n| n.get(resource_name)) {
-                                                
-                                                // Return the complete network data with ABIs included
-                                                let mut result = network_data.clone();
-                                                if let Some(abis) = protocol_data.get("abis") {
-                                                    result["abis"] = abis.clone();
+                                    }
+                                    "storage_layout" => {
+                                        // protocol://uniswap/storage_layout/UniswapV3Pool
+                                        if let Some(layout) = protocol_data.storage_layouts.get(resource_name) {
+                                            return Ok(json!({
+                                                "jsonrpc": "2.0",
+                                                "id": id,
+                                                "result": {
+                                                    "contents": [{
+                                                        "type": "text",
+                                                        "text": serde_json::to_string_pretty(layout)?
+                                                    }]
+                                                }
+                                            }));
+                                        }
+                                    }
+                                    "events" => {
+                                        // protocol://uniswap/events/Swap
+                                        if let Some(entry) = event_catalog::catalog_entry(protocol_name, protocol_data, resource_name) {
+                                            return Ok(json!({
+                                                "jsonrpc": "2.0",
+                                                "id": id,
+                                                "result": {
+                                                    "contents": [{
+                                                        "type": "text",
+                                                        "text": serde_json::to_string_pretty(&entry)?
+                                                    }]
                                                 }
-                                                
-                                                return Ok(json!({
-                                                    "jsonrpc": "2.0",
-                                                    "id": id,
-                                                    "result": {
-                                                        "contents": [{
-                                                            "type": "text",
-                                                            "text": serde_json::to_string_pretty(&result)?
-                                                        }]
-                                                    }
-                                                }));
+                                            }));
+                                        }
+                                    }
+                                    "misc_data" => {
+                                        // protocol://uniswap/misc_data/mainnet (aliases and chain ids resolve too)
+                                        let network = self.networks.canonical_name(resource_name);
+                                        if let Some(network_data) = protocol_data.networks.get(&network) {
+                                            // Return the complete network data with ABIs and chain metadata included
+                                            let mut result = serde_json::to_value(network_data)?;
+                                            result["abis"] = serde_json::to_value(&protocol_data.abis)?;
+                                            if let Some(info) = self.networks.resolve(&network) {
+                                                result["network_info"] = serde_json::to_value(info)?;
                                             }
+                                            
+                                            return Ok(json!({
+                                                "jsonrpc": "2.0",
+                                                "id": id,
+                                                "result": {
+                                                    "contents": [{
+                                                        "type": "text",
+                                                        "text": serde_json::to_string_pretty(&result)?
+                                                    }]
+                                                }
+                                            }));
                                         }
-                                        _ => {}
                                     }
+                                    _ => {}
                                 }
-                                _ => {}
                             }
                             
-                            return Ok(json!({
+                            Ok(json!({
                                 "jsonrpc": "2.0",
                                 "id": id,
                                 "error": {
                                     "code": -32602,
                                     "message": format!("Resource not found: {}", uri)
                                 }
-                            }));
+                            }))
                         } else {
-                            return Ok(json!({
+                            Ok(json!({
                                 "jsonrpc": "2.0",
                                 "id": id,
                                 "error": {
                                     "code": -32602,
                                     "message": format!("Protocol '{}' not found", protocol_name)
                                 }
-                            }));
+                            }))
                         }
                     } else {
-                        return Ok(json!({
+                        Ok(json!({
                             "jsonrpc": "2.0",
                             "id": id,
                             "error": {
                                 "code": -32602,
                                 "message": "Invalid protocol URI format"
                             }
-                        }));
+                        }))
+                    }
+                } else if uri.starts_with("network://") {
+                    // network://hoodi (aliases and chain ids resolve too)
+                    let name = uri.strip_prefix("network://").unwrap();
+
+                    if let Some(info) = self.networks.resolve(name) {
+                        Ok(json!({
+                            "jsonrpc": "2.0",
+                            "id": id,
+                            "result": {
+                                "contents": [{
+                                    "type": "text",
+                                    "text": serde_json::to_string_pretty(info)?
+                                }]
+                            }
+                        }))
+                    } else {
+                        Ok(json!({
+                            "jsonrpc": "2.0",
+                            "id": id,
+                            "error": {
+                                "code": -32602,
+                                "message": format!("Network '{}' not found (known: {})", name, self.networks.names().join(", "))
+                            }
+                        }))
+                    }
+                } else if uri.starts_with("address://") {
+                    // address://mainnet/uniswap
+                    let path = uri.strip_prefix("address://").unwrap();
+                    let entries = path.split_once('/').and_then(|(network, protocol_name)| {
+                        let protocol_data = self.protocols.get(protocol_name)?;
+                        let network = self.networks.canonical_name(network);
+                        let entries = address_book::entries(protocol_name, protocol_data, &network);
+                        (!entries.is_empty()).then_some(entries)
+                    });
+
+                    match entries {
+                        Some(entries) => Ok(json!({
+                            "jsonrpc": "2.0",
+                            "id": id,
+                            "result": {
+                                "contents": [{
+                                    "type": "text",
+                                    "text": serde_json::to_string_pretty(&entries)?
+                                }]
+                            }
+                        })),
+                        None => Ok(json!({
+                            "jsonrpc": "2.0",
+                            "id": id,
+                            "error": {
+                                "code": -32602,
+                                "message": format!("Resource not found: {}", uri)
+                            }
+                        })),
+                    }
+                } else if uri.starts_with("scenario://") {
+                    // scenario://uniswap/price_manipulation
+                    let path = uri.strip_prefix("scenario://").unwrap();
+                    let entry = path.split_once('/').and_then(|(protocol_name, scenario_name)| {
+                        let protocol_data = self.protocols.get(protocol_name)?;
+                        scenario_catalog::scenario_entry(protocol_name, protocol_data, scenario_name, &self.trap_examples)
+                    });
+
+                    match entry {
+                        Some(entry) => Ok(json!({
+                            "jsonrpc": "2.0",
+                            "id": id,
+                            "result": {
+                                "contents": [{
+                                    "type": "text",
+                                    "text": serde_json::to_string_pretty(&entry)?
+                                }]
+                            }
+                        })),
+                        None => Ok(json!({
+                            "jsonrpc": "2.0",
+                            "id": id,
+                            "error": {
+                                "code": -32602,
+                                "message": format!("Resource not found: {}", uri)
+                            }
+                        })),
+                    }
+                } else if uri == "drosera://deployments.json" && self.deployments.is_some() {
+                    Ok(json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "result": {
+                            "contents": [{
+                                "type": "text",
+                                "text": serde_json::to_string_pretty(&self.deployments)?
+                            }]
+                        }
+                    }))
+                } else if let Some(lib_path) = uri.strip_prefix("drosera-lib://") {
+                    match self.drosera_lib.get(lib_path) {
+                        Some(source) => Ok(json!({
+                            "jsonrpc": "2.0",
+                            "id": id,
+                            "result": {
+                                "contents": [{
+                                    "type": "text",
+                                    "text": source
+                                }]
+                            }
+                        })),
+                        None => Ok(json!({
+                            "jsonrpc": "2.0",
+                            "id": id,
+                            "error": {
+                                "code": -32602,
+                                "message": format!("Drosera library file '{}' not found (known: {})", lib_path,
+                                    self.drosera_lib.keys().cloned().collect::<Vec<_>>().join(", "))
+                            }
+                        })),
                     }
                 } else if uri.starts_with("drosera://") {
                     let doc_name = uri.strip_prefix("drosera://").unwrap();
@@ -546,7 +1227,7 @@ impl DroseraServer {
                     if let Some(doc_data) = self.drosera_context.get(doc_name) {
                         let content = doc_data.get("content").and_then(|c| c.as_str()).unwrap_or("");
                         
-                        return Ok(json!({
+                        Ok(json!({
                             "jsonrpc": "2.0",
                             "id": id,
                             "result": {
@@ -555,22 +1236,22 @@ impl DroseraServer {
                                     "text": content
                                 }]
                             }
-                        }));
+                        }))
                     } else {
-                        return Ok(json!({
+                        Ok(json!({
                             "jsonrpc": "2.0",
                             "id": id,
                             "error": {
                                 "code": -32602,
                                 "message": format!("Drosera documentation '{}' not found", doc_name)
                             }
-                        }));
+                        }))
                     }
                 } else if uri.starts_with("trap-example://") {
                     let example_name = uri.strip_prefix("trap-example://").unwrap();
                     
                     if let Some(example_data) = self.trap_examples.get(example_name) {
-                        return Ok(json!({
+                        Ok(json!({
                             "jsonrpc": "2.0",
                             "id": id,
                             "result": {
@@ -579,21 +1260,21 @@ impl DroseraServer {
                                     "text": serde_json::to_string_pretty(&example_data)?
                                 }]
                             }
-                        }));
+                        }))
                     } else {
-                        return Ok(json!({
+                        Ok(json!({
                             "jsonrpc": "2.0",
                             "id": id,
                             "error": {
                                 "code": -32602,
                                 "message": format!("Trap example '{}' not found", example_name)
                             }
-                        }));
+                        }))
                     }
                 } else if uri.starts_with("index://") {
                     if uri == "index://context" {
                         if let Some(index_data) = &self.index {
-                            return Ok(json!({
+                            Ok(json!({
                                 "jsonrpc": "2.0",
                                 "id": id,
                                 "result": {
@@ -602,27 +1283,63 @@ impl DroseraServer {
                                         "text": serde_json::to_string_pretty(&index_data)?
                                     }]
                                 }
-                            }));
+                            }))
                         } else {
-                            return Ok(json!({
+                            Ok(json!({
                                 "jsonrpc": "2.0",
                                 "id": id,
                                 "error": {
                                     "code": -32602,
                                     "message": "Context index not available"
                                 }
-                            }));
+                            }))
                         }
                     } else {
-                        return Ok(json!({
+                        Ok(json!({
                             "jsonrpc": "2.0",
                             "id": id,
                             "error": {
                                 "code": -32602,
                                 "message": format!("Unknown index URI: {}", uri)
                             }
-                        }));
+                        }))
                     }
+                } else if uri.starts_with("schema://") {
+                    match DataFileKind::ALL.into_iter().find(|kind| kind.schema_uri() == uri) {
+                        Some(kind) => {
+                            Ok(json!({
+                                "jsonrpc": "2.0",
+                                "id": id,
+                                "result": {
+                                    "contents": [{
+                                        "type": "text",
+                                        "text": serde_json::to_string_pretty(&kind.schema())?
+                                    }]
+                                }
+                            }))
+                        }
+                        None => {
+                            Ok(json!({
+                                "jsonrpc": "2.0",
+                                "id": id,
+                                "error": {
+                                    "code": -32602,
+                                    "message": format!("Unknown schema URI: {}", uri)
+                                }
+                            }))
+                        }
+                    }
+                } else if uri == "diagnostics://load" {
+                    Ok(json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "result": {
+                            "contents": [{
+                                "type": "text",
+                                "text": serde_json::to_string_pretty(&self.load_report)?
+                            }]
+                        }
+                    }))
                 } else {
                     Ok(json!({
                         "jsonrpc": "2.0",
@@ -634,6 +1351,57 @@ impl DroseraServer {
                     }))
                 }
             }
+            "resources/subscribe" | "resources/unsubscribe" => {
+                let uri = request["params"]["uri"].as_str().unwrap_or("");
+                
+                if !self.is_known_resource(uri) {
+                    return Ok(json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": {
+                            "code": -32602,
+                            "message": format!("Resource not found: {}", uri)
+                        }
+                    }));
+                }
+                
+                let mut session = session.lock().await;
+                if method == "resources/subscribe" {
+                    session.subscribe(uri);
+                } else {
+                    session.unsubscribe(uri);
+                }
+                
+                Ok(json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "result": {}
+                }))
+            }
+            "logging/setLevel" => {
+                let level = request["params"]["level"].as_str().unwrap_or("");
+                
+                match LogLevel::parse(level) {
+                    Some(level) => {
+                        session.lock().await.set_log_level(level);
+                        Ok(json!({
+                            "jsonrpc": "2.0",
+                            "id": id,
+                            "result": {}
+                        }))
+                    }
+                    None => {
+                        Ok(json!({
+                            "jsonrpc": "2.0",
+                            "id": id,
+                            "error": {
+                                "code": -32602,
+                                "message": format!("Invalid log level: {}", level)
+                            }
+                        }))
+                    }
+                }
+            }
             "prompts/list" => {
                 Ok(json!({
                     "jsonrpc": "2.0",
@@ -721,37 +1489,164 @@ impl DroseraServer {
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Configure tracing to write to stderr instead of stdout
-    tracing_subscriber::fmt()
-        .with_writer(std::io::stderr)
+    let strict = match cli::parse_args(std::env::args().skip(1))? {
+        Command::Serve { strict } => strict,
+        Command::ValidateData { data_dir } => {
+            if cli::validate_data(&data_dir)? > 0 {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Command::Schema { kind } => {
+            println!("{}", serde_json::to_string_pretty(&kind.schema())?);
+            return Ok(());
+        }
+        Command::ImportProtocol(options) => {
+            if protocol_import::import_protocol(&options)? > 0 {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+    };
+
+    // Configure tracing to write to stderr instead of stdout, and forward
+    // this crate's events to the client as notifications/message
+    let (client_log_layer, log_records) = ClientLogLayer::new();
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer()
+            .with_writer(std::io::stderr)
+            .with_filter(LevelFilter::INFO))
+        .with(client_log_layer.with_filter(Targets::new().with_target(env!("CARGO_CRATE_NAME"), LevelFilter::DEBUG)))
         .init();
     
     info!("🦀 Starting Drosera Traps MCP Server (Rust)...");
 
     let server = DroseraServer::new()?;
-    let stdin = io::stdin();
-    let mut stdout = io::stdout();
+    server.log_load_summary();
+    // --strict turns any load diagnostic into a startup failure
+    if strict && !server.load_report.is_empty() {
+        bail!(
+            "{} data file(s) could not be loaded (strict mode):\n{}",
+            server.load_report.len(),
+            serde_json::to_string_pretty(&server.load_report)?
+        );
+    }
+    // Requests and the watcher share the loaded data through a swappable
+    // snapshot, so a reload never waits for a running tool call
+    let server = Arc::new(RwLock::new(Arc::new(server)));
+    let session = Arc::new(Mutex::new(Session::default()));
+    
+    // Responses and server-initiated notifications share one writer so lines never interleave
+    let (outgoing, mut outgoing_rx) = mpsc::unbounded_channel::<Value>();
+    let writer = tokio::spawn(async move {
+        let mut stdout = tokio::io::stdout();
+        while let Some(message) = outgoing_rx.recv().await {
+            let message_str = serde_json::to_string(&message)?;
+            stdout.write_all(message_str.as_bytes()).await?;
+            stdout.write_all(b"\n").await?;
+            stdout.flush().await?;
+        }
+        Ok::<(), anyhow::Error>(())
+    });
+    
+    let log_forwarder = tokio::spawn(client_log::forward_logs(log_records, session.clone(), outgoing.clone()));
+    let watcher = tokio::spawn(watcher::watch_resources(server.clone(), session.clone(), outgoing.clone()));
     
     // Simple MCP protocol implementation
-    for line in stdin.lock().lines() {
-        let line = line?;
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Some(line) = lines.next_line().await? {
         if line.trim().is_empty() {
             continue;
         }
 
         // Parse JSON-RPC message
         if let Ok(request) = serde_json::from_str::<Value>(&line) {
-            let response = server.handle_request(request).await?;
+            let server = server.read().await.clone();
+            let response = server.handle_request(&session, request).await?;
             
             // Only send response if it's not null (notifications don't get responses)
             if !response.is_null() {
-                let response_str = serde_json::to_string(&response)?;
-                writeln!(stdout, "{}", response_str)?;
-                stdout.flush()?;
+                outgoing.send(response)?;
             }
         }
     }
 
+    // Let the writer drain pending messages before exiting
+    watcher.abort();
+    log_forwarder.abort();
+    drop(outgoing);
+    writer.await??;
     Ok(())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every protocol index.json points at (its protocols section and the
+    // protocols/related_protocols of examples and docs) must load from
+    // src/data/protocols with ABIs and mainnet data
+    #[test]
+    fn index_protocols_resolve() {
+        let server = DroseraServer::new().expect("server loads");
+        let index = server.index.as_ref().expect("index.json loads");
+
+        let mut names: HashSet<String> = index["protocols"]
+            .as_object()
+            .expect("index.json has a protocols section")
+            .keys()
+            .cloned()
+            .collect();
+        for section in ["trap_examples", "documentation"] {
+            for entries in index[section].as_object().into_iter().flat_map(|groups| groups.values()) {
+                for entry in entries.as_object().into_iter().flat_map(|entries| entries.values()) {
+                    for key in ["protocols", "related_protocols"] {
+                        let referenced = entry[key].as_array().into_iter().flatten();
+                        names.extend(referenced.filter_map(Value::as_str).map(str::to_string));
+                    }
+                }
+            }
+        }
+
+        let mut names: Vec<String> = names.into_iter().collect();
+        names.sort();
+        for name in &names {
+            let protocol = server
+                .protocols
+                .get(name)
+                .unwrap_or_else(|| panic!("index.json names protocol '{}' but it did not load", name));
+            assert!(!protocol.abis.is_empty(), "protocol '{}' has no ABIs", name);
+            assert!(protocol.networks.contains_key("mainnet"), "protocol '{}' has no mainnet data", name);
+        }
+        assert!(
+            server.load_report.is_empty(),
+            "bundled data has load diagnostics: {:?}",
+            server.load_report.diagnostics
+        );
+    }
+
+    // test_trap may run forge for minutes; the call must go through while the
+    // session is locked elsewhere (log forwarding, the watcher)
+    #[tokio::test]
+    async fn tool_calls_leave_the_session_unlocked() {
+        let server = Arc::new(DroseraServer::new().expect("server loads"));
+        let session = Mutex::new(Session::default());
+        let _held = session.lock().await;
+
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "tools/call",
+            "params": {
+                "name": "test_trap",
+                "arguments": {"sources": {"test/Empty.t.sol": "contract EmptyTest {}"}}
+            }
+        });
+        let response = tokio::time::timeout(std::time::Duration::from_secs(60), server.handle_request(&session, request))
+            .await
+            .expect("tools/call waited for the session lock")
+            .expect("tools/call returns a response");
+        assert_eq!(response["id"], 1);
+        assert!(response["result"]["isError"].is_boolean());
+    }
+}
@@ -0,0 +1,264 @@
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::protocol::{AbiError, AbiEvent, AbiFile, AbiFunction, AbiParam, AbiStruct};
+
+// Normalizes standard solc/Foundry ABI JSON (an array of function, event and
+// error entries) into the server's ABI model, generating the human-readable
+// signatures the custom format carries.
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum SolcAbiItem {
+    Function {
+        name: String,
+        #[serde(default)]
+        inputs: Vec<AbiParam>,
+        #[serde(default)]
+        outputs: Vec<AbiParam>,
+        #[serde(rename = "stateMutability", default)]
+        state_mutability: Option<String>,
+        // Pre-0.5 ABIs use constant/payable instead of stateMutability
+        #[serde(default)]
+        constant: bool,
+        #[serde(default)]
+        payable: bool,
+    },
+    Event {
+        name: String,
+        #[serde(default)]
+        inputs: Vec<AbiParam>,
+        #[serde(default)]
+        anonymous: bool,
+    },
+    Error {
+        name: String,
+        #[serde(default)]
+        inputs: Vec<AbiParam>,
+    },
+    // constructor, fallback and receive are not callable by name
+    #[serde(other)]
+    Other,
+}
+
+pub fn normalize(interface: &str, abi: Value) -> Result<AbiFile> {
+    let items: Vec<SolcAbiItem> = serde_json::from_value(abi)?;
+
+    let mut abi_file = AbiFile {
+        interface: interface.to_string(),
+        description: format!("{} (imported from solc ABI JSON)", interface),
+        functions: Vec::new(),
+        events: Vec::new(),
+        errors: Vec::new(),
+        structs: Vec::new(),
+    };
+
+    for item in items {
+        match item {
+            SolcAbiItem::Function { name, inputs, outputs, state_mutability, constant, payable } => {
+                let state_mutability = state_mutability.unwrap_or_else(|| {
+                    if constant {
+                        "view".to_string()
+                    } else if payable {
+                        "payable".to_string()
+                    } else {
+                        "nonpayable".to_string()
+                    }
+                });
+                abi_file.functions.push(AbiFunction {
+                    signature: function_signature(&name, &inputs, &outputs, &state_mutability),
+                    name,
+                    description: None,
+                    inputs,
+                    outputs,
+                    state_mutability: Some(state_mutability),
+                    canonical_signature: None,
+                    selector: None,
+                });
+            }
+            SolcAbiItem::Event { name, inputs, anonymous } => {
+                abi_file.events.push(AbiEvent {
+                    signature: event_signature(&name, &inputs, anonymous),
+                    name,
+                    description: None,
+                    inputs,
+                    anonymous,
+                    canonical_signature: None,
+                    topic0: None,
+                });
+            }
+            SolcAbiItem::Error { name, inputs } => {
+                abi_file.errors.push(AbiError {
+                    signature: format!("error {}({})", name, param_list(&inputs, None)),
+                    name,
+                    description: None,
+                    inputs,
+                    canonical_signature: None,
+                    selector: None,
+                });
+            }
+            SolcAbiItem::Other => {}
+        }
+    }
+
+    // Struct definitions referenced by tuple parameters
+    let params = abi_file.functions.iter().flat_map(|f| f.inputs.iter().chain(&f.outputs))
+        .chain(abi_file.events.iter().flat_map(|e| &e.inputs))
+        .chain(abi_file.errors.iter().flat_map(|e| &e.inputs));
+    for param in params {
+        collect_structs(param, &mut abi_file.structs);
+    }
+
+    Ok(abi_file)
+}
+
+fn collect_structs(param: &AbiParam, structs: &mut Vec<AbiStruct>) {
+    for component in &param.components {
+        collect_structs(component, structs);
+    }
+    if !param.ty.starts_with("tuple") {
+        return;
+    }
+    let name = solidity_type(param);
+    let name = strip_array_suffixes(&name);
+    if name.starts_with('(') || structs.iter().any(|s| s.name == name) {
+        return;
+    }
+    structs.push(AbiStruct {
+        name: name.to_string(),
+        fields: param_list(&param.components, None),
+    });
+}
+
+// Element type of a (possibly nested) array type: `Params2[3][]` -> `Params2`
+pub fn strip_array_suffixes(ty: &str) -> &str {
+    let mut ty = ty;
+    while let Some(start) = ty.strip_suffix(']').and_then(|inner| inner.rfind('[')) {
+        ty = &ty[..start];
+    }
+    ty
+}
+
+// Solidity spelling of a parameter type, using the struct name for tuples
+// when solc provided one (`struct ISwapRouter.ExactInputSingleParams` -> `ExactInputSingleParams`)
+pub fn solidity_type(param: &AbiParam) -> String {
+    if let Some(internal_type) = &param.internal_type {
+        if let Some(struct_name) = internal_type.strip_prefix("struct ") {
+            return struct_name.rsplit('.').next().unwrap_or(struct_name).to_string();
+        }
+    }
+    match param.ty.strip_prefix("tuple") {
+        Some(array_suffix) => {
+            let members: Vec<String> = param.components.iter().map(solidity_type).collect();
+            format!("({}){}", members.join(","), array_suffix)
+        }
+        None => param.ty.clone(),
+    }
+}
+
+fn is_reference_type(param: &AbiParam) -> bool {
+    param.ty == "string" || param.ty == "bytes" || param.ty.starts_with("tuple") || param.ty.ends_with(']')
+}
+
+fn param_list(params: &[AbiParam], location: Option<&str>) -> String {
+    params
+        .iter()
+        .map(|param| {
+            let mut rendered = solidity_type(param);
+            if let Some(location) = location.filter(|_| is_reference_type(param)) {
+                rendered.push(' ');
+                rendered.push_str(location);
+            }
+            if param.indexed == Some(true) {
+                rendered.push_str(" indexed");
+            }
+            if !param.name.is_empty() {
+                rendered.push(' ');
+                rendered.push_str(&param.name);
+            }
+            rendered
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+pub fn function_signature(name: &str, inputs: &[AbiParam], outputs: &[AbiParam], state_mutability: &str) -> String {
+    let mut signature = format!("function {}({}) external", name, param_list(inputs, Some("calldata")));
+    if state_mutability != "nonpayable" {
+        signature.push(' ');
+        signature.push_str(state_mutability);
+    }
+    if !outputs.is_empty() {
+        signature.push_str(&format!(" returns ({})", param_list(outputs, Some("memory"))));
+    }
+    signature
+}
+
+pub fn event_signature(name: &str, inputs: &[AbiParam], anonymous: bool) -> String {
+    let mut signature = format!("{}({})", name, param_list(inputs, None));
+    if anonymous {
+        signature.push_str(" anonymous");
+    }
+    signature
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signature::{self, SignatureKind, StructDefs};
+    use serde_json::json;
+
+    #[test]
+    fn struct_names_ending_in_digits() {
+        let abi = json!([{
+            "type": "function",
+            "name": "modify",
+            "stateMutability": "nonpayable",
+            "inputs": [
+                {
+                    "name": "key",
+                    "type": "tuple",
+                    "internalType": "struct IHooks.PoolKeyV2",
+                    "components": [
+                        {"name": "currency0", "type": "address", "internalType": "address"},
+                        {"name": "fee", "type": "uint24", "internalType": "uint24"}
+                    ]
+                },
+                {
+                    "name": "params",
+                    "type": "tuple[2][]",
+                    "internalType": "struct IHooks.Params2[2][]",
+                    "components": [
+                        {"name": "tickLower", "type": "int24", "internalType": "int24"},
+                        {"name": "liquidityDelta", "type": "int256", "internalType": "int256"}
+                    ]
+                }
+            ],
+            "outputs": []
+        }]);
+        let abi_file = normalize("IHooks", abi).unwrap();
+
+        let names: Vec<&str> = abi_file.structs.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["PoolKeyV2", "Params2"]);
+        assert_eq!(abi_file.structs[1].fields, "int24 tickLower, int256 liquidityDelta");
+
+        let function = &abi_file.functions[0];
+        assert_eq!(
+            function.signature,
+            "function modify(PoolKeyV2 calldata key, Params2[2][] calldata params) external"
+        );
+        let parsed = signature::parse_signature(&function.signature, SignatureKind::Function, &StructDefs::new(&abi_file.structs)).unwrap();
+        assert_eq!(
+            signature::canonical_signature(&parsed.name, &parsed.inputs),
+            "modify((address,uint24),(int24,int256)[2][])"
+        );
+    }
+
+    #[test]
+    fn strips_only_array_suffixes() {
+        assert_eq!(strip_array_suffixes("Params2[3][]"), "Params2");
+        assert_eq!(strip_array_suffixes("PoolKeyV2"), "PoolKeyV2");
+        assert_eq!(strip_array_suffixes("(uint256,address)[]"), "(uint256,address)");
+    }
+}
@@ -0,0 +1,115 @@
+use anyhow::{bail, Result};
+
+use crate::protocol::{AbiFile, AbiFunction, AbiParam};
+use crate::solc_abi;
+
+// Renders a compilable Solidity interface from a loaded ABI, for pasting at
+// the top of a trap in place of a hand-written interface block.
+
+// View and pure functions by default, or the named subset (all overloads of each name)
+pub fn select_functions<'a>(abi: &'a AbiFile, names: Option<&[String]>) -> Result<Vec<&'a AbiFunction>> {
+    let Some(names) = names else {
+        return Ok(abi
+            .functions
+            .iter()
+            .filter(|f| matches!(f.state_mutability.as_deref(), Some("view") | Some("pure")))
+            .collect());
+    };
+
+    let mut selected = Vec::new();
+    for name in names {
+        let before = selected.len();
+        selected.extend(abi.functions.iter().filter(|f| &f.name == name));
+        if selected.len() == before {
+            bail!("function '{}' not found in {}", name, abi.interface);
+        }
+    }
+    Ok(selected)
+}
+
+pub fn render_interface(abi: &AbiFile, functions: &[&AbiFunction], source_uri: &str) -> Result<String> {
+    let mut structs = Vec::new();
+    for function in functions {
+        for param in function.inputs.iter().chain(&function.outputs) {
+            collect_struct_definitions(param, &mut structs)?;
+        }
+    }
+
+    let mut source = String::new();
+    source.push_str("// SPDX-License-Identifier: MIT\n");
+    source.push_str("pragma solidity ^0.8.19;\n\n");
+    source.push_str(&format!("// Generated from {}\n", source_uri));
+    source.push_str(&format!("interface {} {{\n", abi.interface));
+
+    for (name, members) in &structs {
+        source.push_str(&format!("    struct {} {{\n", name));
+        for (i, member) in members.iter().enumerate() {
+            let member_name = if member.name.is_empty() { format!("field{}", i) } else { member.name.clone() };
+            source.push_str(&format!("        {} {};\n", solc_abi::solidity_type(member), member_name));
+        }
+        source.push_str("    }\n\n");
+    }
+
+    for function in functions {
+        let state_mutability = function.state_mutability.as_deref().unwrap_or("nonpayable");
+        let signature = solc_abi::function_signature(&function.name, &function.inputs, &function.outputs, state_mutability);
+        source.push_str(&format!("    {};\n", signature));
+    }
+
+    source.push_str("}\n");
+    Ok(source)
+}
+
+// Struct definitions referenced by a parameter, dependencies first
+fn collect_struct_definitions(param: &AbiParam, structs: &mut Vec<(String, Vec<AbiParam>)>) -> Result<()> {
+    if !param.ty.starts_with("tuple") {
+        return Ok(());
+    }
+    for component in &param.components {
+        collect_struct_definitions(component, structs)?;
+    }
+
+    let type_name = solc_abi::solidity_type(param);
+    let name = solc_abi::strip_array_suffixes(&type_name);
+    if name.starts_with('(') {
+        bail!(
+            "parameter '{}' is an unnamed tuple; declare it as a struct in the ABI to render an interface",
+            param.name
+        );
+    }
+    if !structs.iter().any(|(existing, _)| existing == name) {
+        structs.push((name.to_string(), param.components.clone()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn declares_structs_whose_names_end_in_digits() {
+        let abi = json!([{
+            "type": "function",
+            "name": "settle",
+            "stateMutability": "nonpayable",
+            "inputs": [{
+                "name": "keys",
+                "type": "tuple[]",
+                "internalType": "struct IHooks.PoolKeyV2[]",
+                "components": [
+                    {"name": "currency0", "type": "address", "internalType": "address"},
+                    {"name": "fee", "type": "uint24", "internalType": "uint24"}
+                ]
+            }],
+            "outputs": []
+        }]);
+        let abi = solc_abi::normalize("IHooks", abi).unwrap();
+        let functions: Vec<&AbiFunction> = abi.functions.iter().collect();
+        let source = render_interface(&abi, &functions, "protocol://test/abi/IHooks").unwrap();
+
+        assert!(source.contains("    struct PoolKeyV2 {\n        address currency0;\n        uint24 fee;\n    }\n"));
+        assert!(source.contains("function settle(PoolKeyV2[] calldata keys) external;"));
+    }
+}
@@ -0,0 +1,467 @@
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+
+use crate::abi_codec;
+use crate::protocol::{AbiParam, StorageLayout, StorageType, StorageVariable};
+use crate::signature::{keccak256, to_hex};
+
+// Storage slot computation over solc storage layouts, for traps that read raw
+// storage. A variable path is a top-level variable followed by struct member
+// accesses and mapping/array indexes:
+//
+//   totalSupply
+//   balances[0xA0b8...eB48]
+//   positions[0x1c5e...].liquidity
+//   observations[12].tickCumulative
+//
+// Mapping keys:      slot = keccak256(pad32(key) . slot), keccak256(key . slot) for string/bytes keys
+// Dynamic arrays:    data starts at keccak256(slot); elements of 16 bytes or less are packed
+// Static arrays:     elements start at the array's own slot, packed the same way
+// Struct members:    slot + member.slot, at member.offset
+
+type Word = [u8; 32];
+
+#[derive(Debug, Serialize)]
+pub struct SlotLocation {
+    pub path: String,
+    /// 32-byte hex slot, ready for vm.load / extsload
+    pub slot: String,
+    pub slot_decimal: String,
+    /// Byte offset of the value within the slot (from the least significant end)
+    pub offset: u32,
+    pub number_of_bytes: u64,
+    #[serde(rename = "type")]
+    pub type_label: String,
+    pub encoding: String,
+    /// Slot after each path segment
+    pub steps: Vec<SlotStep>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SlotStep {
+    pub path: String,
+    pub slot: String,
+    #[serde(rename = "type")]
+    pub type_label: String,
+}
+
+enum Segment {
+    Member(String),
+    Index(String),
+}
+
+pub fn resolve(layout: &StorageLayout, path: &str) -> Result<SlotLocation> {
+    let (root, segments) = parse_path(path)?;
+    let Some(variable) = layout.storage.iter().find(|variable| variable.label == root) else {
+        let labels: Vec<&str> = layout.storage.iter().map(|variable| variable.label.as_str()).collect();
+        bail!("no storage variable '{}' (variables: {})", root, labels.join(", "));
+    };
+
+    let mut slot = parse_uint(&variable.slot)?;
+    let mut offset = variable.offset;
+    let mut type_id = variable.ty.as_str();
+    let mut walked = root.clone();
+    let mut steps = vec![SlotStep { path: walked.clone(), slot: to_hex(&slot), type_label: type_of(layout, type_id)?.label.clone() }];
+
+    for segment in &segments {
+        let ty = type_of(layout, type_id)?;
+        match segment {
+            Segment::Index(key) if ty.encoding == "mapping" => {
+                let key_type = type_of(layout, ty.key.as_deref().context("mapping type has no key")?)?;
+                let mut preimage = encode_key(key_type, key)?;
+                preimage.extend_from_slice(&slot);
+                slot = keccak256(&preimage);
+                offset = 0;
+                type_id = ty.value.as_deref().context("mapping type has no value")?;
+                walked.push_str(&format!("[{}]", key));
+            }
+            Segment::Index(index) if ty.base.is_some() && (ty.encoding == "dynamic_array" || ty.encoding == "inplace") => {
+                let base_id = ty.base.as_deref().expect("checked above");
+                let index_word = parse_uint(index).with_context(|| format!("array index '{}' is not a number", index))?;
+                let start = if ty.encoding == "dynamic_array" {
+                    keccak256(&slot)
+                } else {
+                    if let Some(length) = static_length(&ty.label) {
+                        if index_word[..24].iter().any(|b| *b != 0) || u64::from_be_bytes(index_word[24..].try_into()?) >= length {
+                            bail!("index {} is out of bounds for {}", index, ty.label);
+                        }
+                    }
+                    slot
+                };
+                let element_bytes = number_of_bytes(type_of(layout, base_id)?)?;
+                if element_bytes == 0 {
+                    bail!("element type of {} has no size", ty.label);
+                }
+                if element_bytes <= 16 {
+                    let per_slot = 32 / element_bytes;
+                    let (slot_index, position) = divmod_small(&index_word, per_slot);
+                    slot = add(&start, &slot_index);
+                    offset = (position * element_bytes) as u32;
+                } else {
+                    slot = add(&start, &mul_small(&index_word, element_bytes.div_ceil(32)));
+                    offset = 0;
+                }
+                type_id = base_id;
+                walked.push_str(&format!("[{}]", index));
+            }
+            Segment::Member(name) if ty.members.is_some() => {
+                let members = ty.members.as_deref().expect("checked above");
+                let Some(member) = members.iter().find(|member| member.label == *name) else {
+                    let labels: Vec<&str> = members.iter().map(|member| member.label.as_str()).collect();
+                    bail!("{} has no member '{}' (members: {})", ty.label, name, labels.join(", "));
+                };
+                slot = add(&slot, &parse_uint(&member.slot)?);
+                offset = member.offset;
+                type_id = &member.ty;
+                walked.push_str(&format!(".{}", name));
+            }
+            Segment::Index(key) => bail!("cannot index {} ({}) with [{}]", walked, ty.label, key),
+            Segment::Member(name) => bail!("cannot access .{} on {} ({})", name, walked, ty.label),
+        }
+        steps.push(SlotStep { path: walked.clone(), slot: to_hex(&slot), type_label: type_of(layout, type_id)?.label.clone() });
+    }
+
+    let ty = type_of(layout, type_id)?;
+    let note = match ty.encoding.as_str() {
+        "mapping" => Some("mapping slots hold no data; index the mapping to reach a value".to_string()),
+        "dynamic_array" => Some(format!("this slot holds the length; elements start at {}", to_hex(&keccak256(&slot)))),
+        "bytes" => Some(format!(
+            "values under 32 bytes are stored here with length * 2 in the lowest byte; longer values store length * 2 + 1 here and the data from {}",
+            to_hex(&keccak256(&slot))
+        )),
+        _ if ty.members.is_some() => Some("struct; access a member to get its slot and offset".to_string()),
+        _ => None,
+    };
+    Ok(SlotLocation {
+        path: walked,
+        slot: to_hex(&slot),
+        slot_decimal: abi_codec::to_decimal(&slot),
+        offset,
+        number_of_bytes: number_of_bytes(ty)?,
+        type_label: ty.label.clone(),
+        encoding: ty.encoding.clone(),
+        steps,
+        note,
+    })
+}
+
+// Every type a layout references must be described, with numeric slots and sizes
+pub fn check(layout: &StorageLayout) -> Result<()> {
+    fn check_variables(layout: &StorageLayout, variables: &[StorageVariable], depth: usize) -> Result<()> {
+        for variable in variables {
+            parse_uint(&variable.slot).with_context(|| format!("slot of {}", variable.label))?;
+            check_type(layout, &variable.ty, depth).with_context(|| format!("type of {}", variable.label))?;
+        }
+        Ok(())
+    }
+    fn check_type(layout: &StorageLayout, type_id: &str, depth: usize) -> Result<()> {
+        if depth > 32 {
+            bail!("type {} nests too deeply", type_id);
+        }
+        let ty = type_of(layout, type_id)?;
+        number_of_bytes(ty)?;
+        for referenced in [&ty.key, &ty.value, &ty.base].into_iter().flatten() {
+            check_type(layout, referenced, depth + 1)?;
+        }
+        if let Some(members) = &ty.members {
+            check_variables(layout, members, depth + 1)?;
+        }
+        Ok(())
+    }
+    check_variables(layout, &layout.storage, 0)
+}
+
+fn type_of<'a>(layout: &'a StorageLayout, type_id: &str) -> Result<&'a StorageType> {
+    layout.types.get(type_id).with_context(|| format!("type {} is not described in the layout", type_id))
+}
+
+fn number_of_bytes(ty: &StorageType) -> Result<u64> {
+    ty.number_of_bytes.parse().with_context(|| format!("numberOfBytes of {} is not a number", ty.label))
+}
+
+// `uint256[3]` -> 3, `struct Oracle.Observation[65535]` -> 65535
+fn static_length(label: &str) -> Option<u64> {
+    let inner = label.strip_suffix(']')?;
+    inner[inner.rfind('[')? + 1..].parse().ok()
+}
+
+fn parse_path(path: &str) -> Result<(String, Vec<Segment>)> {
+    let path = path.trim();
+    let root_end = path.find(['.', '[']).unwrap_or(path.len());
+    let root = path[..root_end].trim().to_string();
+    if root.is_empty() {
+        bail!("path '{}' must start with a variable name", path);
+    }
+
+    let mut segments = Vec::new();
+    let mut rest = &path[root_end..];
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            let name = after[..end].trim();
+            if name.is_empty() {
+                bail!("empty member name in '{}'", path);
+            }
+            segments.push(Segment::Member(name.to_string()));
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            // Quoted string keys may contain ']'
+            let end = if let Some(quoted) = after.trim_start().strip_prefix('"') {
+                let close = quoted.find('"').with_context(|| format!("unterminated string key in '{}'", path))?;
+                let consumed = after.len() - quoted.len() + close + 1;
+                consumed + after[consumed..].find(']').with_context(|| format!("missing ']' in '{}'", path))?
+            } else {
+                after.find(']').with_context(|| format!("missing ']' in '{}'", path))?
+            };
+            segments.push(Segment::Index(after[..end].trim().to_string()));
+            rest = &after[end + 1..];
+        } else {
+            bail!("unexpected '{}' in '{}'", rest, path);
+        }
+    }
+    Ok((root, segments))
+}
+
+// Mapping key preimage: value types are padded to 32 bytes, string and bytes keys are used as-is
+fn encode_key(key_type: &StorageType, key: &str) -> Result<Vec<u8>> {
+    let label = key_type.label.as_str();
+    let invalid = || format!("'{}' is not a valid {} key", key, label);
+
+    if label == "string" {
+        let text = key.strip_prefix('"').and_then(|k| k.strip_suffix('"')).unwrap_or(key);
+        return Ok(text.as_bytes().to_vec());
+    }
+    if label == "bytes" {
+        return abi_codec::parse_hex(key).with_context(invalid);
+    }
+
+    // Contracts are stored as addresses and enums as uint8
+    let abi_type = if label.starts_with("contract ") || label.starts_with("address") {
+        "address".to_string()
+    } else if label.starts_with("enum ") {
+        "uint8".to_string()
+    } else {
+        label.to_string()
+    };
+
+    let word: Word = if abi_type == "address" {
+        let bytes = abi_codec::parse_hex(key).with_context(invalid)?;
+        if bytes.len() != 20 {
+            bail!(invalid());
+        }
+        left_pad(&bytes)
+    } else if abi_type == "bool" {
+        match key {
+            "true" => left_pad(&[1]),
+            "false" => [0; 32],
+            _ => bail!(invalid()),
+        }
+    } else if let Some(size) = abi_type.strip_prefix("bytes") {
+        let size: usize = size.parse().with_context(invalid)?;
+        let bytes = abi_codec::parse_hex(key).with_context(invalid)?;
+        if bytes.len() > size {
+            bail!(invalid());
+        }
+        let mut word = [0; 32];
+        word[..bytes.len()].copy_from_slice(&bytes);
+        word
+    } else if abi_type.starts_with("int") {
+        let (negative, digits) = match key.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, key),
+        };
+        let magnitude = parse_uint(digits).with_context(invalid)?;
+        abi_codec::ensure_int_magnitude(&abi_type, &magnitude, negative).with_context(invalid)?;
+        if negative { negate(&magnitude) } else { magnitude }
+    } else if abi_type.starts_with("uint") {
+        parse_uint(key).with_context(invalid)?
+    } else {
+        bail!("unsupported mapping key type {}", label);
+    };
+
+    // Range and padding checks: the word must decode back as the key type
+    let param = AbiParam { name: String::new(), ty: abi_type, internal_type: None, components: Vec::new(), indexed: None };
+    abi_codec::decode_word(&param, &word).with_context(invalid)?;
+    Ok(word.to_vec())
+}
+
+fn left_pad(bytes: &[u8]) -> Word {
+    let mut word = [0; 32];
+    word[32 - bytes.len()..].copy_from_slice(bytes);
+    word
+}
+
+// Decimal or 0x-hex unsigned 256-bit integer
+fn parse_uint(input: &str) -> Result<Word> {
+    let input = input.trim();
+    if let Some(hex) = input.strip_prefix("0x") {
+        let bytes = abi_codec::parse_hex(&format!("{}{}", if hex.len().is_multiple_of(2) { "" } else { "0" }, hex))?;
+        if bytes.len() > 32 {
+            bail!("{} does not fit in 256 bits", input);
+        }
+        return Ok(left_pad(&bytes));
+    }
+    if input.is_empty() || !input.chars().all(|c| c.is_ascii_digit()) {
+        bail!("'{}' is not a number", input);
+    }
+    let mut word = [0u8; 32];
+    for digit in input.bytes().map(|c| (c - b'0') as u64) {
+        let (product, overflow) = mul_small_carry(&word, 10);
+        if overflow != 0 {
+            bail!("{} does not fit in 256 bits", input);
+        }
+        let sum = add(&product, &left_pad(&[digit as u8]));
+        if sum < product {
+            bail!("{} does not fit in 256 bits", input);
+        }
+        word = sum;
+    }
+    Ok(word)
+}
+
+fn add(a: &Word, b: &Word) -> Word {
+    let mut out = [0u8; 32];
+    let mut carry = 0u16;
+    for i in (0..32).rev() {
+        let sum = a[i] as u16 + b[i] as u16 + carry;
+        out[i] = sum as u8;
+        carry = sum >> 8;
+    }
+    out
+}
+
+fn mul_small(a: &Word, m: u64) -> Word {
+    mul_small_carry(a, m).0
+}
+
+fn mul_small_carry(a: &Word, m: u64) -> (Word, u128) {
+    let mut out = [0u8; 32];
+    let mut carry = 0u128;
+    for i in (0..32).rev() {
+        let product = a[i] as u128 * m as u128 + carry;
+        out[i] = product as u8;
+        carry = product >> 8;
+    }
+    (out, carry)
+}
+
+fn divmod_small(a: &Word, d: u64) -> (Word, u64) {
+    let mut out = [0u8; 32];
+    let mut remainder = 0u128;
+    for (quotient, byte) in out.iter_mut().zip(a) {
+        let value = (remainder << 8) | *byte as u128;
+        *quotient = (value / d as u128) as u8;
+        remainder = value % d as u128;
+    }
+    (out, remainder as u64)
+}
+
+fn negate(word: &Word) -> Word {
+    add(&word.map(|b| !b), &left_pad(&[1]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn bundled(path: &str) -> StorageLayout {
+        let text = std::fs::read_to_string(path).expect("bundled layout exists");
+        serde_json::from_str(&text).expect("bundled layout parses")
+    }
+
+    // values: uint64[] at slot 0, limits: uint128[4] at slots 1-2, byTick: mapping(int256 => uint256) at slot 3
+    fn packed_layout() -> StorageLayout {
+        serde_json::from_value(json!({
+            "storage": [
+                { "label": "values", "offset": 0, "slot": "0", "type": "t_array(t_uint64)dyn_storage" },
+                { "label": "limits", "offset": 0, "slot": "1", "type": "t_array(t_uint128)4_storage" },
+                { "label": "byTick", "offset": 0, "slot": "3", "type": "t_mapping(t_int256,t_uint256)" }
+            ],
+            "types": {
+                "t_array(t_uint64)dyn_storage": { "base": "t_uint64", "encoding": "dynamic_array", "label": "uint64[]", "numberOfBytes": "32" },
+                "t_array(t_uint128)4_storage": { "base": "t_uint128", "encoding": "inplace", "label": "uint128[4]", "numberOfBytes": "64" },
+                "t_mapping(t_int256,t_uint256)": { "encoding": "mapping", "key": "t_int256", "value": "t_uint256", "label": "mapping(int256 => uint256)", "numberOfBytes": "32" },
+                "t_int256": { "encoding": "inplace", "label": "int256", "numberOfBytes": "32" },
+                "t_uint64": { "encoding": "inplace", "label": "uint64", "numberOfBytes": "8" },
+                "t_uint128": { "encoding": "inplace", "label": "uint128", "numberOfBytes": "16" },
+                "t_uint256": { "encoding": "inplace", "label": "uint256", "numberOfBytes": "32" }
+            }
+        }))
+        .expect("valid layout")
+    }
+
+    #[test]
+    fn bundled_layouts_check() {
+        check(&bundled("src/data/protocols/maker/storage_layout/Vat.json")).unwrap();
+        check(&bundled("src/data/protocols/uniswap/storage_layout/UniswapV3Pool.json")).unwrap();
+    }
+
+    #[test]
+    fn nested_mapping_struct_member() {
+        let vat = bundled("src/data/protocols/maker/storage_layout/Vat.json");
+        let art = resolve(&vat, "urns[0x4554482d41][0x1111111111111111111111111111111111111111].art").unwrap();
+        let steps: Vec<&str> = art.steps.iter().map(|step| step.slot.as_str()).collect();
+        assert_eq!(
+            steps,
+            [
+                "0x0000000000000000000000000000000000000000000000000000000000000003",
+                "0xe7259ff59164a72b5f35e5528f8180bdd0ad91aeb7f85f65a9d56666dc29dea9",
+                "0xd66fa4ee5a7c8821ebb7f1791c5f0284ca3f7f4e61da21e9b8110d92de43fa55",
+                "0xd66fa4ee5a7c8821ebb7f1791c5f0284ca3f7f4e61da21e9b8110d92de43fa56",
+            ]
+        );
+        assert_eq!((art.offset, art.type_label.as_str()), (0, "uint256"));
+        assert!(resolve(&vat, "urns[0x4554482d41][0x11]").is_err());
+    }
+
+    #[test]
+    fn static_struct_array() {
+        let pool = bundled("src/data/protocols/uniswap/storage_layout/UniswapV3Pool.json");
+        let observation = resolve(&pool, "observations[3].tickCumulative").unwrap();
+        assert_eq!(observation.slot_decimal, "11");
+        assert_eq!((observation.offset, observation.number_of_bytes), (4, 7));
+        assert!(resolve(&pool, "observations[65534]").is_ok());
+        assert!(resolve(&pool, "observations[65535]").is_err());
+    }
+
+    #[test]
+    fn signed_mapping_keys() {
+        let pool = bundled("src/data/protocols/uniswap/storage_layout/UniswapV3Pool.json");
+        let word = resolve(&pool, "tickBitmap[-1]").unwrap();
+        assert_eq!(word.slot, "0x63187d71e139eee983a88d0737447c7451979b3dbb75903c76b5fe430d36588e");
+        assert!(resolve(&pool, "tickBitmap[32768]").is_err());
+        assert!(resolve(&pool, "tickBitmap[-32769]").is_err());
+
+        // int256 keys at the bounds: -2^255 fits, 2^255 and -(2^255 + 1) do not
+        let layout = packed_layout();
+        let min = resolve(&layout, "byTick[-57896044618658097711785492504343953926634992332820282019728792003956564819968]").unwrap();
+        assert_eq!(min.slot, "0xdc3c184d5d67fe93f9e42d921d7905515ad0fac8e1a301c506fe8afaba1f093a");
+        assert!(resolve(&layout, "byTick[57896044618658097711785492504343953926634992332820282019728792003956564819967]").is_ok());
+        assert!(resolve(&layout, "byTick[57896044618658097711785492504343953926634992332820282019728792003956564819968]").is_err());
+        assert!(resolve(&layout, "byTick[-57896044618658097711785492504343953926634992332820282019728792003956564819969]").is_err());
+    }
+
+    #[test]
+    fn packed_arrays() {
+        let layout = packed_layout();
+        // Four uint64 per slot: element 5 is the second in the data's second slot
+        let value = resolve(&layout, "values[5]").unwrap();
+        assert_eq!(value.slot, "0x290decd9548b62a8d60345a988386fc84ba6bc95484008f6362f93160ef3e564");
+        assert_eq!(value.offset, 8);
+
+        let limit = resolve(&layout, "limits[3]").unwrap();
+        assert_eq!((limit.slot_decimal.as_str(), limit.offset), ("2", 16));
+        assert!(resolve(&layout, "limits[4]").is_err());
+    }
+
+    #[test]
+    fn rejects_bad_paths() {
+        let layout = packed_layout();
+        for path in ["", "missing", "values.length", "values[x]", "byTick[1", "limits[0][0]"] {
+            assert!(resolve(&layout, path).is_err(), "{} should not resolve", path);
+        }
+    }
+}
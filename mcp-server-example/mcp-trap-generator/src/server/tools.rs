@@ -0,0 +1,1119 @@
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::protocol::{AbiParam, StorageLayout};
+use crate::{abi_codec, address_book, drosera_toml, event_catalog, evm, exploit_port, foundry, replay, response_scaffold, scenario_catalog, signature, solidity_interface, storage_layout, trap_analysis, DroseraServer};
+
+// MCP tools exposed through tools/list and tools/call. Each tool takes its
+// arguments as a JSON object and returns a JSON value rendered as text content.
+
+pub fn definitions() -> Vec<Value> {
+    vec![json!({
+        "name": "lookup_selector",
+        "description": "Map a 4-byte function/error selector (or calldata starting with one) or a 32-byte event topic0 back to the matching entries in the loaded protocol ABIs",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "selector": {
+                    "type": "string",
+                    "description": "Hex selector such as 0x414bf389, calldata, or a 32-byte event topic"
+                }
+            },
+            "required": ["selector"]
+        }
+    }), json!({
+        "name": "generate_interface",
+        "description": "Render a compilable Solidity interface (with struct definitions) from a loaded protocol ABI. Includes the view/pure functions unless a subset is requested",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "protocol": {
+                    "type": "string",
+                    "description": "Protocol name, e.g. uniswap"
+                },
+                "interface": {
+                    "type": "string",
+                    "description": "ABI name, e.g. IUniswapV3Pool"
+                },
+                "functions": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Function names to include (any mutability); defaults to all view/pure functions"
+                }
+            },
+            "required": ["protocol", "interface"]
+        }
+    }), json!({
+        "name": "resolve_address",
+        "description": "Map addresses to known protocol contract labels from the address book. Pass addresses directly or trap source code to scan; non-checksummed and unknown addresses are flagged",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "addresses": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Addresses to resolve"
+                },
+                "source": {
+                    "type": "string",
+                    "description": "Solidity source to scan for address literals"
+                },
+                "network": {
+                    "type": "string",
+                    "description": "Only match entries on this network, e.g. mainnet"
+                }
+            }
+        }
+    }), json!({
+        "name": "generate_drosera_toml",
+        "description": "Render a drosera.toml for one trap, with ethereum_rpc, drosera_rpc, eth_chain_id and drosera_address taken from the network registry",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "network": { "type": "string", "description": "Network name, alias or chain id, e.g. hoodi, holesky, mainnet" },
+                "trap_name": { "type": "string", "description": "Key under [traps], e.g. fee_change" },
+                "contract_name": { "type": "string", "description": "Trap contract name, e.g. SimpleFeeChangeTrap" },
+                "response_contract": { "type": "string", "description": "Response contract address (defaults to the zero address)" },
+                "response_function": { "type": "string", "description": "Response function signature, e.g. pause(address)" },
+                "cooldown_period_blocks": { "type": "integer", "minimum": 0 },
+                "min_number_of_operators": { "type": "integer", "minimum": 1 },
+                "max_number_of_operators": { "type": "integer", "minimum": 1 },
+                "block_sample_size": { "type": "integer", "minimum": 1 },
+                "private_trap": { "type": "boolean" },
+                "whitelist": { "type": "array", "items": { "type": "string" } }
+            },
+            "required": ["network", "trap_name", "contract_name", "response_function"]
+        }
+    }), json!({
+        "name": "decode_log",
+        "description": "Decode a raw event log (topics and data) against every event signature in the loaded protocol ABIs. Indexed strings, bytes, arrays and structs come back as their hash",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "topics": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "minItems": 1,
+                    "maxItems": 4,
+                    "description": "32-byte hex topics, topic0 first"
+                },
+                "data": {
+                    "type": "string",
+                    "description": "Hex log data (defaults to 0x)"
+                },
+                "address": {
+                    "type": "string",
+                    "description": "Emitting contract; matches from protocols that list it are returned first"
+                }
+            },
+            "required": ["topics"]
+        }
+    }), json!({
+        "name": "storage_slot",
+        "description": "Compute the storage slot and byte offset of a variable path such as balances[0xabc...] or positions[0x...].liquidity from a solc storage layout, following mapping, array and struct rules",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "path": {
+                    "type": "string",
+                    "description": "Variable path: a state variable followed by [key] and .member accesses"
+                },
+                "protocol": {
+                    "type": "string",
+                    "description": "Protocol whose storage_layout to use, e.g. uniswap"
+                },
+                "contract": {
+                    "type": "string",
+                    "description": "Contract name of the storage layout, e.g. UniswapV3Pool"
+                },
+                "storage_layout": {
+                    "type": "object",
+                    "description": "solc storageLayout output to use instead of a protocol's layout"
+                }
+            },
+            "required": ["path"]
+        }
+    }), json!({
+        "name": "find_scenarios",
+        "description": "Search the monitoring scenarios of all loaded protocols. Each result links its trap categories, example traps, target addresses, and the ABI functions and events it needs",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "protocol": {
+                    "type": "string",
+                    "description": "Only scenarios of this protocol, e.g. aave"
+                },
+                "category": {
+                    "type": "string",
+                    "description": "Only scenarios in this trap category, e.g. price_oracle_trap"
+                },
+                "function": {
+                    "type": "string",
+                    "description": "Only scenarios that need this function, given as a name (latestRoundData), canonical signature or selector"
+                }
+            }
+        }
+    }), json!({
+        "name": "compile_trap",
+        "description": "Compile trap sources offline in a temporary Foundry project with a locally installed forge or solc. Returns diagnostics (file, line, severity, message) and the ABI and bytecode size of each contract",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "sources": {
+                    "type": "object",
+                    "additionalProperties": { "type": "string" },
+                    "description": "Project-relative path (e.g. src/MyTrap.sol; bare file names go under src/) -> Solidity source"
+                },
+                "source": {
+                    "type": "string",
+                    "description": "A single Solidity source, written to src/Trap.sol"
+                },
+                "compiler": {
+                    "type": "string",
+                    "enum": ["forge", "solc"],
+                    "description": "Compiler to use (defaults to forge, then solc, from PATH)"
+                },
+                "dependencies": {
+                    "type": "string",
+                    "description": "node_modules directory providing forge-std, contracts (drosera-contracts/) and @openzeppelin/contracts, as installed by a trap example. Without contracts, Drosera imports resolve to the bundled drosera-lib:// sources"
+                },
+                "remappings": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Additional prefix=path remappings"
+                }
+            }
+        }
+    }), json!({
+        "name": "test_trap",
+        "description": "Run the Foundry tests of a trap offline (forge test --json) in a temporary project and return per-test status, gas, revert reason and console.log output. Test files that fork a live chain are left out",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "sources": {
+                    "type": "object",
+                    "additionalProperties": { "type": "string" },
+                    "description": "Project-relative path -> Solidity source, e.g. src/MyTrap.sol and test/MyTrap.t.sol (bare *.t.sol names go under test/)"
+                },
+                "dependencies": {
+                    "type": "string",
+                    "description": "node_modules directory providing forge-std, contracts (drosera-contracts/) and @openzeppelin/contracts, as installed by a trap example. Without contracts, Drosera imports resolve to the bundled drosera-lib:// sources"
+                },
+                "remappings": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Additional prefix=path remappings"
+                },
+                "match_test": {
+                    "type": "string",
+                    "description": "Only run tests whose name matches this regex"
+                },
+                "match_contract": {
+                    "type": "string",
+                    "description": "Only run test contracts whose name matches this regex"
+                }
+            },
+            "required": ["sources"]
+        }
+    }), json!({
+        "name": "analyze_trap",
+        "description": "Statically analyze a trap's Solidity source: the data collect() returns via abi.encode (structs expanded to ABI types), the external calls it makes, which samples shouldRespond reads and decodes (data[0], data[1], ...), and the block_sample_size it needs. With a drosera.toml, warns when block_sample_size or cooldown_period_blocks is inconsistent with the source, or drosera_address or drosera_rpc with the Drosera deployments (drosera://deployments.json)",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "source": {
+                    "type": "string",
+                    "description": "A single Solidity source containing the trap"
+                },
+                "sources": {
+                    "type": "object",
+                    "additionalProperties": { "type": "string" },
+                    "description": "File name -> Solidity source, for traps split across files"
+                },
+                "example": {
+                    "type": "string",
+                    "description": "Name of a bundled trap example (trap-example://{name}); its drosera.toml is checked too"
+                },
+                "contract": {
+                    "type": "string",
+                    "description": "Trap contract to analyze (defaults to the one implementing collect and shouldRespond)"
+                },
+                "drosera_toml": {
+                    "type": "string",
+                    "description": "drosera.toml contents to check against the source and the Drosera deployments"
+                },
+                "trap": {
+                    "type": "string",
+                    "description": "Key under [traps] in drosera_toml (defaults to the trap whose path builds the contract)"
+                },
+                "response_effect_blocks": {
+                    "type": "integer",
+                    "minimum": 0,
+                    "description": "Blocks the response needs to take effect after triggering (default 1); cooldown_period_blocks is checked against this plus the sample window"
+                }
+            }
+        }
+    }), json!({
+        "name": "evaluate_should_respond",
+        "description": "Run a trap's shouldRespond(bytes[]) on given collect() outputs in an embedded EVM (no network; calls to other contracts revert). Returns the boolean, the response bytes and, with a response_function, the decoded response arguments",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "samples": {
+                    "type": "array",
+                    "items": {
+                        "oneOf": [
+                            { "type": "string", "description": "ABI-encoded collect() output as 0x-hex" },
+                            {
+                                "type": "object",
+                                "properties": {
+                                    "types": { "type": "string", "description": "ABI types of collect()'s encoded values, e.g. (address,uint256,uint256)[]; derived from the source when omitted" },
+                                    "values": { "type": "array", "description": "JSON values for those types (integers as numbers or decimal strings, tuples as arrays or objects)" }
+                                },
+                                "required": ["values"]
+                            }
+                        ]
+                    },
+                    "description": "collect() outputs in shouldRespond order: samples[0] is data[0], the newest block"
+                },
+                "bytecode": {
+                    "type": "string",
+                    "description": "Creation bytecode of the trap (0x-hex), deployed before the call"
+                },
+                "deployed_bytecode": {
+                    "type": "string",
+                    "description": "Runtime bytecode of the trap (0x-hex), placed at the trap address without running a constructor"
+                },
+                "source": {
+                    "type": "string",
+                    "description": "Trap source, compiled with compile_trap's toolchain"
+                },
+                "sources": {
+                    "type": "object",
+                    "additionalProperties": { "type": "string" },
+                    "description": "File name -> Solidity source, compiled with compile_trap's toolchain"
+                },
+                "example": {
+                    "type": "string",
+                    "description": "Name of a bundled trap example to compile; its drosera.toml supplies response_function"
+                },
+                "contract": {
+                    "type": "string",
+                    "description": "Contract to run when compiling sources (defaults to the trap contract)"
+                },
+                "compiler": {
+                    "type": "string",
+                    "enum": ["forge", "solc"],
+                    "description": "Compiler to use (defaults to forge, then solc, from PATH)"
+                },
+                "dependencies": {
+                    "type": "string",
+                    "description": "node_modules directory providing forge-std, contracts and @openzeppelin/contracts. Without contracts, Drosera imports resolve to the bundled drosera-lib:// sources"
+                },
+                "remappings": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Additional prefix=path remappings"
+                },
+                "response_function": {
+                    "type": "string",
+                    "description": "Response function signature, e.g. handleViolation(uint256), to decode the response bytes as its arguments"
+                }
+            },
+            "required": ["samples"]
+        }
+    }), json!({
+        "name": "replay_trap",
+        "description": "Replay a trap against a block-state fixture in an embedded EVM: per block, apply the fixture's state, deploy the trap, run collect(), then shouldRespond over the newest block_sample_size samples. Reports every block and the first one that triggers. Trap examples ship no fixtures; pass one captured from an archive node",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "fixture": {
+                    "type": "object",
+                    "description": "Block-state fixture: description, network, provenance (recorded with captured_with, or illustrative), optional contract, constructor_args, block_sample_size and incident_block, and blocks of accounts and mocked calls"
+                },
+                "bytecode": {
+                    "type": "string",
+                    "description": "Creation bytecode of the trap (0x-hex); the fixture's constructor_args are appended"
+                },
+                "deployed_bytecode": {
+                    "type": "string",
+                    "description": "Runtime bytecode of the trap (0x-hex), placed without running a constructor"
+                },
+                "source": {
+                    "type": "string",
+                    "description": "Trap source, compiled with compile_trap's toolchain"
+                },
+                "sources": {
+                    "type": "object",
+                    "additionalProperties": { "type": "string" },
+                    "description": "File name -> Solidity source, compiled with compile_trap's toolchain"
+                },
+                "example": {
+                    "type": "string",
+                    "description": "Bundled trap example to compile"
+                },
+                "contract": {
+                    "type": "string",
+                    "description": "Contract to run when compiling sources (defaults to the fixture's contract)"
+                },
+                "compiler": {
+                    "type": "string",
+                    "enum": ["forge", "solc"],
+                    "description": "Compiler to use (defaults to forge, then solc, from PATH)"
+                },
+                "dependencies": {
+                    "type": "string",
+                    "description": "node_modules directory providing forge-std, contracts and @openzeppelin/contracts"
+                },
+                "remappings": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Additional prefix=path remappings"
+                },
+                "block_sample_size": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "description": "Samples per shouldRespond call (defaults to the fixture's, then drosera.toml's, then what shouldRespond reads)"
+                }
+            },
+            "required": ["fixture"]
+        }
+    }), json!({
+        "name": "abi_encode",
+        "description": "ABI-encode JSON values as abi.encode(...) does, e.g. a collect() payload or shouldRespond response. Struct, contract (as address) and enum (as uint8) names in the types resolve against a submitted trap source",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "types": {
+                    "type": "string",
+                    "description": "Comma-separated Solidity types, e.g. (address,uint256,uint256)[] or FeeData[] with a source defining FeeData"
+                },
+                "values": {
+                    "type": "array",
+                    "description": "One JSON value per type: integers as numbers or decimal/0x strings, bytes as 0x-hex, tuples as arrays or objects keyed by member name"
+                },
+                "source": {
+                    "type": "string",
+                    "description": "Solidity source declaring the structs named in 'types'"
+                },
+                "sources": {
+                    "type": "object",
+                    "additionalProperties": { "type": "string" },
+                    "description": "File name -> Solidity source declaring the structs named in 'types'"
+                },
+                "example": {
+                    "type": "string",
+                    "description": "Bundled trap example whose structs the types may name"
+                }
+            },
+            "required": ["types", "values"]
+        }
+    }), json!({
+        "name": "abi_decode",
+        "description": "Decode ABI-encoded hex (abi.decode(data, (...))) into JSON values, e.g. bytes returned by collect() or shouldRespond. Struct, contract (as address) and enum (as uint8) names in the types resolve against a submitted trap source and decode as objects keyed by member name",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "types": {
+                    "type": "string",
+                    "description": "Comma-separated Solidity types, e.g. (address,uint256,uint256)[] or FeeData[] with a source defining FeeData"
+                },
+                "data": {
+                    "type": "string",
+                    "description": "ABI-encoded data as 0x-hex"
+                },
+                "source": {
+                    "type": "string",
+                    "description": "Solidity source declaring the structs named in 'types'"
+                },
+                "sources": {
+                    "type": "object",
+                    "additionalProperties": { "type": "string" },
+                    "description": "File name -> Solidity source declaring the structs named in 'types'"
+                },
+                "example": {
+                    "type": "string",
+                    "description": "Bundled trap example whose structs the types may name"
+                }
+            },
+            "required": ["types", "data"]
+        }
+    }), json!({
+        "name": "scaffold_response_contract",
+        "description": "Generate a response contract implementing a drosera.toml response_function: callable only by the network's Drosera contract, emitting an event with its arguments, plus a Foundry test of both paths. The sources can go straight to compile_trap and test_trap",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "response_function": {
+                    "type": "string",
+                    "description": "Response function signature, e.g. executeScheduledAction(uint256,string) or pause(address target)"
+                },
+                "network": {
+                    "type": "string",
+                    "description": "Network name, alias or chain id whose Drosera address may call the response, e.g. hoodi, mainnet"
+                },
+                "contract": {
+                    "type": "string",
+                    "description": "Contract name (defaults to the function name in PascalCase plus Response)"
+                }
+            },
+            "required": ["response_function", "network"]
+        }
+    }), json!({
+        "name": "port_exploit",
+        "description": "Generate a trap project for a historical exploit, shaped like the bundled historical-exploits examples: a Snapshot of victim balances with thresholds picked from the incident, fork tests around the attack block, a README, foundry.toml and remappings.txt",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "name": { "type": "string", "description": "Protocol or incident name, e.g. Euler Finance" },
+                "contract": { "type": "string", "description": "Trap contract name (defaults to the name in PascalCase plus Trap)" },
+                "network": { "type": "string", "description": "Network name, alias or chain id the attack happened on" },
+                "attack_block": { "type": "integer", "minimum": 2, "description": "First block containing the attack" },
+                "date": { "type": "string", "description": "When it happened, e.g. March 2023" },
+                "summary": { "type": "string", "description": "What happened, for the README" },
+                "invariant": { "type": "string", "description": "The property the attack broke, e.g. DAI held by Euler never drops by more than 15M in a block" },
+                "victims": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "name": { "type": "string", "description": "Solidity identifier, e.g. eulerCore" },
+                            "address": { "type": "string" }
+                        },
+                        "required": ["name", "address"]
+                    },
+                    "description": "Contracts holding the drained assets"
+                },
+                "assets": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "symbol": { "type": "string", "description": "Token symbol used as its identifier, e.g. DAI" },
+                            "address": { "type": "string", "description": "ERC-20 address; omit for the native currency" },
+                            "decimals": { "type": "integer", "minimum": 0, "description": "Token decimals (default 18)" },
+                            "held_by": { "type": "array", "items": { "type": "string" }, "description": "Victim names holding it (default all)" },
+                            "stolen": { "type": "string", "description": "Amount drained, in whole tokens; half of it becomes the threshold unless max_outflow is given" },
+                            "max_outflow": { "type": "string", "description": "Per-block outflow that triggers, in whole tokens" },
+                            "max_outflow_bps": { "type": "integer", "minimum": 1, "maximum": 10000, "description": "Per-block outflow that triggers, in basis points of the previous balance" }
+                        },
+                        "required": ["symbol"]
+                    },
+                    "description": "Drained assets with the thresholds to watch them by"
+                }
+            },
+            "required": ["name", "network", "attack_block", "invariant", "victims", "assets"]
+        }
+    })]
+}
+
+impl DroseraServer {
+    pub(crate) fn call_tool(&self, name: &str, arguments: &Value) -> Result<Value> {
+        match name {
+            "lookup_selector" => self.lookup_selector(arguments),
+            "generate_interface" => self.generate_interface(arguments),
+            "resolve_address" => self.resolve_address(arguments),
+            "generate_drosera_toml" => self.generate_drosera_toml(arguments),
+            "decode_log" => self.decode_log(arguments),
+            "storage_slot" => self.storage_slot(arguments),
+            "find_scenarios" => self.find_scenarios(arguments),
+            "compile_trap" => self.compile_trap(arguments),
+            "test_trap" => self.test_trap(arguments),
+            "analyze_trap" => self.analyze_trap(arguments),
+            "evaluate_should_respond" => self.evaluate_should_respond(arguments),
+            "replay_trap" => self.replay_trap(arguments),
+            "abi_encode" => self.abi_encode(arguments),
+            "abi_decode" => self.abi_decode(arguments),
+            "scaffold_response_contract" => self.scaffold_response_contract(arguments),
+            "port_exploit" => self.port_exploit(arguments),
+            _ => bail!("Unknown tool: {}", name),
+        }
+    }
+
+    fn lookup_selector(&self, arguments: &Value) -> Result<Value> {
+        let input = arguments["selector"].as_str().context("missing string argument 'selector'")?;
+        let hex = input.trim().trim_start_matches("0x").to_ascii_lowercase();
+        if hex.len() < 8 || hex.len() % 2 != 0 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            bail!("'{}' is not a hex selector, calldata or event topic", input);
+        }
+
+        // Exactly 32 bytes is treated as an event topic, anything else as calldata
+        let is_topic = hex.len() == 64;
+        let needle = if is_topic { format!("0x{}", hex) } else { format!("0x{}", &hex[..8]) };
+
+        let mut protocol_names: Vec<&String> = self.protocols.keys().collect();
+        protocol_names.sort();
+
+        let mut matches = Vec::new();
+        for protocol_name in protocol_names {
+            for (interface, abi) in &self.protocols[protocol_name].abis {
+                let entry = |kind: &str, name: &str, signature: &str, canonical: &Option<String>| {
+                    json!({
+                        "protocol": protocol_name,
+                        "interface": interface,
+                        "kind": kind,
+                        "name": name,
+                        "signature": signature,
+                        "canonical_signature": canonical,
+                    })
+                };
+                if is_topic {
+                    for event in abi.events.iter().filter(|e| e.topic0.as_deref() == Some(needle.as_str())) {
+                        matches.push(entry("event", &event.name, &event.signature, &event.canonical_signature));
+                    }
+                }
+                for function in abi.functions.iter().filter(|f| f.selector.as_deref() == Some(needle.as_str())) {
+                    matches.push(entry("function", &function.name, &function.signature, &function.canonical_signature));
+                }
+                for error in abi.errors.iter().filter(|e| e.selector.as_deref() == Some(needle.as_str())) {
+                    matches.push(entry("error", &error.name, &error.signature, &error.canonical_signature));
+                }
+            }
+        }
+
+        Ok(json!({
+            "selector": needle,
+            "matches": matches
+        }))
+    }
+
+    fn generate_interface(&self, arguments: &Value) -> Result<Value> {
+        let protocol_name = arguments["protocol"].as_str().context("missing string argument 'protocol'")?;
+        let interface = arguments["interface"].as_str().context("missing string argument 'interface'")?;
+        let names: Option<Vec<String>> = match &arguments["functions"] {
+            Value::Null => None,
+            value => Some(serde_json::from_value(value.clone()).context("'functions' must be an array of strings")?),
+        };
+
+        let protocol = self.protocols.get(protocol_name).with_context(|| format!("protocol '{}' not found", protocol_name))?;
+        let abi = protocol
+            .abis
+            .get(interface)
+            .with_context(|| format!("ABI '{}' not found in protocol {}", interface, protocol_name))?;
+
+        let functions = solidity_interface::select_functions(abi, names.as_deref())?;
+        let abi_uri = format!("protocol://{}/abi/{}", protocol_name, interface);
+        Ok(Value::String(solidity_interface::render_interface(abi, &functions, &abi_uri)?))
+    }
+
+    fn resolve_address(&self, arguments: &Value) -> Result<Value> {
+        let network = arguments["network"].as_str().map(|name| self.networks.canonical_name(name));
+        if arguments["addresses"].is_null() && arguments["source"].is_null() {
+            bail!("pass 'addresses' or 'source'");
+        }
+        let mut queries: Vec<(Option<usize>, String)> = Vec::new();
+        if let Some(addresses) = arguments["addresses"].as_array() {
+            for address in addresses {
+                let address = address.as_str().context("'addresses' must be an array of strings")?;
+                queries.push((None, address.trim().to_string()));
+            }
+        }
+        if let Some(source) = arguments["source"].as_str() {
+            queries.extend(address_book::scan_addresses(source).into_iter().map(|(line, address)| (Some(line), address)));
+        }
+
+        let mut book = Vec::new();
+        let mut protocol_names: Vec<&String> = self.protocols.keys().collect();
+        protocol_names.sort();
+        for protocol_name in protocol_names {
+            let protocol = &self.protocols[protocol_name];
+            for book_network in address_book::networks(protocol) {
+                if network.as_ref().is_none_or(|n| *n == book_network) {
+                    book.extend(address_book::entries(protocol_name, protocol, &book_network));
+                }
+            }
+        }
+
+        let mut results = Vec::new();
+        for (line, address) in queries {
+            let Some(checksummed) = address_book::to_checksum_address(&address) else {
+                results.push(json!({
+                    "address": address,
+                    "line": line,
+                    "flags": ["invalid_address"]
+                }));
+                continue;
+            };
+            let labels: Vec<Value> = book
+                .iter()
+                .filter(|entry| entry.address.eq_ignore_ascii_case(&checksummed))
+                .map(|entry| json!({
+                    "protocol": entry.protocol,
+                    "network": entry.network,
+                    "role": entry.role,
+                    "kind": entry.kind,
+                    "abi": entry.abi,
+                }))
+                .collect();
+
+            let mut flags = Vec::new();
+            if checksummed != address {
+                flags.push("not_checksummed");
+            }
+            if labels.is_empty() {
+                flags.push("unknown");
+            }
+            results.push(json!({
+                "address": address,
+                "line": line,
+                "checksummed_address": checksummed,
+                "labels": labels,
+                "flags": flags
+            }));
+        }
+
+        Ok(json!({ "results": results }))
+    }
+
+    fn generate_drosera_toml(&self, arguments: &Value) -> Result<Value> {
+        let network_name = arguments["network"].as_str().context("missing string argument 'network'")?;
+        let network = self.networks.resolve(network_name).with_context(|| {
+            format!("unknown network '{}' (known: {})", network_name, self.networks.names().join(", "))
+        })?;
+        let trap: drosera_toml::TrapTomlConfig =
+            serde_json::from_value(arguments.clone()).context("invalid trap configuration")?;
+        Ok(Value::String(drosera_toml::render(network, &trap)?))
+    }
+    fn decode_log(&self, arguments: &Value) -> Result<Value> {
+        let topics: Vec<String> =
+            serde_json::from_value(arguments["topics"].clone()).context("'topics' must be an array of hex strings")?;
+        if topics.is_empty() || topics.len() > 4 {
+            bail!("a log has 1 to 4 topics, got {}", topics.len());
+        }
+        let topics = topics
+            .iter()
+            .map(|topic| match abi_codec::parse_hex(topic)? {
+                bytes if bytes.len() == 32 => Ok(bytes),
+                _ => bail!("topic '{}' is not 32 bytes", topic),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let data = abi_codec::parse_hex(arguments["data"].as_str().unwrap_or("0x"))?;
+        let address = match arguments["address"].as_str() {
+            Some(address) => Some(
+                address_book::to_checksum_address(address.trim())
+                    .with_context(|| format!("'{}' is not an address", address))?,
+            ),
+            None => None,
+        };
+        let topic0 = signature::to_hex(&topics[0]);
+
+        let mut protocol_names: Vec<&String> = self.protocols.keys().collect();
+        protocol_names.sort();
+
+        let mut matches = Vec::new();
+        let mut rejected = Vec::new();
+        for protocol_name in protocol_names {
+            let protocol = &self.protocols[protocol_name];
+            for (interface, abi) in &protocol.abis {
+                // Anonymous events have no topic0 to match on
+                let candidates = abi.events.iter().filter(|e| !e.anonymous && e.topic0.as_deref() == Some(topic0.as_str()));
+                for event in candidates {
+                    let args = match event_catalog::decode_event(event, &topics[1..], &data) {
+                        Ok(args) => args,
+                        Err(e) => {
+                            rejected.push(json!({
+                                "protocol": protocol_name,
+                                "interface": interface,
+                                "event": event.name,
+                                "error": format!("{:#}", e),
+                            }));
+                            continue;
+                        }
+                    };
+                    let emitters: Vec<Value> = address
+                        .iter()
+                        .flat_map(|address| {
+                            address_book::networks(protocol)
+                                .into_iter()
+                                .flat_map(|network| address_book::entries(protocol_name, protocol, &network))
+                                .filter(move |entry| entry.address == *address)
+                        })
+                        .filter(|entry| entry.abi.as_deref().is_none_or(|abi| abi == interface))
+                        .map(|entry| json!({ "network": entry.network, "role": entry.role }))
+                        .collect();
+                    matches.push(json!({
+                        "protocol": protocol_name,
+                        "interface": interface,
+                        "event": event.name,
+                        "signature": event.signature,
+                        "uri": format!("protocol://{}/events/{}", protocol_name, event.name),
+                        "emitters": emitters,
+                        "args": args,
+                    }));
+                }
+            }
+        }
+        // Prefer decodings whose protocol lists the emitting address
+        matches.sort_by_key(|m| m["emitters"].as_array().is_none_or(|emitters| emitters.is_empty()));
+
+        let mut result = json!({
+            "topic0": topic0,
+            "address": address,
+            "matches": matches,
+        });
+        if !rejected.is_empty() {
+            result["rejected"] = Value::Array(rejected);
+        }
+        Ok(result)
+    }
+    fn storage_slot(&self, arguments: &Value) -> Result<Value> {
+        let path = arguments["path"].as_str().context("missing string argument 'path'")?;
+        let inline_layout: StorageLayout;
+        let layout = if arguments["storage_layout"].is_object() {
+            inline_layout = serde_json::from_value(arguments["storage_layout"].clone()).context("invalid 'storage_layout'")?;
+            storage_layout::check(&inline_layout)?;
+            &inline_layout
+        } else {
+            let protocol_name = arguments["protocol"].as_str().context("pass 'storage_layout' or 'protocol' and 'contract'")?;
+            let contract = arguments["contract"].as_str().context("missing string argument 'contract'")?;
+            let protocol = self.protocols.get(protocol_name).with_context(|| format!("protocol '{}' not found", protocol_name))?;
+            protocol.storage_layouts.get(contract).with_context(|| {
+                let known: Vec<&str> = protocol.storage_layouts.keys().map(String::as_str).collect();
+                format!("no storage layout for {} in protocol {} (available: {})", contract, protocol_name, known.join(", "))
+            })?
+        };
+        Ok(serde_json::to_value(storage_layout::resolve(layout, path)?)?)
+    }
+
+    fn find_scenarios(&self, arguments: &Value) -> Result<Value> {
+        let protocol_filter = arguments["protocol"].as_str();
+        let category = arguments["category"].as_str();
+        let function = arguments["function"].as_str();
+        if let Some(protocol_name) = protocol_filter {
+            if !self.protocols.contains_key(protocol_name) {
+                bail!("protocol '{}' not found", protocol_name);
+            }
+        }
+
+        let mut protocol_names: Vec<&String> = self.protocols.keys().collect();
+        protocol_names.sort();
+
+        let mut scenarios = Vec::new();
+        for protocol_name in protocol_names.into_iter().filter(|name| protocol_filter.is_none_or(|p| p == name.as_str())) {
+            let protocol = &self.protocols[protocol_name];
+            for name in scenario_catalog::scenario_names(protocol) {
+                let Some(entry) = scenario_catalog::scenario_entry(protocol_name, protocol, name, &self.trap_examples) else {
+                    continue;
+                };
+                if category.is_some_and(|category| !scenario_catalog::in_category(&entry, category))
+                    || function.is_some_and(|function| !scenario_catalog::uses_function(&entry, function))
+                {
+                    continue;
+                }
+                scenarios.push(entry);
+            }
+        }
+
+        Ok(json!({ "scenarios": scenarios }))
+    }
+
+    fn compile_trap(&self, arguments: &Value) -> Result<Value> {
+        let mut sources: BTreeMap<String, String> = match &arguments["sources"] {
+            Value::Null => BTreeMap::new(),
+            value => serde_json::from_value(value.clone()).context("'sources' must map paths to source strings")?,
+        };
+        if let Some(source) = arguments["source"].as_str() {
+            sources.insert("src/Trap.sol".to_string(), source.to_string());
+        }
+        if sources.is_empty() {
+            bail!("pass 'source' or 'sources'");
+        }
+        let remappings = remappings_argument(arguments)?;
+
+        let compiler = foundry::Compiler::detect(arguments["compiler"].as_str())?;
+        let project = foundry::Project::create(&sources, arguments["dependencies"].as_str().map(Path::new), &remappings)?;
+        Ok(serde_json::to_value(project.compile(&compiler)?)?)
+    }
+
+    fn test_trap(&self, arguments: &Value) -> Result<Value> {
+        let sources: BTreeMap<String, String> =
+            serde_json::from_value(arguments["sources"].clone()).context("'sources' must map paths to source strings")?;
+        let remappings = remappings_argument(arguments)?;
+        let filter = foundry::TestFilter {
+            match_test: arguments["match_test"].as_str().map(str::to_string),
+            match_contract: arguments["match_contract"].as_str().map(str::to_string),
+        };
+
+        // Fork tests need an RPC endpoint; everything else runs against a blank local chain
+        let (fork_tests, sources): (BTreeMap<String, String>, BTreeMap<String, String>) =
+            sources.into_iter().partition(|(path, source)| path.ends_with(".t.sol") && foundry::is_fork_test(source));
+        if !sources.keys().any(|path| path.ends_with(".t.sol")) {
+            bail!("no non-fork test files (*.t.sol) in 'sources'");
+        }
+
+        let compiler = foundry::Compiler::detect(Some("forge"))?;
+        let project = foundry::Project::create(&sources, arguments["dependencies"].as_str().map(Path::new), &remappings)?;
+        let mut result = serde_json::to_value(project.test(&compiler, &filter)?)?;
+        result["excluded_fork_tests"] = json!(fork_tests.keys().collect::<Vec<_>>());
+        Ok(result)
+    }
+
+    fn analyze_trap(&self, arguments: &Value) -> Result<Value> {
+        let (sources, example) = self.trap_sources(arguments)?;
+        let toml_text = arguments["drosera_toml"].as_str().or_else(|| example.and_then(|e| e["drosera_toml"].as_str()));
+
+        let analysis = trap_analysis::analyze(&sources, arguments["contract"].as_str())?;
+        let mut result = serde_json::to_value(&analysis)?;
+        if let Some(toml_text) = toml_text {
+            let traps = drosera_toml::parse_traps(toml_text)?;
+            let trap = drosera_toml::select_trap(&traps, arguments["trap"].as_str(), &analysis.contract)?;
+            let required = analysis.should_respond.as_ref().map(|s| s.required_block_sample_size);
+            let effect_blocks = match &arguments["response_effect_blocks"] {
+                Value::Null => trap_analysis::DEFAULT_RESPONSE_EFFECT_BLOCKS,
+                value => value.as_u64().context("'response_effect_blocks' must be a non-negative integer")?,
+            };
+            let mut warnings = drosera_toml::check_deployment(toml_text, &self.networks)?;
+            warnings.extend(trap_analysis::check_config(&analysis, trap, effect_blocks));
+            result["drosera_toml"] = json!({
+                "trap": trap,
+                "required_block_sample_size": required,
+                "sufficient": required.map(|required| trap.block_sample_size >= required),
+                "warnings": warnings,
+            });
+        }
+        Ok(result)
+    }
+
+    fn evaluate_should_respond(&self, arguments: &Value) -> Result<Value> {
+        let samples = arguments["samples"].as_array().context("missing array argument 'samples'")?;
+
+        let trap = self.trap_code(arguments, None)?;
+        let collect_types = trap.collect_types.clone();
+
+        // Samples as passed to shouldRespond: data[0] is the newest block.
+        // Struct, contract and enum names in 'types' resolve against the trap sources
+        let user_types = self.source_types(arguments)?;
+        let structs = user_types.defs();
+        let mut encoded = Vec::with_capacity(samples.len());
+        for (i, sample) in samples.iter().enumerate() {
+            let bytes = match sample {
+                Value::String(hex) => abi_codec::parse_hex(hex).with_context(|| format!("samples[{}]", i))?,
+                Value::Object(described) => {
+                    let types = described.get("types").and_then(Value::as_str).map(str::to_string).or_else(|| collect_types.clone());
+                    let types = types.with_context(|| format!("samples[{}] needs 'types' (collect()'s return types could not be derived)", i))?;
+                    let params = signature::parse_types(&types, &structs).with_context(|| format!("samples[{}]", i))?;
+                    let values = described.get("values").and_then(Value::as_array).with_context(|| format!("samples[{}] needs a 'values' array", i))?;
+                    abi_codec::encode(&params, values).with_context(|| format!("samples[{}]", i))?
+                }
+                _ => bail!("samples[{}] must be a hex string or an object with 'values' (and 'types')", i),
+            };
+            encoded.push(bytes);
+        }
+
+        let mut sandbox = evm::Sandbox::new();
+        let address = sandbox.deploy(trap.code)?;
+        let outcome = sandbox.call(address, evm::should_respond_calldata(&encoded))?;
+        let mut result = json!({
+            "contract": trap.contract,
+            "samples": encoded.len(),
+            "success": outcome.success,
+            "gas_used": outcome.gas_used,
+        });
+        if !outcome.success {
+            result["error"] = json!(outcome.error);
+            return Ok(result);
+        }
+
+        let returns = signature::parse_types("bool,bytes", &structs).expect("valid types");
+        let decoded = abi_codec::decode(&returns, &outcome.output).context("shouldRespond did not return (bool, bytes)")?;
+        result["should_respond"] = decoded[0].clone();
+        result["response"] = decoded[1].clone();
+
+        let response_function = arguments["response_function"].as_str().map(str::to_string).or(trap.declared.and_then(|t| t.response_function));
+        if let Some(response_function) = response_function {
+            let function = signature::parse_signature(&response_function, signature::SignatureKind::Function, &structs)?;
+            let response = abi_codec::parse_hex(decoded[1].as_str().unwrap_or("0x"))?;
+            result["response_function"] = json!(signature::canonical_signature(&function.name, &function.inputs));
+            match abi_codec::decode(&function.inputs, &response) {
+                Ok(values) => {
+                    let args: Vec<Value> = function
+                        .inputs
+                        .iter()
+                        .zip(values)
+                        .map(|(input, value)| json!({ "name": input.name, "type": signature::canonical_type(input), "value": value }))
+                        .collect();
+                    result["response_decoded"] = json!(args);
+                }
+                Err(e) => result["response_decode_error"] = json!(format!("{:#}", e)),
+            }
+        }
+        Ok(result)
+    }
+
+    fn replay_trap(&self, arguments: &Value) -> Result<Value> {
+        // Trap examples ship no fixtures: capturing one needs an archive node
+        if !arguments["fixture"].is_object() {
+            bail!("'fixture' must be a fixture object");
+        }
+        let fixture: replay::Fixture = serde_json::from_value(arguments["fixture"].clone()).context("invalid 'fixture'")?;
+        let trap = self.trap_code(arguments, fixture.contract.as_deref())?;
+
+        let sample_size = match &arguments["block_sample_size"] {
+            Value::Null => fixture
+                .block_sample_size
+                .or(trap.declared.as_ref().map(|t| t.block_sample_size))
+                .or(trap.required_samples)
+                .unwrap_or(1),
+            value => value.as_u64().context("'block_sample_size' must be a positive integer")?,
+        };
+        let mut result = serde_json::to_value(replay::replay(&fixture, &trap.code, sample_size)?)?;
+        result["contract"] = json!(trap.contract.or(fixture.contract));
+        Ok(result)
+    }
+
+    fn abi_encode(&self, arguments: &Value) -> Result<Value> {
+        let values = arguments["values"].as_array().context("missing array argument 'values'")?;
+        let user_types = self.source_types(arguments)?;
+        let params = abi_types_argument(arguments, &user_types)?;
+        let encoded = abi_codec::encode(&params, values)?;
+        Ok(json!({
+            "types": params.iter().map(signature::canonical_type).collect::<Vec<_>>().join(","),
+            "data": signature::to_hex(&encoded),
+        }))
+    }
+
+    fn abi_decode(&self, arguments: &Value) -> Result<Value> {
+        let data = arguments["data"].as_str().context("missing string argument 'data'")?;
+        let data = abi_codec::parse_hex(data).context("invalid 'data'")?;
+        let user_types = self.source_types(arguments)?;
+        let params = abi_types_argument(arguments, &user_types)?;
+        let values = abi_codec::decode(&params, &data)?;
+        Ok(json!({
+            "types": params.iter().map(signature::canonical_type).collect::<Vec<_>>().join(","),
+            "values": values,
+        }))
+    }
+
+    fn scaffold_response_contract(&self, arguments: &Value) -> Result<Value> {
+        let response_function = arguments["response_function"].as_str().context("missing string argument 'response_function'")?;
+        let network_name = arguments["network"].as_str().context("missing string argument 'network'")?;
+        let network = self.networks.resolve(network_name).with_context(|| {
+            format!("unknown network '{}' (known: {})", network_name, self.networks.names().join(", "))
+        })?;
+        let scaffold = response_scaffold::scaffold(network, response_function, arguments["contract"].as_str())?;
+        Ok(serde_json::to_value(scaffold)?)
+    }
+
+    fn port_exploit(&self, arguments: &Value) -> Result<Value> {
+        let spec: exploit_port::IncidentSpec = serde_json::from_value(arguments.clone()).context("invalid incident spec")?;
+        let network = self.networks.resolve(&spec.network).with_context(|| {
+            format!("unknown network '{}' (known: {})", spec.network, self.networks.names().join(", "))
+        })?;
+        Ok(serde_json::to_value(exploit_port::port(&spec, network)?)?)
+    }
+
+    // Types declared in 'source', 'sources' or 'example', if any were given
+    fn source_types(&self, arguments: &Value) -> Result<trap_analysis::UserTypes> {
+        if ["source", "sources", "example"].iter().all(|key| arguments[*key].is_null()) {
+            return Ok(trap_analysis::UserTypes::default());
+        }
+        let (sources, _) = self.trap_sources(arguments)?;
+        Ok(trap_analysis::user_types(&sources))
+    }
+
+    // Trap code from 'bytecode' or 'deployed_bytecode', or compiled from the
+    // trap sources, with what the analyzer and drosera.toml tell about it
+    fn trap_code(&self, arguments: &Value, default_contract: Option<&str>) -> Result<TrapCode> {
+        let has_source = ["source", "sources", "example"].iter().any(|key| !arguments[*key].is_null());
+        let mut contract_name = None;
+        let mut collect_types = None;
+        let mut declared = None;
+        let mut required_samples = None;
+        let code = if let Some(bytecode) = arguments["bytecode"].as_str() {
+            evm::Code::Creation(abi_codec::parse_hex(bytecode).context("invalid 'bytecode'")?)
+        } else if let Some(bytecode) = arguments["deployed_bytecode"].as_str() {
+            evm::Code::Runtime(abi_codec::parse_hex(bytecode).context("invalid 'deployed_bytecode'")?)
+        } else if has_source {
+            let (sources, example) = self.trap_sources(arguments)?;
+            let analysis = trap_analysis::analyze(&sources, arguments["contract"].as_str().or(default_contract)).ok();
+            collect_types = analysis.as_ref().and_then(|a| a.collect.as_ref()).and_then(|c| c.abi_types.clone()).map(|types| types.join(","));
+            required_samples = analysis.as_ref().and_then(|a| a.should_respond.as_ref()).map(|s| s.required_block_sample_size);
+            if let (Some(toml_text), Some(analysis)) = (example.and_then(|e| e["drosera_toml"].as_str()), &analysis) {
+                let traps = drosera_toml::parse_traps(toml_text)?;
+                declared = drosera_toml::select_trap(&traps, None, &analysis.contract).ok().cloned();
+            }
+
+            let compiler = foundry::Compiler::detect(arguments["compiler"].as_str())?;
+            let project = foundry::Project::create(&sources, arguments["dependencies"].as_str().map(Path::new), &remappings_argument(arguments)?)?;
+            let output = project.compile(&compiler)?;
+            if !output.success {
+                let errors: Vec<String> = output
+                    .diagnostics
+                    .iter()
+                    .filter(|d| d.severity == "error")
+                    .map(|d| format!("{}:{}: {}", d.file.as_deref().unwrap_or("?"), d.line.unwrap_or(0), d.message))
+                    .collect();
+                bail!("compilation failed:\n{}", errors.join("\n"));
+            }
+            let wanted = arguments["contract"].as_str().or(default_contract).map(str::to_string).or_else(|| analysis.map(|a| a.contract));
+            let contract = output
+                .contracts
+                .into_iter()
+                .find(|c| match &wanted {
+                    Some(name) => &c.name == name,
+                    None => c.abi.as_array().is_some_and(|abi| abi.iter().any(|item| item["name"] == "shouldRespond")),
+                })
+                .context("no compiled contract implements shouldRespond; pass 'contract'")?;
+            contract_name = Some(contract.name);
+            evm::Code::Creation(abi_codec::parse_hex(&contract.bytecode).context("contract bytecode is not hex (unlinked libraries?)")?)
+        } else {
+            bail!("pass 'bytecode', 'deployed_bytecode', 'source', 'sources' or 'example'");
+        };
+
+        Ok(TrapCode { code, contract: contract_name, collect_types, declared, required_samples })
+    }
+
+    // Trap sources from 'source', 'sources' and 'example', with the example's data
+    fn trap_sources(&self, arguments: &Value) -> Result<(BTreeMap<String, String>, Option<&Value>)> {
+        let mut sources: BTreeMap<String, String> = match &arguments["sources"] {
+            Value::Null => BTreeMap::new(),
+            value => serde_json::from_value(value.clone()).context("'sources' must map file names to source strings")?,
+        };
+        if let Some(source) = arguments["source"].as_str() {
+            sources.insert("Trap.sol".to_string(), source.to_string());
+        }
+        let mut example = None;
+        if let Some(example_name) = arguments["example"].as_str() {
+            let Some(example_data) = self.trap_examples.get(example_name) else {
+                bail!("trap example '{}' not found", example_name);
+            };
+            let example_sources: BTreeMap<String, String> = serde_json::from_value(example_data["sources"].clone()).unwrap_or_default();
+            if example_sources.is_empty() {
+                bail!("trap example '{}' has no src/ sources", example_name);
+            }
+            sources.extend(example_sources);
+            example = Some(example_data);
+        }
+        if sources.is_empty() {
+            bail!("pass 'source', 'sources' or 'example'");
+        }
+        Ok((sources, example))
+    }
+}
+
+struct TrapCode {
+    code: evm::Code,
+    /// Compiled contract name, when built from source
+    contract: Option<String>,
+    /// collect()'s encoded types as found by the analyzer
+    collect_types: Option<String>,
+    /// The example's drosera.toml entry for the contract
+    declared: Option<drosera_toml::DeclaredTrap>,
+    /// Samples shouldRespond reads as found by the analyzer
+    required_samples: Option<u64>,
+}
+
+fn abi_types_argument(arguments: &Value, user_types: &trap_analysis::UserTypes) -> Result<Vec<AbiParam>> {
+    let types = arguments["types"].as_str().context("missing string argument 'types'")?;
+    signature::parse_types(types, &user_types.defs()).context("invalid 'types'")
+}
+
+fn remappings_argument(arguments: &Value) -> Result<Vec<String>> {
+    match &arguments["remappings"] {
+        Value::Null => Ok(Vec::new()),
+        value => serde_json::from_value(value.clone()).context("'remappings' must be an array of strings"),
+    }
+}
@@ -0,0 +1,1397 @@
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::Range;
+
+use crate::drosera_toml::DeclaredTrap;
+use crate::protocol::AbiStruct;
+use crate::signature::StructDefs;
+
+// Static analysis of trap sources: what collect() encodes and which contracts
+// it calls, and which collected samples shouldRespond() reads. It works on
+// tokens rather than a full Solidity AST, so anything it cannot resolve is
+// reported as unresolved instead of guessed.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Ident,
+    Number,
+    Str,
+    Punct,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: Kind,
+    text: String,
+    line: usize,
+}
+
+const TWO_CHAR_PUNCT: [&str; 17] = ["==", "!=", "<=", ">=", "&&", "||", "++", "--", "+=", "-=", "*=", "/=", "=>", "<<", ">>", "**", "%="];
+
+fn tokenize(source: &str) -> Vec<Token> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\n' {
+            line += 1;
+            i += 1;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c == '/' && chars.get(i + 1) == Some(&'/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '/' && chars.get(i + 1) == Some(&'*') {
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                if chars[i] == '\n' {
+                    line += 1;
+                }
+                i += 1;
+            }
+            i += 2;
+        } else if c.is_ascii_alphabetic() || c == '_' || c == '$' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || chars[i] == '$') {
+                i += 1;
+            }
+            tokens.push(Token { kind: Kind::Ident, text: chars[start..i].iter().collect(), line });
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || chars[i] == '.') {
+                i += 1;
+            }
+            tokens.push(Token { kind: Kind::Number, text: chars[start..i].iter().collect(), line });
+        } else if c == '"' || c == '\'' {
+            let start = i;
+            i += 1;
+            while i < chars.len() && chars[i] != c {
+                if chars[i] == '\\' {
+                    i += 1;
+                }
+                i += 1;
+            }
+            i += 1;
+            let text: String = chars[start..i.min(chars.len())].iter().collect();
+            tokens.push(Token { kind: Kind::Str, text, line });
+        } else {
+            let pair: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+            let len = if TWO_CHAR_PUNCT.contains(&pair.as_str()) { 2 } else { 1 };
+            tokens.push(Token { kind: Kind::Punct, text: chars[i..i + len].iter().collect(), line });
+            i += len;
+        }
+    }
+    tokens
+}
+
+// Index of the bracket closing the one at `open`
+fn matching(tokens: &[Token], open: usize) -> usize {
+    let (opener, closer) = match tokens[open].text.as_str() {
+        "(" => ("(", ")"),
+        "[" => ("[", "]"),
+        _ => ("{", "}"),
+    };
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        if token.kind == Kind::Punct && token.text == opener {
+            depth += 1;
+        } else if token.kind == Kind::Punct && token.text == closer {
+            depth -= 1;
+            if depth == 0 {
+                return i;
+            }
+        }
+    }
+    tokens.len()
+}
+
+// Splits `tokens` at top-level commas
+fn split_commas(tokens: &[Token]) -> Vec<&[Token]> {
+    let mut parts = Vec::new();
+    let mut depth = 0i32;
+    let mut start = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match token.text.as_str() {
+            "(" | "[" | "{" if token.kind == Kind::Punct => depth += 1,
+            ")" | "]" | "}" if token.kind == Kind::Punct => depth -= 1,
+            "," if token.kind == Kind::Punct && depth == 0 => {
+                parts.push(&tokens[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if start < tokens.len() {
+        parts.push(&tokens[start..]);
+    }
+    parts
+}
+
+fn text(tokens: &[Token]) -> String {
+    let mut out = String::new();
+    for (i, token) in tokens.iter().enumerate() {
+        let glue = i == 0
+            || matches!(token.text.as_str(), "." | "(" | ")" | "[" | "]" | ",")
+            || matches!(tokens[i - 1].text.as_str(), "." | "(" | "[" | "!" | ",");
+        if !glue {
+            out.push(' ');
+        }
+        out.push_str(&token.text);
+        if token.text == "," {
+            out.push(' ');
+        }
+    }
+    out
+}
+
+fn is_address_literal(token: &Token) -> bool {
+    token.kind == Kind::Number && token.text.len() == 42 && token.text.starts_with("0x")
+}
+
+const LOCATIONS: [&str; 3] = ["memory", "storage", "calldata"];
+const STATEMENT_KEYWORDS: [&str; 18] = [
+    "return", "emit", "if", "else", "for", "while", "do", "delete", "require", "revert", "assert", "unchecked", "assembly",
+    "break", "continue", "try", "catch", "new",
+];
+
+#[derive(Debug, Clone)]
+struct Variable {
+    ty: String,
+    /// Initializer tokens, if declared with `= ...`
+    init: Vec<Token>,
+}
+
+#[derive(Debug, Clone)]
+struct Function {
+    name: String,
+    params: Vec<(String, String)>,
+    body: Option<Range<usize>>,
+}
+
+#[derive(Debug, Clone)]
+struct Unit {
+    kind: String,
+    name: String,
+    bases: Vec<String>,
+    state: BTreeMap<String, Variable>,
+    functions: Vec<Function>,
+}
+
+// All contracts, interfaces, libraries, structs and enums across the sources
+struct Sources {
+    files: Vec<(String, Vec<Token>)>,
+    units: Vec<(usize, Unit)>,
+    structs: HashMap<String, Vec<(String, String)>>,
+    enums: BTreeSet<String>,
+    /// Contract and interface names, declared or imported
+    contract_types: BTreeSet<String>,
+}
+
+// Parses a type at `i`: elementary or user-defined (dotted) names, mappings
+// and array suffixes. Returns the type text and the index after it.
+fn parse_type(tokens: &[Token], i: usize) -> Option<(String, usize)> {
+    let first = tokens.get(i)?;
+    if first.kind != Kind::Ident {
+        return None;
+    }
+    let mut j = i + 1;
+    let mut ty = first.text.clone();
+    if first.text == "mapping" && tokens.get(j).is_some_and(|t| t.text == "(") {
+        let close = matching(tokens, j);
+        ty = format!("mapping{}", text(&tokens[j..=close.min(tokens.len() - 1)]));
+        j = close + 1;
+    } else {
+        while tokens.get(j).is_some_and(|t| t.text == ".") && tokens.get(j + 1).is_some_and(|t| t.kind == Kind::Ident) {
+            ty.push('.');
+            ty.push_str(&tokens[j + 1].text);
+            j += 2;
+        }
+        if ty == "address" && tokens.get(j).is_some_and(|t| t.text == "payable") {
+            j += 1;
+        }
+    }
+    while tokens.get(j).is_some_and(|t| t.text == "[") {
+        let close = matching(tokens, j);
+        ty.push_str(&text(&tokens[j..=close.min(tokens.len() - 1)]).replace(' ', ""));
+        j = close + 1;
+    }
+    Some((ty, j))
+}
+
+// `Type [location] name` parameter lists
+fn parse_params(tokens: &[Token]) -> Vec<(String, String)> {
+    split_commas(tokens)
+        .into_iter()
+        .filter_map(|param| {
+            let (ty, mut j) = parse_type(param, 0)?;
+            while param.get(j).is_some_and(|t| LOCATIONS.contains(&t.text.as_str()) || t.text == "indexed") {
+                j += 1;
+            }
+            let name = param.get(j).filter(|t| t.kind == Kind::Ident).map(|t| t.text.clone()).unwrap_or_default();
+            Some((ty, name))
+        })
+        .collect()
+}
+
+impl Sources {
+    fn parse(sources: &BTreeMap<String, String>) -> Self {
+        let mut parsed = Sources {
+            files: Vec::new(),
+            units: Vec::new(),
+            structs: HashMap::new(),
+            enums: BTreeSet::new(),
+            contract_types: BTreeSet::new(),
+        };
+        for (name, source) in sources {
+            parsed.files.push((name.clone(), tokenize(source)));
+        }
+        for file in 0..parsed.files.len() {
+            let tokens = parsed.files[file].1.clone();
+            parsed.parse_file(file, &tokens);
+        }
+        parsed
+    }
+
+    fn parse_file(&mut self, file: usize, tokens: &[Token]) {
+        let mut i = 0;
+        while i < tokens.len() {
+            let token = &tokens[i];
+            match token.text.as_str() {
+                // import {A, B as C} from "...";
+                "import" => {
+                    let end = (i..tokens.len()).find(|&j| tokens[j].text == ";").unwrap_or(tokens.len());
+                    for j in i..end {
+                        let imported = &tokens[j];
+                        let is_symbol = imported.kind == Kind::Ident
+                            && imported.text.chars().next().is_some_and(|c| c.is_ascii_uppercase())
+                            && matches!(tokens[j - 1].text.as_str(), "{" | "," | "as");
+                        if is_symbol {
+                            self.contract_types.insert(imported.text.clone());
+                        }
+                    }
+                    i = end + 1;
+                }
+                "contract" | "interface" | "library" if token.kind == Kind::Ident => {
+                    let Some(name) = tokens.get(i + 1).filter(|t| t.kind == Kind::Ident) else {
+                        i += 1;
+                        continue;
+                    };
+                    let Some(open) = (i..tokens.len()).find(|&j| tokens[j].text == "{") else {
+                        break;
+                    };
+                    let bases: Vec<String> = match (i + 2..open).find(|&j| tokens[j].text == "is") {
+                        Some(is) => split_commas(&tokens[is + 1..open])
+                            .into_iter()
+                            .filter_map(|base| base.first().map(|t| t.text.clone()))
+                            .collect(),
+                        None => Vec::new(),
+                    };
+                    let close = matching(tokens, open);
+                    let kind = if i > 0 && tokens[i - 1].text == "abstract" { "abstract contract" } else { token.text.as_str() };
+                    if kind != "library" {
+                        self.contract_types.insert(name.text.clone());
+                    }
+                    let unit = self.parse_unit(tokens, kind, &name.text, bases, open + 1..close);
+                    self.units.push((file, unit));
+                    i = close + 1;
+                }
+                "struct" | "enum" => i = self.parse_type_definition(tokens, i),
+                _ => i += 1,
+            }
+        }
+    }
+
+    // struct Name { Type field; ... } or enum Name { A, B }
+    fn parse_type_definition(&mut self, tokens: &[Token], i: usize) -> usize {
+        let Some(name) = tokens.get(i + 1).filter(|t| t.kind == Kind::Ident) else {
+            return i + 1;
+        };
+        let Some(open) = tokens.get(i + 2).filter(|t| t.text == "{").map(|_| i + 2) else {
+            return i + 1;
+        };
+        let close = matching(tokens, open);
+        if tokens[i].text == "enum" {
+            self.enums.insert(name.text.clone());
+        } else {
+            let mut fields = Vec::new();
+            let mut j = open + 1;
+            while j < close {
+                let end = (j..close).find(|&k| tokens[k].text == ";").unwrap_or(close);
+                if let Some((ty, after)) = parse_type(&tokens[..end], j) {
+                    if let Some(field) = tokens.get(after).filter(|t| t.kind == Kind::Ident && after < end) {
+                        fields.push((ty, field.text.clone()));
+                    }
+                }
+                j = end + 1;
+            }
+            self.structs.insert(name.text.clone(), fields);
+        }
+        close + 1
+    }
+
+    fn parse_unit(&mut self, tokens: &[Token], kind: &str, name: &str, bases: Vec<String>, body: Range<usize>) -> Unit {
+        let mut unit = Unit { kind: kind.to_string(), name: name.to_string(), bases, state: BTreeMap::new(), functions: Vec::new() };
+        let mut i = body.start;
+        while i < body.end {
+            let token = &tokens[i];
+            match token.text.as_str() {
+                "struct" | "enum" => i = self.parse_type_definition(tokens, i),
+                "function" | "constructor" | "modifier" | "fallback" | "receive" => {
+                    let (name, open_paren) = if token.text == "function" {
+                        match tokens.get(i + 1) {
+                            Some(t) if t.kind == Kind::Ident => (t.text.clone(), i + 2),
+                            _ => (String::new(), i + 1),
+                        }
+                    } else {
+                        (token.text.clone(), i + 1)
+                    };
+                    let params_end = if tokens.get(open_paren).is_some_and(|t| t.text == "(") {
+                        matching(tokens, open_paren)
+                    } else {
+                        open_paren
+                    };
+                    let params = if params_end > open_paren { parse_params(&tokens[open_paren + 1..params_end]) } else { Vec::new() };
+                    // Skip modifiers and returns(...) up to the body or `;`
+                    let mut j = params_end + 1;
+                    while j < body.end && tokens[j].text != "{" && tokens[j].text != ";" {
+                        j = if tokens[j].text == "(" { matching(tokens, j) + 1 } else { j + 1 };
+                    }
+                    let (fn_body, next) = if j < body.end && tokens[j].text == "{" {
+                        let close = matching(tokens, j);
+                        (Some(j + 1..close), close + 1)
+                    } else {
+                        (None, j + 1)
+                    };
+                    if token.text != "modifier" {
+                        unit.functions.push(Function { name, params, body: fn_body });
+                    }
+                    i = next;
+                }
+                "event" | "error" | "using" | "pragma" => {
+                    i = (i..body.end).find(|&j| tokens[j].text == ";").unwrap_or(body.end) + 1;
+                }
+                _ => {
+                    // State variable: Type [qualifiers] name [= init];
+                    let end = statement_end(tokens, i, body.end);
+                    if let Some((ty, mut j)) = parse_type(&tokens[..end], i) {
+                        while j < end && tokens[j].kind == Kind::Ident && tokens.get(j + 1).is_some_and(|t| t.kind == Kind::Ident) {
+                            j += 1;
+                        }
+                        if let Some(var) = tokens.get(j).filter(|t| t.kind == Kind::Ident && j < end) {
+                            let init = if tokens.get(j + 1).is_some_and(|t| t.text == "=") { tokens[j + 2..end].to_vec() } else { Vec::new() };
+                            unit.state.insert(var.text.clone(), Variable { ty, init });
+                        }
+                    }
+                    i = end + 1;
+                }
+            }
+        }
+        unit
+    }
+
+    fn struct_fields(&self, ty: &str) -> Option<&Vec<(String, String)>> {
+        self.structs.get(ty).or_else(|| self.structs.get(ty.rsplit('.').next().unwrap_or(ty)))
+    }
+
+    fn is_contract_type(&self, ty: &str) -> bool {
+        let base = ty.rsplit('.').next().unwrap_or(ty);
+        self.contract_types.contains(base) && self.struct_fields(ty).is_none() && !self.enums.contains(base)
+    }
+
+    // Canonical ABI type, e.g. `tuple(address,uint256)[]` for `FeeData[]`
+    fn abi_type(&self, ty: &str, depth: usize) -> String {
+        let (base, suffix) = match ty.find('[') {
+            Some(at) => ty.split_at(at),
+            None => (ty, ""),
+        };
+        let base_name = base.rsplit('.').next().unwrap_or(base);
+        let canonical = match base {
+            "uint" => "uint256".to_string(),
+            "int" => "int256".to_string(),
+            _ if depth < 8 && self.struct_fields(base).is_some() => {
+                let members: Vec<String> = self.struct_fields(base).unwrap().iter().map(|(ty, _)| self.abi_type(ty, depth + 1)).collect();
+                format!("tuple({})", members.join(","))
+            }
+            _ if self.enums.contains(base_name) => "uint8".to_string(),
+            _ if self.is_contract_type(base) => "address".to_string(),
+            _ => base.to_string(),
+        };
+        format!("{}{}", canonical, suffix)
+    }
+
+    // Member type for a struct definition: like abi_type, but nested structs stay named
+    fn member_type(&self, ty: &str) -> String {
+        let base = ty.split('[').next().unwrap_or(ty);
+        if self.struct_fields(base).is_some() {
+            ty.to_string()
+        } else {
+            self.abi_type(ty, 0)
+        }
+    }
+
+    fn schema(&self, ty: &str, depth: usize) -> TypeSchema {
+        let base = ty.split('[').next().unwrap_or(ty);
+        let fields = match self.struct_fields(base) {
+            Some(fields) if depth < 8 => Some(
+                fields.iter().map(|(field_ty, name)| FieldSchema { name: name.clone(), schema: self.schema(field_ty, depth + 1) }).collect(),
+            ),
+            _ => None,
+        };
+        TypeSchema { ty: ty.to_string(), abi_type: self.abi_type(ty, depth), fields }
+    }
+}
+
+// End of the statement starting at `i`: the `;` at bracket depth 0
+fn statement_end(tokens: &[Token], i: usize, limit: usize) -> usize {
+    let mut j = i;
+    while j < limit {
+        match tokens[j].text.as_str() {
+            ";" => return j,
+            "(" | "[" | "{" => j = matching(tokens, j) + 1,
+            _ => j += 1,
+        }
+    }
+    limit
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TypeSchema {
+    #[serde(rename = "type")]
+    pub ty: String,
+    pub abi_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fields: Option<Vec<FieldSchema>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldSchema {
+    pub name: String,
+    #[serde(flatten)]
+    pub schema: TypeSchema,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EncodedValue {
+    pub expression: String,
+    /// None when the expression's type could not be resolved
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schema: Option<TypeSchema>,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExternalCall {
+    /// call (through an interface type), low_level_call or balance
+    pub kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interface: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub function: Option<String>,
+    pub target: String,
+    /// Addresses the target resolves to through initializers and constructor pushes
+    pub addresses: Vec<String>,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CollectAnalysis {
+    /// Values passed to abi.encode, in order: the tuple collect() returns
+    pub encodes: Vec<EncodedValue>,
+    /// Canonical ABI tuple of the encoded values, when every type resolved
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub abi_types: Option<Vec<String>>,
+    pub external_calls: Vec<ExternalCall>,
+    pub notes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SampleAccess {
+    /// `0`, `length - 1`, or the index expression for dynamic accesses
+    pub index: String,
+    /// Smallest sample count for this access to be in bounds, when static
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_samples: Option<u64>,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SampleDecode {
+    pub index: String,
+    pub types: Vec<TypeSchema>,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LengthGuard {
+    pub expression: String,
+    /// Samples needed to get past the guard
+    pub min_samples: u64,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ShouldRespondAnalysis {
+    pub data_param: String,
+    pub accesses: Vec<SampleAccess>,
+    pub decodes: Vec<SampleDecode>,
+    pub length_guards: Vec<LengthGuard>,
+    /// A loop runs over every sample passed in
+    pub iterates_all_samples: bool,
+    /// Highest literal index accessed + 1 (`data[1]` -> 2), or 0 when none
+    pub deepest_index_samples: u64,
+    /// Smallest block_sample_size at which shouldRespond can respond
+    pub required_block_sample_size: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TrapAnalysis {
+    pub contract: String,
+    pub file: String,
+    pub collect: Option<CollectAnalysis>,
+    pub should_respond: Option<ShouldRespondAnalysis>,
+}
+
+// User-defined types declared in the sources, for resolving names in ABI type
+// strings. Struct members that are enums become uint8 and contracts address
+#[derive(Debug, Clone, Default)]
+pub struct UserTypes {
+    pub structs: Vec<AbiStruct>,
+    /// Contract and interface names, declared or imported
+    pub contracts: Vec<String>,
+    pub enums: Vec<String>,
+}
+
+impl UserTypes {
+    pub fn defs(&self) -> StructDefs<'_> {
+        StructDefs::new(&self.structs).with_names(&self.contracts, &self.enums)
+    }
+}
+
+pub fn user_types(sources: &BTreeMap<String, String>) -> UserTypes {
+    let parsed = Sources::parse(sources);
+    let mut structs: Vec<AbiStruct> = parsed
+        .structs
+        .iter()
+        .map(|(name, fields)| AbiStruct {
+            name: name.clone(),
+            fields: fields.iter().map(|(ty, field)| format!("{} {}", parsed.member_type(ty), field)).collect::<Vec<_>>().join(", "),
+        })
+        .collect();
+    structs.sort_by(|a, b| a.name.cmp(&b.name));
+    UserTypes {
+        structs,
+        contracts: parsed.contract_types.iter().cloned().collect(),
+        enums: parsed.enums.iter().cloned().collect(),
+    }
+}
+
+// Analyzes `contract`, or the contract defining both collect and shouldRespond
+pub fn analyze(sources: &BTreeMap<String, String>, contract: Option<&str>) -> Result<TrapAnalysis> {
+    let parsed = Sources::parse(sources);
+    let candidates: Vec<&(usize, Unit)> = parsed
+        .units
+        .iter()
+        .filter(|(_, unit)| !unit.kind.contains("interface") && !unit.kind.contains("library"))
+        .filter(|(_, unit)| match contract {
+            Some(name) => unit.name == name,
+            None => ["collect", "shouldRespond"].iter().all(|f| unit.functions.iter().any(|func| func.name == *f && func.body.is_some())),
+        })
+        .collect();
+    let (file, unit) = match (candidates.as_slice(), contract) {
+        ([single], _) => *single,
+        ([], Some(name)) => bail!("contract {} not found in the sources", name),
+        ([], None) => bail!("no contract in the sources implements both collect() and shouldRespond()"),
+        (many, _) => {
+            let names: Vec<&str> = many.iter().map(|(_, unit)| unit.name.as_str()).collect();
+            bail!("several trap contracts found ({}); pass 'contract'", names.join(", "))
+        }
+    };
+    let tokens = &parsed.files[*file].1;
+    let analyzer = Analyzer { sources: &parsed, unit, tokens, file: *file };
+
+    Ok(TrapAnalysis {
+        contract: unit.name.clone(),
+        file: parsed.files[*file].0.clone(),
+        collect: analyzer.function("collect").map(|f| analyzer.analyze_collect(f)),
+        should_respond: analyzer.function("shouldRespond").and_then(|f| analyzer.analyze_should_respond(f)),
+    })
+}
+
+struct Analyzer<'a> {
+    sources: &'a Sources,
+    unit: &'a Unit,
+    tokens: &'a [Token],
+    file: usize,
+}
+
+impl<'a> Analyzer<'a> {
+    fn function(&self, name: &str) -> Option<&'a Function> {
+        self.unit.functions.iter().find(|f| f.name == name && f.body.is_some()).or_else(|| {
+            // Inherited implementation from a base in the same sources
+            self.base_units().flat_map(|unit| &unit.functions).find(|f| f.name == name && f.body.is_some())
+        })
+    }
+
+    fn base_units(&self) -> impl Iterator<Item = &'a Unit> + '_ {
+        self.sources
+            .units
+            .iter()
+            .filter(|(file, unit)| *file == self.file && self.unit.bases.contains(&unit.name))
+            .map(|(_, unit)| unit)
+    }
+
+    fn state(&self, name: &str) -> Option<&'a Variable> {
+        self.unit.state.get(name).or_else(|| self.base_units().find_map(|unit| unit.state.get(name)))
+    }
+
+    // Parameters and local declarations of a function body
+    fn locals(&self, function: &Function) -> HashMap<String, Variable> {
+        let mut locals: HashMap<String, Variable> =
+            function.params.iter().map(|(ty, name)| (name.clone(), Variable { ty: ty.clone(), init: Vec::new() })).collect();
+        let Some(body) = &function.body else {
+            return locals;
+        };
+        let tokens = self.tokens;
+        for i in body.clone() {
+            let after_statement = i == body.start || matches!(tokens[i - 1].text.as_str(), "{" | ";" | "}");
+            let in_header = tokens[i - 1].text == "(" && i >= 2 && matches!(tokens[i - 2].text.as_str(), "for" | "returns" | "{" | ";" | "}");
+            let in_list = tokens[i - 1].text == "," && locals_list_context(tokens, i, body.start);
+            if !(after_statement || in_header || in_list) || STATEMENT_KEYWORDS.contains(&tokens[i].text.as_str()) {
+                continue;
+            }
+            let Some((ty, mut j)) = parse_type(&tokens[..body.end], i) else {
+                continue;
+            };
+            if tokens.get(j).is_some_and(|t| LOCATIONS.contains(&t.text.as_str())) {
+                j += 1;
+            }
+            let Some(name) = tokens.get(j).filter(|t| t.kind == Kind::Ident && j < body.end) else {
+                continue;
+            };
+            match tokens.get(j + 1).map(|t| t.text.as_str()) {
+                Some("=") => {
+                    let end = statement_end(tokens, j + 2, body.end);
+                    locals.insert(name.text.clone(), Variable { ty, init: tokens[j + 2..end].to_vec() });
+                }
+                Some(";") | Some(")") | Some(",") => {
+                    locals.insert(name.text.clone(), Variable { ty, init: Vec::new() });
+                }
+                _ => {}
+            }
+        }
+        locals
+    }
+
+    fn variable<'b>(&'b self, locals: &'b HashMap<String, Variable>, name: &str) -> Option<&'b Variable> {
+        locals.get(name).or_else(|| self.state(name))
+    }
+
+    // Internal functions reachable from `function`, including itself
+    fn reachable(&self, function: &'a Function) -> Vec<&'a Function> {
+        let mut seen: Vec<&'a Function> = vec![function];
+        let mut i = 0;
+        while i < seen.len() {
+            if let Some(body) = seen[i].body.clone() {
+                for j in body {
+                    let called = &self.tokens[j];
+                    let is_call = called.kind == Kind::Ident
+                        && self.tokens.get(j + 1).is_some_and(|t| t.text == "(")
+                        && self.tokens[j - 1].text != ".";
+                    if !is_call {
+                        continue;
+                    }
+                    if let Some(callee) = self.function(&called.text) {
+                        if !seen.iter().any(|f| std::ptr::eq(*f, callee)) {
+                            seen.push(callee);
+                        }
+                    }
+                }
+            }
+            i += 1;
+        }
+        seen
+    }
+
+    fn analyze_collect(&self, function: &'a Function) -> CollectAnalysis {
+        let mut analysis = CollectAnalysis { encodes: Vec::new(), abi_types: None, external_calls: Vec::new(), notes: Vec::new() };
+        let reachable = self.reachable(function);
+
+        // The encode in collect() itself, else in a helper it calls
+        for candidate in &reachable {
+            let locals = self.locals(candidate);
+            let body = candidate.body.clone().expect("reachable functions have bodies");
+            for i in body.clone() {
+                if !(self.tokens[i].text == "abi" && self.tokens.get(i + 1).is_some_and(|t| t.text == ".")) {
+                    continue;
+                }
+                let method = self.tokens.get(i + 2).map(|t| t.text.as_str());
+                if !matches!(method, Some("encode") | Some("encodePacked")) || self.tokens.get(i + 3).is_none_or(|t| t.text != "(") {
+                    continue;
+                }
+                if method == Some("encodePacked") {
+                    analysis.notes.push(format!(
+                        "line {}: abi.encodePacked output cannot be abi.decoded in shouldRespond",
+                        self.tokens[i].line
+                    ));
+                }
+                let close = matching(self.tokens, i + 3);
+                for argument in split_commas(&self.tokens[i + 4..close]) {
+                    analysis.encodes.push(EncodedValue {
+                        expression: text(argument),
+                        schema: self.expression_type(&locals, argument).map(|ty| self.sources.schema(&ty, 0)),
+                        line: self.tokens[i].line,
+                    });
+                }
+            }
+            if !analysis.encodes.is_empty() {
+                break;
+            }
+        }
+        if analysis.encodes.is_empty() {
+            analysis.notes.push("no abi.encode found in collect() or the functions it calls".to_string());
+        } else if analysis.encodes.iter().all(|e| e.schema.is_some()) {
+            analysis.abi_types = Some(analysis.encodes.iter().map(|e| e.schema.as_ref().unwrap().abi_type.clone()).collect());
+        }
+
+        for candidate in &reachable {
+            let locals = self.locals(candidate);
+            for call in self.external_calls(candidate, &locals) {
+                if !analysis.external_calls.iter().any(|c| c.kind == call.kind && c.interface == call.interface && c.function == call.function && c.target == call.target) {
+                    analysis.external_calls.push(call);
+                }
+            }
+        }
+        analysis
+    }
+
+    fn expression_type(&self, locals: &HashMap<String, Variable>, expression: &[Token]) -> Option<String> {
+        let first = expression.first()?;
+        match expression {
+            [single] if single.kind == Kind::Ident => match single.text.as_str() {
+                "true" | "false" => Some("bool".to_string()),
+                name => self.variable(locals, name).map(|v| v.ty.clone()),
+            },
+            [single] if single.kind == Kind::Number => Some(if is_address_literal(single) { "address" } else { "uint256" }.to_string()),
+            [single] if single.kind == Kind::Str => Some("string".to_string()),
+            [object, dot, member] if dot.text == "." => match (object.text.as_str(), member.text.as_str()) {
+                ("block", "timestamp" | "number" | "basefee" | "chainid" | "gaslimit" | "prevrandao" | "difficulty") => Some("uint256".to_string()),
+                ("block", "coinbase") | ("msg", "sender") | ("tx", "origin") => Some("address".to_string()),
+                (_, "balance") | (_, "length") => Some("uint256".to_string()),
+                (object, member) => {
+                    let ty = self.variable(locals, object)?.ty.clone();
+                    self.sources.struct_fields(&ty)?.iter().find(|(_, name)| name == member).map(|(ty, _)| ty.clone())
+                }
+            },
+            _ if first.kind == Kind::Ident && expression.get(1).is_some_and(|t| t.text == "(") && matching(expression, 1) == expression.len() - 1 => {
+                let name = &first.text;
+                if self.sources.struct_fields(name).is_some() || is_elementary(name) {
+                    Some(name.clone())
+                } else {
+                    // Internal call: the function's single return type is not tracked
+                    None
+                }
+            }
+            _ if first.kind == Kind::Ident && expression.get(1).is_some_and(|t| t.text == "(") => {
+                // `address(this).balance`
+                let close = matching(expression, 1);
+                match (&expression[close + 1..], first.text.as_str()) {
+                    ([dot, member], "address") if dot.text == "." && member.text == "balance" => Some("uint256".to_string()),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn external_calls(&self, function: &Function, locals: &HashMap<String, Variable>) -> Vec<ExternalCall> {
+        let tokens = self.tokens;
+        let body = function.body.clone().expect("analyzed functions have bodies");
+        let mut calls = Vec::new();
+        for i in body.clone() {
+            let token = &tokens[i];
+            if token.text != "." || tokens.get(i + 1).is_none_or(|t| t.kind != Kind::Ident) {
+                continue;
+            }
+            let member = &tokens[i + 1].text;
+            let Some(target_start) = receiver_start(tokens, i, body.start) else {
+                continue;
+            };
+            let receiver = &tokens[target_start..i];
+            let calls_member = tokens.get(i + 2).is_some_and(|t| t.text == "(");
+
+            if member == "balance" && !calls_member {
+                calls.push(self.call("balance", None, None, receiver, locals, token.line));
+            } else if matches!(member.as_str(), "staticcall" | "call" | "delegatecall") && calls_member {
+                let close = matching(tokens, i + 2);
+                let (interface, function) = encoded_function(&tokens[i + 3..close]);
+                calls.push(self.call("low_level_call", interface, function.or(Some(member.clone())), receiver, locals, token.line));
+            } else if calls_member {
+                // IFoo(target).f(...) or foo.f(...) with foo of a contract type
+                let interface = match receiver {
+                    [ty, open, .., close] if open.text == "(" && close.text == ")" && self.sources.is_contract_type(&ty.text) => Some(ty.text.clone()),
+                    [var] if var.kind == Kind::Ident => self
+                        .variable(locals, &var.text)
+                        .map(|v| v.ty.clone())
+                        .filter(|ty| self.sources.is_contract_type(ty)),
+                    _ => None,
+                };
+                if let Some(interface) = interface {
+                    let target = match receiver {
+                        [_, _, inner @ .., _] if receiver.len() > 1 => inner,
+                        _ => receiver,
+                    };
+                    calls.push(self.call("call", Some(interface), Some(member.clone()), target, locals, token.line));
+                }
+            }
+        }
+        calls
+    }
+
+    fn call(&self, kind: &'static str, interface: Option<String>, function: Option<String>, target: &[Token], locals: &HashMap<String, Variable>, line: usize) -> ExternalCall {
+        ExternalCall { kind, interface, function, target: text(target), addresses: self.resolve_addresses(locals, target, 0), line }
+    }
+
+    // Address literals an expression can evaluate to, following variable
+    // initializers, array elements and constructor pushes
+    fn resolve_addresses(&self, locals: &HashMap<String, Variable>, expression: &[Token], depth: usize) -> Vec<String> {
+        if depth > 4 {
+            return Vec::new();
+        }
+        let mut addresses: Vec<String> = expression.iter().filter(|t| is_address_literal(t)).map(|t| t.text.clone()).collect();
+        if !addresses.is_empty() {
+            return addresses;
+        }
+        // Wrappers such as IERC20(x) or address(x)
+        if let [_, open, inner @ .., close] = expression {
+            if open.text == "(" && close.text == ")" && !inner.is_empty() {
+                return self.resolve_addresses(locals, inner, depth + 1);
+            }
+        }
+        // name or name[i]
+        let Some(name) = expression.first().filter(|t| t.kind == Kind::Ident) else {
+            return addresses;
+        };
+        let indexed = expression.get(1).is_some_and(|t| t.text == "[");
+        if expression.len() != 1 && !indexed {
+            return addresses;
+        }
+        if let Some(variable) = self.variable(locals, &name.text) {
+            if !variable.init.is_empty() {
+                addresses = self.resolve_addresses(locals, &variable.init, depth + 1);
+            }
+            if addresses.is_empty() && (indexed || variable.ty.ends_with(']')) {
+                addresses = self.pushed_addresses(&name.text);
+            }
+        }
+        addresses
+    }
+
+    // Address literals stored into an array state variable by the constructor
+    fn pushed_addresses(&self, array: &str) -> Vec<String> {
+        let Some(body) = self.unit.functions.iter().find(|f| f.name == "constructor").and_then(|f| f.body.clone()) else {
+            return Vec::new();
+        };
+        let mut addresses = Vec::new();
+        for i in body.clone() {
+            if self.tokens[i].text != array || self.tokens[i - 1].text == "." {
+                continue;
+            }
+            let end = statement_end(self.tokens, i, body.end);
+            addresses.extend(self.tokens[i..end].iter().filter(|t| is_address_literal(t)).map(|t| t.text.clone()));
+        }
+        addresses
+    }
+
+    fn analyze_should_respond(&self, function: &'a Function) -> Option<ShouldRespondAnalysis> {
+        let data = function.params.iter().find(|(ty, _)| ty == "bytes[]").map(|(_, name)| name.clone())?;
+        let locals = self.locals(function);
+        let body = function.body.clone()?;
+        let tokens = self.tokens;
+
+        // `data.length` and locals initialized from it
+        let mut length_aliases: BTreeSet<String> = BTreeSet::new();
+        for (name, variable) in &locals {
+            if matches!(variable.init.as_slice(), [d, dot, len] if d.text == data && dot.text == "." && len.text == "length") {
+                length_aliases.insert(name.clone());
+            }
+        }
+        // Length of the `data.length` / alias expression ending at `end` (exclusive), if any
+        let length_before = |end: usize| -> Option<usize> {
+            if end >= 3 && tokens[end - 3].text == data && tokens[end - 2].text == "." && tokens[end - 1].text == "length" {
+                Some(3)
+            } else if end >= 1 && length_aliases.contains(&tokens[end - 1].text) && tokens[end - 1].kind == Kind::Ident {
+                Some(1)
+            } else {
+                None
+            }
+        };
+        let length_at = |start: usize| -> Option<usize> {
+            if tokens.get(start).is_some_and(|t| t.text == data)
+                && tokens.get(start + 1).is_some_and(|t| t.text == ".")
+                && tokens.get(start + 2).is_some_and(|t| t.text == "length")
+            {
+                Some(3)
+            } else if tokens.get(start).is_some_and(|t| t.kind == Kind::Ident && length_aliases.contains(&t.text)) {
+                Some(1)
+            } else {
+                None
+            }
+        };
+
+        let mut analysis = ShouldRespondAnalysis {
+            data_param: data.clone(),
+            accesses: Vec::new(),
+            decodes: Vec::new(),
+            length_guards: Vec::new(),
+            iterates_all_samples: false,
+            deepest_index_samples: 0,
+            required_block_sample_size: 1,
+        };
+
+        for i in body.clone() {
+            let token = &tokens[i];
+            // data[index]
+            if token.text == data && tokens.get(i + 1).is_some_and(|t| t.text == "[") && tokens[i - 1].text != "." {
+                let close = matching(tokens, i + 1);
+                let index = &tokens[i + 2..close];
+                let (label, min_samples) = match index {
+                    [n] if n.kind == Kind::Number => (n.text.clone(), parse_number(&n.text).map(|n| n + 1)),
+                    _ => match length_at(i + 2) {
+                        Some(len) if index.len() == len + 2 && index[len].text == "-" && index[len + 1].kind == Kind::Number => {
+                            (format!("length - {}", index[len + 1].text), parse_number(&index[len + 1].text))
+                        }
+                        _ => (text(index), None),
+                    },
+                };
+                if let Some(samples) = min_samples.filter(|_| index.len() == 1) {
+                    analysis.deepest_index_samples = analysis.deepest_index_samples.max(samples);
+                }
+                let is_decoded = i >= 4
+                    && tokens[i - 1].text == "("
+                    && tokens[i - 2].text == "decode"
+                    && tokens[i - 3].text == "."
+                    && tokens[i - 4].text == "abi";
+                if is_decoded {
+                    let types = tokens
+                        .get(close + 1)
+                        .filter(|t| t.text == ",")
+                        .and_then(|_| tokens.get(close + 2))
+                        .filter(|t| t.text == "(")
+                        .map(|_| {
+                            let types_close = matching(tokens, close + 2);
+                            parse_params(&tokens[close + 3..types_close]).into_iter().map(|(ty, _)| self.sources.schema(&ty, 0)).collect()
+                        })
+                        .unwrap_or_default();
+                    analysis.decodes.push(SampleDecode { index: label.clone(), types, line: token.line });
+                }
+                analysis.accesses.push(SampleAccess { index: label, min_samples, line: token.line });
+            }
+
+            // Comparisons of the sample count against a constant
+            if token.kind == Kind::Punct && matches!(token.text.as_str(), "<" | "<=" | ">" | ">=" | "==" | "!=") {
+                let guard = match (length_before(i), tokens.get(i + 1)) {
+                    (Some(len), Some(value)) => self.constant(value).map(|n| (i - len, i + 2, token.text.clone(), n)),
+                    _ => None,
+                }
+                .or_else(|| match (length_at(i + 1), i.checked_sub(1).map(|j| &tokens[j])) {
+                    (Some(len), Some(value)) => self.constant(value).map(|n| (i - 1, i + 1 + len, flip(&token.text), n)),
+                    _ => None,
+                });
+                if let Some((start, end, op, n)) = guard {
+                    let min_samples = match op.as_str() {
+                        // if (data.length < n) return / require(data.length >= n)
+                        "<" | ">=" => Some(n),
+                        "<=" | ">" => Some(n + 1),
+                        "==" if n == 0 => Some(1),
+                        "==" | "!=" => Some(n.max(1)),
+                        _ => None,
+                    };
+                    if let Some(min_samples) = min_samples {
+                        analysis.length_guards.push(LengthGuard { expression: text(&tokens[start..end]), min_samples, line: token.line });
+                    }
+                }
+            }
+
+            // for (...; i < data.length; ...)
+            if token.text == "for" && tokens.get(i + 1).is_some_and(|t| t.text == "(") {
+                let close = matching(tokens, i + 1);
+                if (i + 2..close).any(|j| length_at(j).is_some()) {
+                    analysis.iterates_all_samples = true;
+                }
+            }
+        }
+
+        let from_end = analysis.accesses.iter().filter(|a| a.index.starts_with("length - ")).filter_map(|a| a.min_samples).max().unwrap_or(0);
+        let guarded = analysis.length_guards.iter().map(|g| g.min_samples).max().unwrap_or(0);
+        analysis.required_block_sample_size = [1, analysis.deepest_index_samples, from_end, guarded].into_iter().max().unwrap_or(1);
+        Some(analysis)
+    }
+
+    // A numeric literal, or a constant state variable initialized with one
+    fn constant(&self, token: &Token) -> Option<u64> {
+        match token.kind {
+            Kind::Number => parse_number(&token.text),
+            Kind::Ident => match self.state(&token.text)?.init.as_slice() {
+                [value] if value.kind == Kind::Number => parse_number(&value.text),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+// Whether the declaration at `i` continues a `returns (Type a, Type b)` list or a tuple declaration
+fn locals_list_context(tokens: &[Token], i: usize, start: usize) -> bool {
+    let mut depth = 0i32;
+    for j in (start..i).rev() {
+        match tokens[j].text.as_str() {
+            ")" => depth += 1,
+            "(" if depth == 0 => return j >= 1 && matches!(tokens[j - 1].text.as_str(), "returns" | "{" | ";" | "}"),
+            "(" => depth -= 1,
+            ";" | "{" | "}" => return false,
+            _ => {}
+        }
+    }
+    false
+}
+
+// Start index of the receiver expression ending right before the `.` at `dot`
+fn receiver_start(tokens: &[Token], dot: usize, start: usize) -> Option<usize> {
+    let mut j = dot.checked_sub(1)?;
+    if j < start {
+        return None;
+    }
+    // Trailing calls or index accesses: IFoo(x), list[i]
+    while matches!(tokens[j].text.as_str(), ")" | "]") {
+        let closer = tokens[j].text.clone();
+        let opener = if closer == ")" { "(" } else { "[" };
+        let mut depth = 0;
+        loop {
+            if tokens[j].text == closer {
+                depth += 1;
+            } else if tokens[j].text == opener {
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+            if j == start {
+                return None;
+            }
+            j -= 1;
+        }
+        if j == start {
+            return None;
+        }
+        j -= 1;
+    }
+    if tokens[j].kind != Kind::Ident || tokens[j - 1].text == "." {
+        return None;
+    }
+    Some(j)
+}
+
+// Function encoded by abi.encodeWithSignature("f(uint256)"), encodeWithSelector(I.f.selector) or encodeCall(I.f, ...)
+fn encoded_function(arguments: &[Token]) -> (Option<String>, Option<String>) {
+    let Some(at) = arguments.iter().position(|t| t.text == "abi") else {
+        return (None, None);
+    };
+    let method = arguments.get(at + 2).map(|t| t.text.as_str());
+    let first = arguments.get(at + 4..).unwrap_or_default();
+    match (method, first) {
+        (Some("encodeWithSignature"), [signature, ..]) if signature.kind == Kind::Str => {
+            (None, Some(signature.text.trim_matches(|c| c == '"' || c == '\'').to_string()))
+        }
+        (Some("encodeWithSelector") | Some("encodeCall"), [interface, dot, function, ..]) if dot.text == "." => {
+            (Some(interface.text.clone()), Some(function.text.clone()))
+        }
+        _ => (None, None),
+    }
+}
+
+fn flip(op: &str) -> String {
+    match op {
+        "<" => ">",
+        ">" => "<",
+        "<=" => ">=",
+        ">=" => "<=",
+        other => other,
+    }
+    .to_string()
+}
+
+fn parse_number(literal: &str) -> Option<u64> {
+    let digits = literal.replace('_', "");
+    match digits.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => digits.parse().ok(),
+    }
+}
+
+fn is_elementary(name: &str) -> bool {
+    matches!(name, "address" | "bool" | "string" | "bytes" | "uint" | "int")
+        || ["uint", "int", "bytes"].iter().any(|prefix| name.strip_prefix(prefix).is_some_and(|bits| !bits.is_empty() && bits.chars().all(|c| c.is_ascii_digit())))
+}
+
+/// Blocks a response takes to land when no effect window is given
+pub const DEFAULT_RESPONSE_EFFECT_BLOCKS: u64 = 1;
+
+// Inconsistencies between what shouldRespond reads and the drosera.toml it is
+// deployed with. A response landing `effect_blocks` after the trigger leaves
+// pre-response samples in the window for another block_sample_size - 1 blocks,
+// so a shorter cooldown responds again to the same incident.
+pub fn check_config(analysis: &TrapAnalysis, trap: &DeclaredTrap, effect_blocks: u64) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let samples = trap.block_sample_size;
+    if let Some(should_respond) = &analysis.should_respond {
+        let data = &should_respond.data_param;
+        if should_respond.deepest_index_samples > samples {
+            warnings.push(format!(
+                "traps.{}: shouldRespond reads {}[{}] but block_sample_size = {}, so that sample is never collected",
+                trap.name,
+                data,
+                should_respond.deepest_index_samples - 1,
+                samples
+            ));
+        }
+        for guard in should_respond.length_guards.iter().filter(|guard| guard.min_samples > samples) {
+            warnings.push(format!(
+                "traps.{}: guard `{}` (line {}) needs {} samples but block_sample_size = {}, so shouldRespond never gets past it",
+                trap.name, guard.expression, guard.line, guard.min_samples, samples
+            ));
+        }
+        for access in should_respond.accesses.iter().filter(|a| a.index.starts_with("length - ") && a.min_samples.is_some_and(|n| n > samples)) {
+            warnings.push(format!(
+                "traps.{}: {}[{}.{}] (line {}) is out of bounds with block_sample_size = {}",
+                trap.name, data, data, access.index, access.line, samples
+            ));
+        }
+    }
+    let effect_window = effect_blocks + samples.saturating_sub(1);
+    match trap.cooldown_period_blocks {
+        None => warnings.push(format!("traps.{}: cooldown_period_blocks is not set", trap.name)),
+        Some(cooldown) if cooldown < effect_window => warnings.push(format!(
+            "traps.{}: cooldown_period_blocks = {} is shorter than the response's effect window of {} blocks ({} to take effect + {} more sampled blocks), so the trap can respond again to the same event",
+            trap.name,
+            cooldown,
+            effect_window,
+            effect_blocks,
+            samples.saturating_sub(1)
+        )),
+        Some(_) => {}
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drosera_toml;
+    use std::fs;
+    use std::path::Path;
+
+    fn texts(source: &str) -> Vec<String> {
+        tokenize(source).into_iter().map(|token| token.text).collect()
+    }
+
+    // The .sol files under a bundled example's src/, keyed like trap_sources does
+    fn example_sources(example: &str) -> BTreeMap<String, String> {
+        fn walk(root: &Path, dir: &Path, sources: &mut BTreeMap<String, String>) {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    walk(root, &path, sources);
+                } else if path.extension().is_some_and(|ext| ext == "sol") {
+                    let key = path.strip_prefix(root).unwrap().to_string_lossy().into_owned();
+                    sources.insert(key, fs::read_to_string(&path).unwrap());
+                }
+            }
+        }
+        let root = Path::new("src/data/trap-examples").join(example);
+        let mut sources = BTreeMap::new();
+        walk(&root, &root.join("src"), &mut sources);
+        sources
+    }
+
+    #[test]
+    fn tokenizes_strings_comments_and_operators() {
+        assert_eq!(texts("a <= b && c != 0x1F"), ["a", "<=", "b", "&&", "c", "!=", "0x1F"]);
+        assert_eq!(texts(r#"s = "a \" // b"; t = 'c';"#), ["s", "=", r#""a \" // b""#, ";", "t", "=", "'c'", ";"]);
+        assert_eq!(texts("x /* a * / b */ y // z"), ["x", "y"]);
+
+        let tokens = tokenize("a\n/* one\ntwo */\nb");
+        assert_eq!((tokens[0].line, tokens[1].line), (1, 4));
+    }
+
+    #[test]
+    fn tokenizes_unterminated_input() {
+        let tokens = tokenize(r#"x = "open"#);
+        assert_eq!(tokens.last().map(|t| (t.kind, t.text.as_str())), Some((Kind::Str, r#""open"#)));
+        assert_eq!(texts(r#"x = "ends with \"#), ["x", "=", r#""ends with \"#]);
+        assert_eq!(texts("x /* never closed\n y"), ["x"]);
+        assert_eq!(texts("x // no newline"), ["x"]);
+        assert_eq!(texts("x /"), ["x", "/"]);
+    }
+
+    #[test]
+    fn matches_nested_brackets() {
+        let tokens = tokenize("f(a[b(c)], {d: (e)}) + g");
+        assert_eq!(tokens[matching(&tokens, 1)].text, ")");
+        assert_eq!(matching(&tokens, 1), tokens.len() - 3);
+        assert_eq!(tokens[matching(&tokens, 3)].text, "]");
+        assert_eq!(tokens[matching(&tokens, 10)].text, "}");
+
+        let unclosed = tokenize("f(a[b]");
+        assert_eq!(matching(&unclosed, 1), unclosed.len());
+
+        let tokens = tokenize("a, f(b, c), [d, e], {f: g, h: i}");
+        let parts: Vec<String> = split_commas(&tokens).into_iter().map(text).collect();
+        assert_eq!(parts, ["a", "f(b, c)", "[d, e]", "{ f : g, h : i }"]);
+    }
+
+    #[test]
+    fn analyzes_euler_trap() {
+        let analysis = analyze(&example_sources("historical-exploits/euler"), None).unwrap();
+        assert_eq!(analysis.contract, "EulerTrap");
+
+        let collect = analysis.collect.unwrap();
+        assert_eq!(collect.abi_types.unwrap(), ["tuple(uint256,uint256,uint256,uint256,uint256)"]);
+        let calls: Vec<(&str, &str, &str, &[String])> = collect
+            .external_calls
+            .iter()
+            .map(|call| (call.interface.as_deref().unwrap(), call.function.as_deref().unwrap(), call.target.as_str(), call.addresses.as_slice()))
+            .collect();
+        let dai = ["0x6B175474E89094C44Da98b954EedeAC495271d0F".to_string()];
+        let edai = ["0xe025E3ca2bE02316033184551D4d3Aa22024D9DC".to_string()];
+        assert_eq!(
+            calls,
+            [
+                ("IERC20", "balanceOf", "DAI", &dai[..]),
+                ("EToken", "totalSupplyUnderlying", "eDAI", &edai[..]),
+                ("EToken", "reserveBalanceUnderlying", "eDAI", &edai[..]),
+            ]
+        );
+
+        let should_respond = analysis.should_respond.unwrap();
+        let indices: Vec<(&str, Option<u64>)> = should_respond.accesses.iter().map(|a| (a.index.as_str(), a.min_samples)).collect();
+        assert_eq!(indices, [("0", Some(1)), ("1", Some(2))]);
+        assert!(should_respond.decodes.iter().all(|d| d.types[0].ty == "Snapshot"));
+        let guards: Vec<(&str, u64)> = should_respond.length_guards.iter().map(|g| (g.expression.as_str(), g.min_samples)).collect();
+        assert_eq!(guards, [("data.length < 2", 2)]);
+        assert_eq!(should_respond.deepest_index_samples, 2);
+        assert_eq!(should_respond.required_block_sample_size, 2);
+    }
+
+    #[test]
+    fn analyzes_sudden_balance_drop_trap() {
+        let analysis = analyze(&example_sources("defi-automation/sudden-balance-drop-trap"), None).unwrap();
+        assert_eq!(analysis.contract, "SuddenBalanceDropTrap");
+
+        let collect = analysis.collect.unwrap();
+        assert_eq!(collect.abi_types.unwrap(), ["tuple(address,address,uint256,uint256)[]"]);
+        let call = &collect.external_calls[..];
+        assert_eq!(call.len(), 1);
+        assert_eq!((call[0].kind, call[0].interface.as_deref(), call[0].function.as_deref()), ("call", Some("IERC20"), Some("balanceOf")));
+        // The token comes from a loop variable, so it has no static address
+        assert_eq!(call[0].target, "info.token");
+        assert!(call[0].addresses.is_empty());
+
+        let should_respond = analysis.should_respond.unwrap();
+        let indices: Vec<&str> = should_respond.accesses.iter().map(|a| a.index.as_str()).collect();
+        assert_eq!(indices, ["0", "1"]);
+        assert!(should_respond.decodes.iter().all(|d| d.types[0].ty == "VaultBalance[]"));
+        assert_eq!(should_respond.length_guards[0].expression, "data.length < 2");
+        assert!(!should_respond.iterates_all_samples);
+        assert_eq!(should_respond.required_block_sample_size, 2);
+    }
+
+    fn declared_trap(toml: &str) -> DeclaredTrap {
+        drosera_toml::parse_traps(toml).unwrap().remove(0)
+    }
+
+    fn guarded_trap(guard: u64) -> BTreeMap<String, String> {
+        let source = format!(
+            "contract GuardedTrap {{
+                function collect() external view returns (bytes memory) {{
+                    return abi.encode(block.number);
+                }}
+
+                function shouldRespond(bytes[] calldata data) external pure returns (bool, bytes memory) {{
+                    if (data.length < {guard}) return (false, \"\");
+                    uint256 latest = abi.decode(data[0], (uint256));
+                    return (latest > 0, \"\");
+                }}
+            }}"
+        );
+        BTreeMap::from([("src/GuardedTrap.sol".to_string(), source)])
+    }
+
+    #[test]
+    fn flags_samples_that_are_never_collected() {
+        let example = "defi-automation/sudden-balance-drop-trap";
+        let analysis = analyze(&example_sources(example), None).unwrap();
+        let toml = fs::read_to_string(Path::new("src/data/trap-examples").join(example).join("drosera.toml")).unwrap();
+        let trap = declared_trap(&toml);
+        assert_eq!(trap.block_sample_size, 1);
+
+        assert_eq!(
+            check_config(&analysis, &trap, DEFAULT_RESPONSE_EFFECT_BLOCKS),
+            [
+                "traps.sudden_balance_drop: shouldRespond reads data[1] but block_sample_size = 1, so that sample is never collected",
+                "traps.sudden_balance_drop: guard `data.length < 2` (line 100) needs 2 samples but block_sample_size = 1, so shouldRespond never gets past it",
+            ]
+        );
+    }
+
+    #[test]
+    fn flags_length_guards_above_the_sample_size() {
+        let analysis = analyze(&guarded_trap(5), None).unwrap();
+        let trap = declared_trap("[traps.guarded]\nblock_sample_size = 3\ncooldown_period_blocks = 10\n");
+        assert_eq!(
+            check_config(&analysis, &trap, DEFAULT_RESPONSE_EFFECT_BLOCKS),
+            ["traps.guarded: guard `data.length < 5` (line 7) needs 5 samples but block_sample_size = 3, so shouldRespond never gets past it"]
+        );
+
+        let analysis = analyze(&guarded_trap(3), None).unwrap();
+        assert!(check_config(&analysis, &trap, DEFAULT_RESPONSE_EFFECT_BLOCKS).is_empty());
+    }
+
+    #[test]
+    fn flags_cooldowns_shorter_than_the_effect_window() {
+        let analysis = analyze(&guarded_trap(1), None).unwrap();
+
+        // One block to take effect plus two more blocks whose window still holds pre-response samples
+        let trap = declared_trap("[traps.guarded]\nblock_sample_size = 3\ncooldown_period_blocks = 2\n");
+        assert_eq!(
+            check_config(&analysis, &trap, 1),
+            ["traps.guarded: cooldown_period_blocks = 2 is shorter than the response's effect window of 3 blocks (1 to take effect + 2 more sampled blocks), so the trap can respond again to the same event"]
+        );
+        let trap = declared_trap("[traps.guarded]\nblock_sample_size = 3\ncooldown_period_blocks = 3\n");
+        assert!(check_config(&analysis, &trap, 1).is_empty());
+
+        let trap = declared_trap("[traps.guarded]\nblock_sample_size = 3\n");
+        assert_eq!(check_config(&analysis, &trap, 1), ["traps.guarded: cooldown_period_blocks is not set"]);
+    }
+
+    // fee-change-monitor collects one sample and that is all its
+    // shouldRespond reads, so only a slow response makes the cooldown too short
+    #[test]
+    fn checks_fee_change_monitor() {
+        let example = Path::new("../../trap-examples/defi-automation/fee-change-monitor");
+        let source = fs::read_to_string(example.join("src/SimpleFeeChangeTrap.sol")).unwrap();
+        let analysis = analyze(&BTreeMap::from([("src/SimpleFeeChangeTrap.sol".to_string(), source)]), None).unwrap();
+        let trap = declared_trap(&fs::read_to_string(example.join("drosera.toml")).unwrap());
+        assert_eq!((trap.block_sample_size, trap.cooldown_period_blocks), (1, Some(5)));
+        assert_eq!(analysis.should_respond.as_ref().unwrap().required_block_sample_size, 1);
+
+        assert!(check_config(&analysis, &trap, DEFAULT_RESPONSE_EFFECT_BLOCKS).is_empty());
+        assert_eq!(
+            check_config(&analysis, &trap, 6),
+            ["traps.simple_fee_monitor: cooldown_period_blocks = 5 is shorter than the response's effect window of 6 blocks (6 to take effect + 0 more sampled blocks), so the trap can respond again to the same event"]
+        );
+    }
+}
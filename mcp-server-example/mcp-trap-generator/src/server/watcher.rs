@@ -0,0 +1,142 @@
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::{mpsc, Mutex, RwLock};
+use tokio::task;
+use tracing::{info, warn};
+
+use crate::session::Session;
+use crate::DroseraServer;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const DATA_DIR: &str = "src/data";
+
+type Snapshot = HashMap<PathBuf, Option<SystemTime>>;
+
+// Broken files are watched too, so fixing one triggers a reload
+fn watched_files(server: &DroseraServer) -> Vec<PathBuf> {
+    let broken_files = server
+        .load_report
+        .diagnostics
+        .iter()
+        .map(|diagnostic| PathBuf::from(&diagnostic.path));
+
+    server.resource_files.keys().cloned().chain(broken_files).collect()
+}
+
+// Directory modification times change when entries are added or removed,
+// so watching every data directory picks up new files
+fn data_dirs(dir: &Path, dirs: &mut Vec<PathBuf>) {
+    dirs.push(dir.to_path_buf());
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+        if path.is_dir() {
+            data_dirs(&path, dirs);
+        }
+    }
+}
+
+fn snapshot(mut paths: Vec<PathBuf>) -> Snapshot {
+    data_dirs(Path::new(DATA_DIR), &mut paths);
+    paths
+        .into_iter()
+        .map(|path| {
+            let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+            (path, modified)
+        })
+        .collect()
+}
+
+// File system work runs on the blocking pool, off the runtime threads
+async fn take_snapshot(server: &RwLock<Arc<DroseraServer>>) -> Snapshot {
+    let paths = watched_files(&*server.read().await);
+    task::spawn_blocking(move || snapshot(paths)).await.unwrap_or_default()
+}
+
+// Polls the files backing each resource and, when one changes, reloads the
+// server data and notifies the client about every subscribed URI it affects.
+pub async fn watch_resources(
+    server: Arc<RwLock<Arc<DroseraServer>>>,
+    session: Arc<Mutex<Session>>,
+    outgoing: mpsc::UnboundedSender<Value>,
+) {
+    let mut known = take_snapshot(&server).await;
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let current = take_snapshot(&server).await;
+        let changed: Vec<PathBuf> = current
+            .iter()
+            .filter(|(path, modified)| known.get(*path) != Some(*modified))
+            .map(|(path, _)| path.clone())
+            .collect();
+        known = current;
+
+        if changed.is_empty() {
+            continue;
+        }
+
+        // Collect affected URIs from the mapping before and after the reload,
+        // so both removed and newly served resources are covered
+        let mut uris = BTreeSet::new();
+        let mut collect_uris = |server: &DroseraServer, paths: &[PathBuf]| {
+            for path in paths {
+                if let Some(file_uris) = server.resource_files.get(path) {
+                    uris.extend(file_uris.iter().cloned());
+                }
+            }
+        };
+        collect_uris(&*server.read().await, &changed);
+
+        info!("Reloading data after {} path(s) changed", changed.len());
+        let reloaded = match task::spawn_blocking(DroseraServer::new).await {
+            Ok(reloaded) => reloaded,
+            Err(e) => Err(e.into()),
+        };
+        match reloaded {
+            Ok(reloaded) => {
+                // A new file only shows up as a change to its directory
+                let previous = server.read().await;
+                let mut paths: Vec<PathBuf> = reloaded
+                    .resource_files
+                    .keys()
+                    .filter(|path| !previous.resource_files.contains_key(*path))
+                    .cloned()
+                    .collect();
+                paths.extend(changed);
+                collect_uris(&reloaded, &paths);
+                if reloaded.load_report != previous.load_report {
+                    uris.insert("diagnostics://load".to_string());
+                }
+                drop(previous);
+                *server.write().await = Arc::new(reloaded);
+                known = take_snapshot(&server).await;
+            }
+            Err(e) => {
+                warn!("Failed to reload data: {}", e);
+                continue;
+            }
+        }
+
+        let session = session.lock().await;
+        for uri in uris.iter().filter(|uri| session.is_subscribed(uri)) {
+            let notification = json!({
+                "jsonrpc": "2.0",
+                "method": "notifications/resources/updated",
+                "params": {
+                    "uri": uri
+                }
+            });
+            if outgoing.send(notification).is_err() {
+                return;
+            }
+        }
+    }
+}